@@ -0,0 +1,85 @@
+// Allocations and time to parse a gzipped TS, the old inflate-and-strip way against
+// parse_frame_bytes.
+// cargo bench --bench parse
+
+use darwin_to_gtfs_realtime::darwin_types::Pport;
+use darwin_to_gtfs_realtime::parse::parse_frame_bytes;
+use darwin_to_gtfs_realtime::test_fixtures::{gzip, train_status_message};
+use flate2::read::GzDecoder;
+use quick_xml::de::from_str;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+const ITERATIONS: u32 = 2_000;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// (allocations, bytes) made by `f`
+fn allocations(f: impl FnOnce()) -> (usize, usize) {
+    let before = (
+        ALLOCATIONS.load(Ordering::Relaxed),
+        ALLOCATED_BYTES.load(Ordering::Relaxed),
+    );
+    f();
+    (
+        ALLOCATIONS.load(Ordering::Relaxed) - before.0,
+        ALLOCATED_BYTES.load(Ordering::Relaxed) - before.1,
+    )
+}
+
+fn main() {
+    let ns_re = regex::Regex::new(r"ns\d+:").unwrap();
+    let body = gzip(train_status_message("ns5", 20));
+
+    // What parse_frame_bytes used to do: inflate to a String, strip prefixes, parse
+    let strip_prefixes = || {
+        let mut xml_string = String::new();
+        GzDecoder::new(&body[..])
+            .read_to_string(&mut xml_string)
+            .unwrap();
+        let clean_xml = ns_re.replace_all(&xml_string, "");
+        from_str::<Pport>(&clean_xml).unwrap();
+    };
+    let from_gzip_reader = || {
+        parse_frame_bytes(&body).unwrap();
+    };
+
+    for (name, parse) in [
+        ("inflate + strip prefixes", &strip_prefixes as &dyn Fn()),
+        ("parse_frame_bytes", &from_gzip_reader),
+    ] {
+        parse();
+        let (count, bytes) = allocations(parse);
+        let started = Instant::now();
+        for _ in 0..ITERATIONS {
+            parse();
+        }
+        println!(
+            "{:<32} {:>4} allocations {:>7} bytes {:>6.1} us per message",
+            name,
+            count,
+            bytes,
+            started.elapsed().as_secs_f64() * 1e6 / ITERATIONS as f64
+        );
+    }
+}
@@ -0,0 +1,409 @@
+use crate::darwin_types::{AlertType, MessageBody, MessagePart, StationMessage, TrainAlert};
+use crate::state::AppState;
+use compact_str::{CompactString, format_compact};
+
+use gtfs_realtime::{
+    Alert, EntitySelector, FeedEntity, TranslatedString, TripDescriptor,
+    alert::{Cause, Effect, SeverityLevel},
+    translated_string::Translation,
+};
+
+use std::sync::LazyLock;
+
+// Darwin escapes some HTML inside the message text, which survives XML decoding as literal tags
+static BREAK_RE: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?i)<\s*(br|/p)\s*/?\s*>").unwrap());
+static TAG_RE: LazyLock<regex::Regex> = LazyLock::new(|| regex::Regex::new(r"<[^>]*>").unwrap());
+
+// GTFS route_type for rail, used when a message names no station we can resolve
+const RAIL_ROUTE_TYPE: i32 = 2;
+
+pub fn station_message_key(id: &str) -> CompactString {
+    format_compact!("OW_{}", id)
+}
+
+// Build the Alert entity for a station message.
+// Returns None when the message has no text, which Darwin uses to withdraw it, or is
+// suppressed from public display.
+pub fn station_message_alert(msg: &StationMessage, state: &AppState) -> Option<FeedEntity> {
+    if msg.suppress {
+        return None;
+    }
+    let text = plain_text(&msg.message);
+    if text.is_empty() {
+        return None;
+    }
+
+    let (cause, effect) = category_cause_effect(&msg.category);
+
+    let mut alert = Alert {
+        cause: Some(cause as i32),
+        effect: Some(effect as i32),
+        severity_level: Some(severity_level(msg.severity.as_deref()) as i32),
+        header_text: Some(english(text)),
+        url: first_link(&msg.message).map(|href| english(href.to_string())),
+        ..Default::default()
+    };
+
+    for station in &msg.stations {
+        if let Some(stop_id) = state.gtfs.get_stop_id_for_crs(&station.crs) {
+            alert.informed_entity.push(EntitySelector {
+                stop_id: Some(stop_id.to_string()),
+                ..Default::default()
+            });
+        }
+    }
+    if alert.informed_entity.is_empty() {
+        // Network-wide message, or stations missing from static GTFS
+        alert.informed_entity.push(EntitySelector {
+            route_type: Some(RAIL_ROUTE_TYPE),
+            ..Default::default()
+        });
+    }
+
+    Some(FeedEntity {
+        id: station_message_key(&msg.id).to_string(),
+        alert: Some(alert),
+        ..Default::default()
+    })
+}
+
+pub fn train_alert_key(id: &str) -> CompactString {
+    format_compact!("TA_{}", id)
+}
+
+// A service named by a train alert, resolved against the published trips
+pub struct AlertedTrip {
+    pub trip_id: CompactString,
+    pub start_date: String,
+    pub stop_ids: Vec<CompactString>,
+}
+
+// Build the trip-scoped Alert entity for a train alert.
+// Returns None when none of its services matched a trip.
+pub fn train_alert_entity(ta: &TrainAlert, trips: &[AlertedTrip]) -> Option<FeedEntity> {
+    let text = clean_text(&ta.text);
+    if text.is_empty() || trips.is_empty() {
+        return None;
+    }
+
+    let mut alert = Alert {
+        cause: Some(Cause::UnknownCause as i32),
+        effect: Some(Effect::UnknownEffect as i32),
+        severity_level: Some(match ta.alert_type {
+            AlertType::Forced => SeverityLevel::Warning as i32,
+            AlertType::Normal => SeverityLevel::Info as i32,
+        }),
+        header_text: Some(english(text)),
+        ..Default::default()
+    };
+
+    for trip in trips {
+        let descriptor = trip_descriptor(trip);
+        alert.informed_entity.push(EntitySelector {
+            trip: Some(descriptor.clone()),
+            ..Default::default()
+        });
+
+        // Trip and stop together scope the alert to those calls of this trip
+        for stop_id in &trip.stop_ids {
+            alert.informed_entity.push(EntitySelector {
+                trip: Some(descriptor.clone()),
+                stop_id: Some(stop_id.to_string()),
+                ..Default::default()
+            });
+        }
+    }
+
+    Some(FeedEntity {
+        id: train_alert_key(&ta.id).to_string(),
+        alert: Some(alert),
+        ..Default::default()
+    })
+}
+
+pub fn late_reason_key(rid: &str) -> CompactString {
+    format_compact!("LR_{}", rid)
+}
+
+pub fn cancel_reason_key(rid: &str) -> CompactString {
+    format_compact!("CR_{}", rid)
+}
+
+// Darwin's late running or cancellation reason, as an alert on the trip
+pub fn reason_alert(key: &str, text: String, effect: Effect, trip: &AlertedTrip) -> FeedEntity {
+    let alert = Alert {
+        cause: Some(Cause::UnknownCause as i32),
+        effect: Some(effect as i32),
+        severity_level: Some(SeverityLevel::Warning as i32),
+        header_text: Some(english(text)),
+        informed_entity: vec![EntitySelector {
+            trip: Some(trip_descriptor(trip)),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    FeedEntity {
+        id: key.to_string(),
+        alert: Some(alert),
+        ..Default::default()
+    }
+}
+
+fn trip_descriptor(trip: &AlertedTrip) -> TripDescriptor {
+    TripDescriptor {
+        trip_id: Some(trip.trip_id.to_string()),
+        start_date: Some(trip.start_date.clone()),
+        ..Default::default()
+    }
+}
+
+fn category_cause_effect(category: &str) -> (Cause, Effect) {
+    match category {
+        "Train" => (Cause::UnknownCause, Effect::ModifiedService),
+        "Station" => (Cause::OtherCause, Effect::OtherEffect),
+        "Connections" => (Cause::OtherCause, Effect::ModifiedService),
+        "System" => (Cause::TechnicalProblem, Effect::UnknownEffect),
+        "PriorTrains" => (Cause::Maintenance, Effect::ModifiedService),
+        "PriorOther" => (Cause::Maintenance, Effect::OtherEffect),
+        _ => (Cause::OtherCause, Effect::OtherEffect), // Misc
+    }
+}
+
+fn severity_level(severity: Option<&str>) -> SeverityLevel {
+    match severity {
+        Some("0") => SeverityLevel::Info,
+        Some("1") => SeverityLevel::Warning,
+        Some("2") | Some("3") => SeverityLevel::Severe,
+        _ => SeverityLevel::UnknownSeverity,
+    }
+}
+
+fn english(text: String) -> TranslatedString {
+    TranslatedString {
+        translation: vec![Translation {
+            text,
+            language: Some("en".to_string()),
+        }],
+    }
+}
+
+// Flatten Msg into plain text: one line per paragraph, HTML removed, whitespace collapsed
+pub fn plain_text(body: &MessageBody) -> String {
+    let mut raw = String::new();
+    flatten(body, &mut raw);
+    clean_text(&raw)
+}
+
+// Strip HTML left in the text, keeping line breaks and collapsing other whitespace
+fn clean_text(raw: &str) -> String {
+    let raw = BREAK_RE.replace_all(raw, "\n");
+    let raw = TAG_RE.replace_all(&raw, "");
+    let raw = raw.replace("&nbsp;", " ").replace("&amp;", "&");
+
+    raw.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn flatten(body: &MessageBody, out: &mut String) {
+    for part in &body.parts {
+        match part {
+            MessagePart::Text(text) => {
+                out.push_str(text);
+                out.push(' ');
+            }
+            MessagePart::Anchor(anchor) => {
+                out.push_str(&anchor.text);
+                out.push(' ');
+            }
+            MessagePart::Paragraph(paragraph) => {
+                out.push('\n');
+                flatten(paragraph, out);
+                out.push('\n');
+            }
+        }
+    }
+}
+
+fn first_link(body: &MessageBody) -> Option<&CompactString> {
+    body.parts.iter().find_map(|part| match part {
+        MessagePart::Anchor(anchor) => Some(&anchor.href),
+        MessagePart::Paragraph(paragraph) => first_link(paragraph),
+        MessagePart::Text(_) => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::darwin_types::Pport;
+    use crate::processor::process_pmap;
+    use crate::static_data::{GTFSManager, GtfsData};
+    use quick_xml::de::from_str;
+
+    fn state_with_crs(codes: &[(&str, &str)]) -> AppState {
+        let mut data = GtfsData::default();
+        for (crs, stop_id) in codes {
+            data.tiploc_map.insert((*crs).into(), (*stop_id).into());
+        }
+        let mut state = AppState::new("http://localhost".to_string());
+        state.gtfs = GTFSManager::from_data(data);
+        state
+    }
+
+    fn ur(body: &str) -> Pport {
+        let xml = format!(
+            r#"<Pport ts="2026-01-15T08:00:00" version="16.0"><uR updateOrigin="Workstation">{}</uR></Pport>"#,
+            body
+        );
+        from_str(&xml).unwrap()
+    }
+
+    #[test]
+    fn mixed_content_message_flattens_to_plain_text() {
+        let pport = ur(
+            r#"<OW id="1" cat="Train" sev="1"><Station crs="KGX"/><Msg>Disruption between <p>Stevenage and   Hitchin.</p><p>More at <a href="http://nre.co.uk/x">our site</a>&lt;br&gt;Tickets accepted.</p></Msg></OW>"#,
+        );
+        let msg = &pport.update_record.unwrap().station_message[0];
+
+        assert_eq!(
+            plain_text(&msg.message),
+            "Disruption between\nStevenage and Hitchin.\nMore at our site\nTickets accepted."
+        );
+        assert_eq!(
+            first_link(&msg.message).map(|h| h.as_str()),
+            Some("http://nre.co.uk/x")
+        );
+    }
+
+    #[test]
+    fn station_message_becomes_alert_and_empty_text_withdraws_it() {
+        let state = state_with_crs(&[("KGX", "stop_kgx")]);
+
+        process_pmap(
+            ur(
+                r#"<OW id="42" cat="PriorTrains" sev="2"><Station crs="KGX"/><Station crs="ZZZ"/><Msg>Engineering works this weekend.</Msg></OW>"#,
+            ),
+            &state,
+        );
+
+        let entity = state.alerts.get("OW_42").expect("alert published");
+        let alert = entity.alert.as_ref().unwrap();
+        assert_eq!(alert.cause, Some(Cause::Maintenance as i32));
+        assert_eq!(alert.effect, Some(Effect::ModifiedService as i32));
+        assert_eq!(alert.severity_level, Some(SeverityLevel::Severe as i32));
+        assert_eq!(
+            alert.header_text.as_ref().unwrap().translation[0].text,
+            "Engineering works this weekend."
+        );
+        let stops: Vec<_> = alert
+            .informed_entity
+            .iter()
+            .map(|e| e.stop_id.as_deref())
+            .collect();
+        assert_eq!(stops, vec![Some("stop_kgx")]);
+        drop(entity);
+
+        process_pmap(
+            ur(r#"<OW id="42" cat="PriorTrains" sev="2"><Station crs="KGX"/><Msg/></OW>"#),
+            &state,
+        );
+        assert!(!state.alerts.contains_key("OW_42"));
+    }
+
+    #[test]
+    fn suppressed_station_message_is_not_published() {
+        let state = state_with_crs(&[("KGX", "stop_kgx")]);
+        let message = |suppress: &str| {
+            ur(&format!(
+                r#"<OW id="43" cat="Station" sev="1" suppress="{}"><Station crs="KGX"/><Msg>Lifts out of order.</Msg></OW>"#,
+                suppress
+            ))
+        };
+
+        process_pmap(message("true"), &state);
+        assert!(!state.alerts.contains_key("OW_43"));
+
+        // Lifting the suppression publishes it, and suppressing again takes it down
+        process_pmap(message("false"), &state);
+        assert!(state.alerts.contains_key("OW_43"));
+        process_pmap(message("true"), &state);
+        assert!(!state.alerts.contains_key("OW_43"));
+    }
+
+    #[test]
+    fn network_wide_message_informs_all_rail() {
+        let state = state_with_crs(&[]);
+        process_pmap(
+            ur(
+                r#"<OW id="7" cat="System" sev="0"><Msg>Journey planner is unavailable.</Msg></OW>"#,
+            ),
+            &state,
+        );
+
+        let entity = state.alerts.get("OW_7").unwrap();
+        let alert = entity.alert.as_ref().unwrap();
+        assert_eq!(alert.severity_level, Some(SeverityLevel::Info as i32));
+        assert_eq!(alert.informed_entity[0].route_type, Some(RAIL_ROUTE_TYPE));
+    }
+
+    #[test]
+    fn train_alert_informs_trip_and_stops_until_withdrawn() {
+        let state = state_with_crs(&[("HTCH", "stop_htc")]);
+        state
+            .rid_to_trip_id
+            .insert("202601158001234".into(), "trip_1".into());
+
+        let alert = |services: &str, audience: &str| {
+            ur(&format!(
+                r#"<trainAlert><AlertID>99</AlertID><AlertServices>{}</AlertServices><SendAlertBySMS>false</SendAlertBySMS><SendAlertByEmail>false</SendAlertByEmail><SendAlertByTwitter>false</SendAlertByTwitter><Source>NRE</Source><AlertText>This train will be &lt;b&gt;delayed&lt;/b&gt;.</AlertText><Audience>{}</Audience><AlertType>Forced</AlertType></trainAlert>"#,
+                services, audience
+            ))
+        };
+        let service = r#"<AlertService RID="202601158001234" UID="C12345" SSD="2026-01-15"><Location>HTCH</Location><Location>NOWHERE</Location></AlertService>"#;
+
+        process_pmap(alert(service, "Customer"), &state);
+        let entity = state.alerts.get("TA_99").expect("alert published");
+        let alert_msg = entity.alert.as_ref().unwrap();
+        assert_eq!(
+            alert_msg.header_text.as_ref().unwrap().translation[0].text,
+            "This train will be delayed."
+        );
+        assert_eq!(
+            alert_msg.severity_level,
+            Some(SeverityLevel::Warning as i32)
+        );
+        let informed: Vec<_> = alert_msg
+            .informed_entity
+            .iter()
+            .map(|e| {
+                let trip = e.trip.as_ref().unwrap();
+                (
+                    trip.trip_id.as_deref(),
+                    trip.start_date.as_deref(),
+                    e.stop_id.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            informed,
+            vec![
+                (Some("trip_1"), Some("20260115"), None),
+                (Some("trip_1"), Some("20260115"), Some("stop_htc")),
+            ]
+        );
+        drop(entity);
+
+        // Withdrawn: re-sent with no services
+        process_pmap(alert("", "Customer"), &state);
+        assert!(!state.alerts.contains_key("TA_99"));
+
+        // Staff-only alerts never reach the feed
+        process_pmap(alert(service, "Staff"), &state);
+        assert!(!state.alerts.contains_key("TA_99"));
+    }
+}
@@ -0,0 +1,110 @@
+use chrono::{Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use chrono_tz::Europe::London;
+
+// Darwin times carry no date. Each one is relative to the schedule start date (ssd),
+// and a journey crossing midnight is detected by comparing against the previous time:
+// more than 6 hours earlier means the next day, more than 18 hours later means the day before.
+pub struct DarwinClock {
+    ssd: NaiveDate,
+    previous: Option<NaiveDateTime>,
+}
+
+impl DarwinClock {
+    pub fn new(ssd: NaiveDate) -> Self {
+        Self {
+            ssd,
+            previous: None,
+        }
+    }
+
+    // Clock already positioned at a known origin (e.g. the static GTFS start time)
+    pub fn starting_at(origin: NaiveDateTime) -> Self {
+        Self {
+            ssd: origin.date(),
+            previous: Some(origin),
+        }
+    }
+
+    // Resolve a time in journey order and move the clock on to it
+    pub fn advance(&mut self, time_str: &str) -> Option<NaiveDateTime> {
+        let resolved = self.resolve(time_str)?;
+        self.previous = Some(resolved);
+        Some(resolved)
+    }
+
+    // Resolve a time against the current position without moving the clock,
+    // e.g. a forecast against the location's scheduled time
+    pub fn resolve(&self, time_str: &str) -> Option<NaiveDateTime> {
+        let time = parse_time_of_day(time_str)?;
+        let Some(previous) = self.previous else {
+            return Some(self.ssd.and_time(time));
+        };
+
+        let candidate = previous.date().and_time(time);
+        let diff = candidate - previous;
+        if diff < -Duration::hours(6) {
+            Some(candidate + Duration::days(1))
+        } else if diff > Duration::hours(18) {
+            Some(candidate - Duration::days(1))
+        } else {
+            Some(candidate)
+        }
+    }
+}
+
+// HH:MM (public/forecast times) or HH:MM:SS (working times)
+fn parse_time_of_day(time_str: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(time_str, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time_str, "%H:%M"))
+        .ok()
+}
+
+// Darwin times are UK local time (Europe/London), not UTC.
+// Convert local wall-clock time to a Unix timestamp with DST awareness.
+pub fn london_timestamp(dt: NaiveDateTime) -> Option<i64> {
+    match London.from_local_datetime(&dt) {
+        LocalResult::Single(local_dt) => Some(local_dt.timestamp()),
+        LocalResult::Ambiguous(earliest, _) => Some(earliest.timestamp()),
+        LocalResult::None => {
+            // Spring-forward DST gap: move forward one hour to the next valid local instant.
+            let shifted = dt + Duration::hours(1);
+            Some(London.from_local_datetime(&shifted).earliest()?.timestamp())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32, s: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, s)
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_working_times_with_seconds() {
+        let clock = DarwinClock::new(NaiveDate::from_ymd_opt(2026, 1, 15).unwrap());
+        assert_eq!(clock.resolve("08:24:30"), Some(at(2026, 1, 15, 8, 24, 30)));
+        assert_eq!(clock.resolve("08:24"), Some(at(2026, 1, 15, 8, 24, 0)));
+        assert_eq!(clock.resolve("8h24"), None);
+    }
+
+    #[test]
+    fn rolls_over_midnight_in_journey_order() {
+        let mut clock = DarwinClock::new(NaiveDate::from_ymd_opt(2026, 1, 15).unwrap());
+        assert_eq!(clock.advance("23:40"), Some(at(2026, 1, 15, 23, 40, 0)));
+        assert_eq!(clock.advance("23:58:30"), Some(at(2026, 1, 15, 23, 58, 30)));
+        assert_eq!(clock.advance("00:15"), Some(at(2026, 1, 16, 0, 15, 0)));
+        // A forecast a few minutes early stays on the same day
+        assert_eq!(clock.resolve("00:12"), Some(at(2026, 1, 16, 0, 12, 0)));
+    }
+
+    #[test]
+    fn early_forecast_before_midnight_goes_back_a_day() {
+        let clock = DarwinClock::starting_at(at(2026, 1, 16, 0, 5, 0));
+        assert_eq!(clock.resolve("23:58"), Some(at(2026, 1, 15, 23, 58, 0)));
+    }
+}
@@ -1,78 +1,296 @@
+// Mirrors the Push Port XSDs. The parts whose shape changed between schema versions are
+// generic over the version's SubSchemas, defaulting to v16's: the common model the
+// processor works with (see schema_version).
+
+use crate::schema_version::{SubSchemas, shapes};
 use compact_str::CompactString;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize)]
-#[serde(rename = "Pport")]
-pub struct Pport {
+#[serde(rename = "Pport", bound(deserialize = ""))]
+pub struct Pport<S: SubSchemas = shapes::V16> {
+    #[serde(rename = "@ts")]
+    pub ts: Option<CompactString>,
+    // Schema version, e.g. "16.0" (see schema_version)
+    #[serde(rename = "@version")]
+    pub version: Option<CompactString>,
     #[serde(rename = "uR")]
-    pub update_record: Option<UpdateRecord>,
+    pub update_record: Option<UpdateRecord<S>>,
+    // Snapshot Response: the same content as uR, describing current state
     #[serde(rename = "sR")]
-    pub schedule_record: Option<ScheduleRecord>,
+    pub snapshot_record: Option<UpdateRecord<S>>,
+}
+
+impl<S: SubSchemas> Pport<S> {
+    // Bring a payload written in an older version to the common model
+    pub fn into_latest(self) -> Pport {
+        Pport {
+            ts: self.ts,
+            version: self.version,
+            update_record: self.update_record.map(UpdateRecord::into_latest),
+            snapshot_record: self.snapshot_record.map(UpdateRecord::into_latest),
+        }
+    }
+}
+
+impl Pport {
+    // Every RID the message touches, for keeping each service's updates in order
+    pub fn rids(&self) -> Vec<CompactString> {
+        let mut rids: Vec<CompactString> = Vec::new();
+        for record in [&self.update_record, &self.snapshot_record]
+            .into_iter()
+            .flatten()
+        {
+            rids.extend(record.schedule.iter().map(|s| s.rid.clone()));
+            rids.extend(record.deactivated.iter().map(|d| d.rid.clone()));
+            rids.extend(record.train_status.iter().map(|ts| ts.rid.clone()));
+            for to in &record.train_order {
+                if let Some(set) = &to.set {
+                    rids.extend(
+                        [&set.first, &set.second, &set.third]
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|item| Some(item.rid.as_ref()?.value.clone())),
+                    );
+                }
+            }
+            for alert in &record.train_alert {
+                rids.extend(alert.services.services.iter().filter_map(|s| s.rid.clone()));
+            }
+            rids.extend(record.loading.iter().map(|l| l.rid.clone()));
+            for association in &record.association {
+                rids.push(association.main.rid.clone());
+                rids.push(association.assoc.rid.clone());
+            }
+            rids.extend(record.schedule_formations.iter().map(|sf| sf.rid.clone()));
+        }
+        rids.sort();
+        rids.dedup();
+        rids
+    }
 }
 
 #[derive(Debug, Deserialize)]
-pub struct UpdateRecord {
+#[serde(bound(deserialize = ""))]
+pub struct UpdateRecord<S: SubSchemas = shapes::V16> {
     #[serde(rename = "@updateOrigin")]
     pub update_origin: Option<CompactString>,
+    #[serde(rename = "schedule", default)]
+    pub schedule: Vec<S::Schedule>,
+    #[serde(rename = "deactivated", default)]
+    pub deactivated: Vec<DeactivatedSchedule>,
     #[serde(rename = "TS", default)]
-    pub train_status: Vec<TrainStatus>,
-    #[serde(rename = "TO", default)]
+    pub train_status: Vec<TrainStatus<S::Forecast>>,
+    #[serde(rename = "trainOrder", default)]
     pub train_order: Vec<TrainOrder>,
     #[serde(rename = "OW", default)]
     pub station_message: Vec<StationMessage>,
-    #[serde(rename = "loading", default)]
-    pub loading: Vec<Loading>, // Note: Check if capital 'L' or lowercase. Usually strict XML is sensitive.
-    // Based on user request/Darwin schema, Loading might be new v16 topic but typically LO?
-    // User message said "Loading LO". I will add alias.
-    #[serde(alias = "LO", default)]
-    pub loading_alias: Vec<Loading>,
+    #[serde(rename = "formationLoading", default)]
+    pub loading: Vec<S::Loading>,
 
     #[serde(rename = "association", default)]
     pub association: Vec<Association>,
     #[serde(rename = "scheduleFormations", default)]
-    pub schedule_formations: Vec<crate::formations::v2::ScheduleFormations>,
+    pub schedule_formations: Vec<S::ScheduleFormations>,
     #[serde(rename = "trainAlert", default)]
     pub train_alert: Vec<TrainAlert>,
-    #[serde(rename = "trackingId", default)]
+    #[serde(rename = "trackingID", default)]
     pub tracking_id: Vec<TrackingId>,
     #[serde(rename = "alarm", default)]
     pub rtti_alarm: Vec<RTTIAlarm>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct Formation {
-    #[serde(rename = "@rid")]
-    pub rid: CompactString,
-    #[serde(rename = "coach", default)] // Assuming 'coach' is the element name
-    pub coaches: Vec<Coach>,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct Coach {
-    #[serde(rename = "@number")]
-    pub number: CompactString,
-    #[serde(rename = "@coachClass")]
-    pub class: Option<CompactString>,
+impl<S: SubSchemas> UpdateRecord<S> {
+    pub fn into_latest(self) -> UpdateRecord {
+        UpdateRecord {
+            update_origin: self.update_origin,
+            schedule: self.schedule.into_iter().map(Into::into).collect(),
+            deactivated: self.deactivated,
+            train_status: self
+                .train_status
+                .into_iter()
+                .map(TrainStatus::into_latest)
+                .collect(),
+            train_order: self.train_order,
+            station_message: self.station_message,
+            loading: self.loading.into_iter().map(Into::into).collect(),
+            association: self.association,
+            schedule_formations: self
+                .schedule_formations
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            train_alert: self.train_alert,
+            tracking_id: self.tracking_id,
+            rtti_alarm: self.rtti_alarm,
+        }
+    }
 }
 
+// Darwin's daily timetable file (rttiCTTSchema_v8.xsd). Journeys share the Push Port
+// schedule layout, plus the timetable-only qtrain and can flags.
 #[derive(Debug, Deserialize)]
-pub struct ScheduleRecord {
-    #[serde(rename = "schedule")]
-    pub schedule: Option<Vec<Schedule>>,
+#[serde(rename = "PportTimetable")]
+pub struct PportTimetable {
+    #[serde(rename = "@timetableID")]
+    pub timetable_id: CompactString,
+    #[serde(rename = "Journey", default)]
+    pub journeys: Vec<Schedule>,
+    #[serde(rename = "Association", default)]
+    pub associations: Vec<Association>,
 }
 
-#[derive(Debug, Deserialize)]
+// rttiPPTSchedules_v3 (see schedules for v1 and v2)
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Schedule {
     #[serde(rename = "@rid")]
     pub rid: CompactString,
     #[serde(rename = "@uid")]
     pub uid: CompactString,
+    #[serde(rename = "@trainId")]
+    pub train_id: Option<CompactString>,
+    #[serde(rename = "@rsid")]
+    pub rsid: Option<CompactString>,
     #[serde(rename = "@ssd")]
     pub ssd: CompactString,
+    #[serde(rename = "@toc")]
+    pub toc: Option<CompactString>,
+    #[serde(rename = "@status")]
+    pub status: Option<CompactString>, // CIF status, "P" if absent
+    #[serde(rename = "@trainCat")]
+    pub train_cat: Option<CompactString>, // CIF category, "OO" if absent
+    #[serde(rename = "@isPassengerSvc", default = "default_true")]
+    pub is_passenger_svc: bool,
+    #[serde(rename = "@isActive", default = "default_true")]
+    pub is_active: bool,
+    #[serde(rename = "@deleted", default)]
+    pub deleted: bool,
+    #[serde(rename = "@isCharter", default)]
+    pub is_charter: bool,
+    // Timetable only: a runs-as-required path that hasn't been activated yet
+    #[serde(rename = "@qtrain", default)]
+    pub qtrain: bool,
+    // Timetable only: the whole journey is cancelled
+    #[serde(rename = "@can", default)]
+    pub cancelled: bool,
+    // Calling points in schedule order (OR, OPOR, IP, OPIP, PP, DT, OPDT)
+    #[serde(rename = "$value", default)]
+    pub locations: Vec<ScheduleLocation>,
+    #[serde(rename = "cancelReason")]
+    pub cancel_reason: Option<DisruptionReason>,
+}
+
+// The schedule is no longer active: its train didn't run, or ran under another RID
+#[derive(Debug, Deserialize)]
+pub struct DeactivatedSchedule {
+    #[serde(rename = "@rid")]
+    pub rid: CompactString,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub enum ScheduleLocation {
+    #[serde(rename = "OR")]
+    Origin(SchedulePoint),
+    #[serde(rename = "OPOR")]
+    OperationalOrigin(SchedulePoint),
+    #[serde(rename = "IP")]
+    Intermediate(SchedulePoint),
+    #[serde(rename = "OPIP")]
+    OperationalIntermediate(SchedulePoint),
+    #[serde(rename = "PP")]
+    Pass(SchedulePoint),
+    #[serde(rename = "DT")]
+    Destination(SchedulePoint),
+    #[serde(rename = "OPDT")]
+    OperationalDestination(SchedulePoint),
+}
+
+pub(crate) fn default_true() -> bool {
+    true
+}
+
+impl ScheduleLocation {
+    pub fn point(&self) -> &SchedulePoint {
+        match self {
+            ScheduleLocation::Origin(p)
+            | ScheduleLocation::OperationalOrigin(p)
+            | ScheduleLocation::Intermediate(p)
+            | ScheduleLocation::OperationalIntermediate(p)
+            | ScheduleLocation::Pass(p)
+            | ScheduleLocation::Destination(p)
+            | ScheduleLocation::OperationalDestination(p) => p,
+        }
+    }
+
+    pub fn point_mut(&mut self) -> &mut SchedulePoint {
+        match self {
+            ScheduleLocation::Origin(p)
+            | ScheduleLocation::OperationalOrigin(p)
+            | ScheduleLocation::Intermediate(p)
+            | ScheduleLocation::OperationalIntermediate(p)
+            | ScheduleLocation::Pass(p)
+            | ScheduleLocation::Destination(p)
+            | ScheduleLocation::OperationalDestination(p) => p,
+        }
+    }
+
+    // Only OR/IP/DT are passenger calls; OP* and PP are operational.
+    pub fn is_public_call(&self) -> bool {
+        matches!(
+            self,
+            ScheduleLocation::Origin(_)
+                | ScheduleLocation::Intermediate(_)
+                | ScheduleLocation::Destination(_)
+        )
+    }
 }
 
+// Union of the OR/OPOR/IP/OPIP/PP/DT/OPDT attributes; which are set depends on the variant.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SchedulePoint {
+    #[serde(rename = "@tpl")]
+    pub tiploc: CompactString,
+    #[serde(rename = "@act")]
+    pub act: Option<CompactString>,
+    #[serde(rename = "@planAct")]
+    pub plan_act: Option<CompactString>,
+    #[serde(rename = "@can", default)]
+    pub cancelled: bool,
+    #[serde(rename = "@fid")]
+    pub fid: Option<CompactString>,
+    #[serde(rename = "@pta")]
+    pub pta: Option<CompactString>,
+    #[serde(rename = "@ptd")]
+    pub ptd: Option<CompactString>,
+    #[serde(rename = "@wta")]
+    pub wta: Option<CompactString>,
+    #[serde(rename = "@wtd")]
+    pub wtd: Option<CompactString>,
+    #[serde(rename = "@wtp")]
+    pub wtp: Option<CompactString>,
+    #[serde(rename = "@avgLoading")]
+    pub avg_loading: Option<u32>,
+    #[serde(rename = "@rdelay")]
+    pub rdelay: Option<i32>, // Delay implied by a change to the route, in minutes
+    #[serde(rename = "@fd")]
+    pub false_destination: Option<CompactString>,
+}
+
+// Shared by LateReason and cancelReason (DisruptionReasonType)
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DisruptionReason {
+    #[serde(rename = "$value")]
+    pub code: Option<CompactString>,
+    #[serde(rename = "@tiploc")]
+    pub tiploc: Option<CompactString>,
+    #[serde(rename = "@near")]
+    pub near: Option<bool>,
+}
+
+// F is the version's TSTimeData
 #[derive(Debug, Deserialize)]
-pub struct TrainStatus {
+#[serde(bound(deserialize = "F: Deserialize<'de>"))]
+pub struct TrainStatus<F = Forecast> {
     #[serde(rename = "@rid")]
     pub rid: CompactString,
     #[serde(rename = "@uid")]
@@ -82,19 +300,31 @@ pub struct TrainStatus {
     #[serde(rename = "@isActive")]
     pub is_active: Option<bool>,
     #[serde(rename = "LateReason")]
-    pub late_reason: Option<LateReason>,
+    pub late_reason: Option<DisruptionReason>,
     #[serde(rename = "Location", default)]
-    pub locations: Vec<Location>,
+    pub locations: Vec<Location<F>>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct LateReason {
-    #[serde(rename = "$value")]
-    pub value: Option<CompactString>, // Reason Text
+impl<F: Into<Forecast>> TrainStatus<F> {
+    pub fn into_latest(self) -> TrainStatus {
+        TrainStatus {
+            rid: self.rid,
+            uid: self.uid,
+            ssd: self.ssd,
+            is_active: self.is_active,
+            late_reason: self.late_reason,
+            locations: self
+                .locations
+                .into_iter()
+                .map(Location::into_latest)
+                .collect(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
-pub struct Location {
+#[serde(bound(deserialize = "F: Deserialize<'de>"))]
+pub struct Location<F = Forecast> {
     #[serde(rename = "@tpl")]
     pub tiploc: Option<CompactString>,
     #[serde(rename = "@wta")]
@@ -103,6 +333,8 @@ pub struct Location {
     pub wtp: Option<CompactString>,
     #[serde(rename = "@wtd")]
     pub wtd: Option<CompactString>,
+    #[serde(rename = "@pta")]
+    pub pta: Option<CompactString>,
     #[serde(rename = "@ptd")]
     pub ptd: Option<CompactString>,
     #[serde(rename = "plat")]
@@ -110,15 +342,34 @@ pub struct Location {
     #[serde(rename = "suppr")]
     pub suppr: Option<bool>, // Add suppr flag
     #[serde(rename = "arr")]
-    pub arr: Option<Forecast>,
+    pub arr: Option<F>,
     #[serde(rename = "dep")]
-    pub dep: Option<Forecast>,
+    pub dep: Option<F>,
     #[serde(rename = "pass")]
-    pub pass: Option<Forecast>,
+    pub pass: Option<F>,
     #[serde(rename = "length")]
     pub length: Option<CompactString>,
 }
 
+impl<F: Into<Forecast>> Location<F> {
+    pub fn into_latest(self) -> Location {
+        Location {
+            tiploc: self.tiploc,
+            wta: self.wta,
+            wtp: self.wtp,
+            wtd: self.wtd,
+            pta: self.pta,
+            ptd: self.ptd,
+            platform: self.platform,
+            suppr: self.suppr,
+            arr: self.arr.map(Into::into),
+            dep: self.dep.map(Into::into),
+            pass: self.pass.map(Into::into),
+            length: self.length,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Platform {
     #[serde(rename = "$value")]
@@ -136,12 +387,45 @@ pub struct Platform {
                                         // User example: <fc:plat platsup="true" cisPlatsup="true">2</fc:plat> -> attributes.
 }
 
-#[derive(Debug, Deserialize)]
+// TSTimeData (rttiPPTForecasts_v3; see forecasts for v1 and v2)
+#[derive(Debug, Deserialize, Default)]
 pub struct Forecast {
     #[serde(rename = "@et")]
     pub et: Option<CompactString>,
+    #[serde(rename = "@wet")]
+    pub wet: Option<CompactString>, // Working estimate, may differ from et when suppressed
     #[serde(rename = "@at")]
     pub at: Option<CompactString>,
+    #[serde(rename = "@atRemoved", default)]
+    pub at_removed: bool, // A previous actual was withdrawn, fall back to et
+    #[serde(rename = "@atClass")]
+    pub at_class: Option<CompactString>,
+    #[serde(rename = "@etmin")]
+    pub etmin: Option<CompactString>, // Manually set lower bound for et
+    #[serde(rename = "@etUnknown", default)]
+    pub et_unknown: bool, // A manual "unknown delay" forecast is in place
+    #[serde(rename = "@delayed", default)]
+    pub delayed: bool, // Displayed as "Delayed", et is not reliable
+    #[serde(rename = "@src")]
+    pub src: Option<CompactString>,
+    #[serde(rename = "@srcInst")]
+    pub src_inst: Option<CompactString>,
+}
+
+impl Forecast {
+    // Actual time, unless Darwin has withdrawn it
+    pub fn actual(&self) -> Option<&str> {
+        if self.at_removed {
+            None
+        } else {
+            self.at.as_deref()
+        }
+    }
+
+    // No actual, and the estimate is flagged as an unknown delay
+    pub fn is_unknown_delay(&self) -> bool {
+        self.actual().is_none() && (self.delayed || self.et_unknown)
+    }
 }
 
 // New Types
@@ -202,11 +486,40 @@ pub struct StationMessage {
     #[serde(rename = "@id")]
     pub id: CompactString,
     #[serde(rename = "@cat")]
-    pub category: CompactString,
-    #[serde(rename = "Msg")]
-    pub message: CompactString, // Msg is an element
-    #[serde(rename = "Station")]
-    pub stations: Option<Vec<StationMessageStation>>,
+    pub category: CompactString, // Train, Station, Connections, System, Misc, PriorTrains, PriorOther
+    #[serde(rename = "@sev")]
+    pub severity: Option<CompactString>, // 0 (normal) to 3 (severe)
+    #[serde(rename = "@suppress", default)]
+    pub suppress: bool,
+    #[serde(rename = "Msg", default)]
+    pub message: MessageBody, // Msg is an element
+    #[serde(rename = "Station", default)]
+    pub stations: Vec<StationMessageStation>,
+}
+
+// Msg is mixed content: text interleaved with <p> paragraphs and <a> links
+#[derive(Debug, Deserialize, Default)]
+pub struct MessageBody {
+    #[serde(rename = "$value", default)]
+    pub parts: Vec<MessagePart>,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum MessagePart {
+    #[serde(rename = "$text")]
+    Text(CompactString),
+    #[serde(rename = "p")]
+    Paragraph(MessageBody),
+    #[serde(rename = "a")]
+    Anchor(MessageAnchor),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessageAnchor {
+    #[serde(rename = "@href")]
+    pub href: CompactString,
+    #[serde(rename = "$value", default)]
+    pub text: CompactString,
 }
 
 #[derive(Debug, Deserialize)]
@@ -215,27 +528,69 @@ pub struct StationMessageStation {
     pub crs: CompactString,
 }
 
-#[derive(Debug, Deserialize)]
+// formationLoading (Formations_v1 Loading): coach loading for one formation at one location
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Loading {
+    #[serde(rename = "@fid")]
+    pub fid: CompactString,
     #[serde(rename = "@rid")]
     pub rid: CompactString,
-    // Add loading fields as needed
+    #[serde(rename = "@tpl")]
+    pub tiploc: CompactString,
+    #[serde(rename = "@wta")]
+    pub wta: Option<CompactString>,
+    #[serde(rename = "@wtd")]
+    pub wtd: Option<CompactString>,
+    #[serde(rename = "@wtp")]
+    pub wtp: Option<CompactString>,
+    #[serde(rename = "@pta")]
+    pub pta: Option<CompactString>,
+    #[serde(rename = "@ptd")]
+    pub ptd: Option<CompactString>,
+    #[serde(rename = "loading", default)]
+    pub coaches: Vec<CoachLoading>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CoachLoading {
+    #[serde(rename = "@coachNumber")]
+    pub coach_number: CompactString,
+    #[serde(rename = "@src")]
+    pub src: Option<CompactString>,
+    #[serde(rename = "@srcInst")]
+    pub src_inst: Option<CompactString>,
+    #[serde(rename = "$text")]
+    pub percentage: u32, // 0-100
 }
 
 // Completed Structs
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Association {
     #[serde(rename = "@tiploc")]
     pub tiploc: CompactString,
     #[serde(rename = "@category")]
-    pub category: CompactString, // JJ=Join, VV=Divide, NP=NextWorking, etc.
+    pub category: CompactString, // JJ=Join, VV=Divide, NP=NextWorking, LK=Linked
+    #[serde(rename = "@isCancelled", default)]
+    pub is_cancelled: bool,
+    #[serde(rename = "@isDeleted", default)]
+    pub is_deleted: bool,
     #[serde(rename = "main")]
     pub main: AssociationService,
     #[serde(rename = "assoc")]
     pub assoc: AssociationService,
 }
 
-#[derive(Debug, Deserialize)]
+impl Association {
+    // Darwin identifies an association by its category, location and the two services
+    pub fn same_as(&self, other: &Association) -> bool {
+        self.category == other.category
+            && self.tiploc == other.tiploc
+            && self.main.rid == other.main.rid
+            && self.assoc.rid == other.assoc.rid
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AssociationService {
     #[serde(rename = "@rid")]
     pub rid: CompactString,
@@ -247,55 +602,115 @@ pub struct AssociationService {
     pub wta: Option<CompactString>,
     #[serde(rename = "@wtd")]
     pub wtd: Option<CompactString>,
+    #[serde(rename = "@wtp")]
+    pub wtp: Option<CompactString>,
 }
 
+// TrainAlerts_v1. The schema has no withdrawal element: Darwin re-sends the
+// alert with no services (or no text) to take it down.
 #[derive(Debug, Deserialize)]
 pub struct TrainAlert {
-    #[serde(rename = "@id")]
+    #[serde(rename = "AlertID")]
     pub id: CompactString,
-    // Alert logic is complex, often involves referencing other alerts or messages.
-    // Placeholder for now sufficient until specific alert logic requested?
-    // User requested "Fill in the other placeholder types".
-    // Basic structure:
-    #[serde(rename = "AlertWithdrawn")]
-    pub withdrawn: Option<CompactString>, // Element
-    #[serde(rename = "AlertService")]
-    pub service: Option<Vec<AlertService>>,
+    #[serde(rename = "AlertServices", default)]
+    pub services: AlertServices,
+    #[serde(rename = "SendAlertBySMS", default)]
+    pub send_by_sms: bool,
+    #[serde(rename = "SendAlertByEmail", default)]
+    pub send_by_email: bool,
+    #[serde(rename = "SendAlertByTwitter", default)]
+    pub send_by_twitter: bool,
+    #[serde(rename = "Source", default)]
+    pub source: CompactString,
+    #[serde(rename = "AlertText", default)]
+    pub text: CompactString,
+    #[serde(rename = "Audience", default)]
+    pub audience: AlertAudience,
+    #[serde(rename = "AlertType", default)]
+    pub alert_type: AlertType,
+    #[serde(rename = "CopiedFromAlertID")]
+    pub copied_from_alert_id: Option<CompactString>,
+    #[serde(rename = "CopiedFromSource")]
+    pub copied_from_source: Option<CompactString>,
+}
+
+impl TrainAlert {
+    pub fn is_withdrawn(&self) -> bool {
+        self.services.services.is_empty() || self.text.trim().is_empty()
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct AlertServices {
+    #[serde(rename = "AlertService", default)]
+    pub services: Vec<AlertService>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct AlertService {
-    #[serde(rename = "@rid")]
-    pub rid: CompactString,
-    #[serde(rename = "Location")]
-    pub location: Option<Vec<CompactString>>, // Tpl
+    #[serde(rename = "@RID")]
+    pub rid: Option<CompactString>,
+    #[serde(rename = "@UID")]
+    pub uid: Option<CompactString>,
+    #[serde(rename = "@SSD")]
+    pub ssd: Option<CompactString>,
+    #[serde(rename = "Location", default)]
+    pub locations: Vec<CompactString>, // TIPLOCs
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AlertAudience {
+    #[default]
+    Customer,
+    Staff,
+    Operations,
 }
 
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AlertType {
+    #[default]
+    Normal,
+    Forced,
+}
+
+// TDData_v1: a TD berth's train ID was corrected
 #[derive(Debug, Deserialize)]
 pub struct TrackingId {
-    #[serde(rename = "@id")]
+    #[serde(rename = "berth")]
+    pub berth: TdBerth,
+    #[serde(rename = "incorrectTrainID")]
+    pub incorrect_train_id: CompactString,
+    #[serde(rename = "correctTrainID")]
+    pub correct_train_id: CompactString,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TdBerth {
+    #[serde(rename = "@area")]
+    pub area: CompactString,
+    #[serde(rename = "$text")]
     pub id: CompactString,
-    #[serde(rename = "@correct")]
-    pub correct: bool, // "true" or "false"
 }
 
+// Alarms_v1: set carries the alarm, clear just its ID
 #[derive(Debug, Deserialize)]
 pub struct RTTIAlarm {
-    #[serde(rename = "@id")]
-    pub id: CompactString,
     #[serde(rename = "set")]
     pub set: Option<RTTIAlarmSet>,
     #[serde(rename = "clear")]
-    pub clear: Option<CompactString>, // Element value is ID usually? Or empty? Wiki says "clear element and the same unique identifier".
+    pub clear: Option<CompactString>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct RTTIAlarmSet {
-    #[serde(rename = "@tdAreaFail")]
-    pub td_area_fail: Option<bool>,
-    #[serde(rename = "@tyrell")]
-    pub tyrell: Option<bool>,
-    // and others
+    #[serde(rename = "@id")]
+    pub id: CompactString,
+    #[serde(rename = "tdAreaFail")]
+    pub td_area_fail: Option<CompactString>, // TD area ID
+    #[serde(rename = "tdFeedFail")]
+    pub td_feed_fail: Option<CompactString>,
+    #[serde(rename = "tyrellFeedFail")]
+    pub tyrell_feed_fail: Option<CompactString>,
 }
 
 #[cfg(test)]
@@ -323,4 +738,52 @@ mod tests {
             Err(e) => panic!("Failed full parsing: {:?}", e),
         }
     }
+
+    #[test]
+    fn test_schedule_locations_keep_order() {
+        let xml = r#"<Pport ts="T" version="16.0"><uR updateOrigin="CIS"><schedule rid="R1" uid="U" trainId="1A01" ssd="2026-01-15" toc="GW"><OR tpl="A" ptd="08:00" wtd="08:00"/><PP tpl="B" wtp="08:10"/><IP tpl="C" pta="08:20" ptd="08:21" wta="08:20" wtd="08:21" can="true"/><DT tpl="D" pta="08:40" wta="08:40"/><cancelReason tiploc="C" near="true">104</cancelReason></schedule></uR></Pport>"#;
+        let pport: Pport = from_str(xml).expect("schedule should parse");
+        let schedule = &pport.update_record.unwrap().schedule[0];
+
+        let tiplocs: Vec<&str> = schedule
+            .locations
+            .iter()
+            .map(|l| l.point().tiploc.as_str())
+            .collect();
+        assert_eq!(tiplocs, vec!["A", "B", "C", "D"]);
+        assert!(matches!(schedule.locations[1], ScheduleLocation::Pass(_)));
+        assert!(schedule.locations[2].point().cancelled);
+        assert!(!schedule.locations[3].point().cancelled);
+
+        let reason = schedule.cancel_reason.as_ref().unwrap();
+        assert_eq!(reason.code.as_deref(), Some("104"));
+        assert_eq!(reason.tiploc.as_deref(), Some("C"));
+    }
+
+    #[test]
+    fn test_schedule_record_full_model_roundtrips() {
+        let xml = r#"<Pport ts="T" version="16.0"><sR><schedule rid="202601158712345" uid="C12345" trainId="1A01" ssd="2026-01-15" toc="GW" trainCat="XX"><OR tpl="PADTON" act="TB" ptd="08:00" wtd="08:00" fid="202601158712345-001"/><OPIP tpl="OXFDSJN" wta="08:50:30" wtd="08:51"/><DT tpl="OXFD" act="TF" pta="09:00" wta="08:59" avgLoading="42"/></schedule></sR></Pport>"#;
+        let pport: Pport = from_str(xml).expect("sR should parse");
+        let schedule = pport.snapshot_record.unwrap().schedule.remove(0);
+
+        assert_eq!(schedule.toc.as_deref(), Some("GW"));
+        assert_eq!(schedule.train_cat.as_deref(), Some("XX"));
+        assert!(schedule.is_passenger_svc, "isPassengerSvc defaults to true");
+        assert!(!schedule.deleted);
+        assert_eq!(
+            schedule.locations[1].point().wta.as_deref(),
+            Some("08:50:30")
+        );
+        assert_eq!(schedule.locations[2].point().avg_loading, Some(42));
+
+        // Persistence stores schedules with bincode
+        let bytes = bincode::serialize(&schedule).unwrap();
+        let restored: Schedule = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored.rid, schedule.rid);
+        assert_eq!(restored.locations.len(), 3);
+        assert!(matches!(
+            restored.locations[1],
+            ScheduleLocation::OperationalIntermediate(_)
+        ));
+    }
 }
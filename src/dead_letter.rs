@@ -0,0 +1,222 @@
+// Messages that failed to parse, kept on disk so parser bugs can be reproduced.
+// Each is stored as the STOMP MESSAGE frame it arrived in (Kafka records get their
+// topic, partition and offset as headers), plus dead-letter-* headers saying when and
+// why it failed. Only the newest `capacity` are kept.
+
+use crate::frame_error::FrameError;
+use crate::stomp::{Frame, Headers, read_frame};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const EXTENSION: &str = "stomp";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    pub id: String,
+    // Unix milliseconds
+    pub received_at: i64,
+    pub error_kind: String,
+    pub error: String,
+    pub headers: Vec<(String, String)>,
+    pub body_bytes: usize,
+}
+
+// Disabled (nothing stored) until opened on a directory
+#[derive(Default)]
+pub struct DeadLetters {
+    dir: Option<PathBuf>,
+    capacity: usize,
+    letters: Mutex<VecDeque<DeadLetter>>,
+    next: AtomicU64,
+}
+
+impl DeadLetters {
+    // Index whatever an earlier run left in `dir`
+    pub async fn open(dir: impl Into<PathBuf>, capacity: usize) -> Result<Self> {
+        let dir = dir.into();
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        let mut letters = Vec::new();
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            match read(&path).await {
+                Ok((headers, body)) => letters.push(describe(id, &headers, body.len())),
+                Err(e) => eprintln!("Skipping dead letter {}: {}", path.display(), e),
+            }
+        }
+        letters.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let store = Self {
+            dir: Some(dir),
+            capacity: capacity.max(1),
+            letters: Mutex::new(letters.into()),
+            next: AtomicU64::new(0),
+        };
+        store.evict(&mut store.letters.lock().unwrap());
+        Ok(store)
+    }
+
+    // Write a frame that failed to parse. Blocking file I/O, called from the parser threads.
+    pub fn store(&self, headers: &Headers, body: &[u8], error: &FrameError) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let received_at = Utc::now().timestamp_millis();
+        let id = format!(
+            "{:013}-{:06}",
+            received_at,
+            self.next.fetch_add(1, Ordering::Relaxed) % 1_000_000
+        );
+        let headers = headers
+            .clone()
+            .with("dead-letter-received-at", received_at.to_string())
+            .with("dead-letter-error-kind", error.kind())
+            .with("dead-letter-error", error.to_string());
+
+        let letter = describe(&id, &headers, body.len());
+        let path = dir.join(format!("{}.{}", id, EXTENSION));
+        if let Err(e) = std::fs::write(&path, Frame::Message(headers, body.to_vec()).encode()) {
+            eprintln!("Failed to write dead letter {}: {}", path.display(), e);
+            return;
+        }
+        let mut letters = self.letters.lock().unwrap();
+        letters.push_back(letter);
+        self.evict(&mut letters);
+    }
+
+    fn evict(&self, letters: &mut VecDeque<DeadLetter>) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        while letters.len() > self.capacity {
+            let Some(oldest) = letters.pop_front() else {
+                break;
+            };
+            let path = dir.join(format!("{}.{}", oldest.id, EXTENSION));
+            if let Err(e) = std::fs::remove_file(&path) {
+                eprintln!("Failed to remove dead letter {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    // Oldest first
+    pub fn list(&self) -> Vec<DeadLetter> {
+        self.letters.lock().unwrap().iter().cloned().collect()
+    }
+
+    // The stored frame file, if `id` is one of ours
+    pub async fn frame(&self, id: &str) -> Option<Vec<u8>> {
+        let path = self.path(id)?;
+        tokio::fs::read(path).await.ok()
+    }
+
+    // Just the message body, as it would be handed to the parser
+    pub async fn body(&self, id: &str) -> Option<Vec<u8>> {
+        let path = self.path(id)?;
+        read(&path).await.ok().map(|(_, body)| body)
+    }
+
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        let letters = self.letters.lock().unwrap();
+        letters
+            .iter()
+            .any(|l| l.id == id)
+            .then(|| dir.join(format!("{}.{}", id, EXTENSION)))
+    }
+}
+
+async fn read(path: &std::path::Path) -> Result<(Headers, Vec<u8>)> {
+    let bytes = tokio::fs::read(path).await?;
+    match read_frame(&mut bytes.as_slice()).await? {
+        Frame::Message(headers, body) => Ok((headers, body)),
+        other => anyhow::bail!("Expected a MESSAGE frame, found {}", other.command()),
+    }
+}
+
+fn describe(id: &str, headers: &Headers, body_bytes: usize) -> DeadLetter {
+    let header = |name| headers.get(name).unwrap_or_default().to_string();
+    DeadLetter {
+        id: id.to_string(),
+        received_at: header("dead-letter-received-at")
+            .parse()
+            .unwrap_or_default(),
+        error_kind: header("dead-letter-error-kind"),
+        error: header("dead-letter-error"),
+        headers: headers
+            .iter()
+            .filter(|(name, _)| !name.starts_with("dead-letter-"))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        body_bytes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(message_id: &str) -> Headers {
+        Headers::new()
+            .with("destination", "/topic/darwin.pushport-v16")
+            .with("message-id", message_id)
+    }
+
+    #[tokio::test]
+    async fn keeps_the_newest_frames_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DeadLetters::open(dir.path(), 2).await.unwrap();
+        let error = FrameError::XmlSyntax("unexpected end of input".to_string());
+        // A gzipped body has NULs in it; content-length keeps it whole
+        let body = [0x1f, 0x8b, 0x08, 0x00, b'\n', 0x00, 0xff];
+        for message_id in ["ID:1", "ID:2", "ID:3"] {
+            store.store(&headers(message_id), &body, &error);
+        }
+
+        let letters = store.list();
+        assert_eq!(letters.len(), 2);
+        assert_eq!(letters[0].headers[1], ("message-id".into(), "ID:2".into()));
+        assert_eq!(letters[1].error_kind, "xml_syntax");
+        assert_eq!(letters[1].error, "Malformed XML: unexpected end of input");
+        assert_eq!(letters[1].body_bytes, body.len());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        assert_eq!(store.body(&letters[1].id).await.unwrap(), body);
+        let frame = store.frame(&letters[1].id).await.unwrap();
+        assert!(frame.starts_with(b"MESSAGE\ndestination:/topic/darwin.pushport-v16\n"));
+        assert_eq!(store.body("../../etc/passwd").await, None);
+
+        // Reopened with room for only one
+        let reopened = DeadLetters::open(dir.path(), 1).await.unwrap();
+        let letters = reopened.list();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].headers[1], ("message-id".into(), "ID:3".into()));
+        assert_eq!(letters[0].received_at, store.list()[1].received_at);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn disabled_store_keeps_nothing() {
+        let store = DeadLetters::default();
+        store.store(
+            &headers("ID:1"),
+            b"<Pport",
+            &FrameError::XmlSyntax(String::new()),
+        );
+        assert!(store.list().is_empty());
+    }
+}
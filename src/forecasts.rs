@@ -0,0 +1,90 @@
+// TSTimeData as rttiPPTForecasts_v1 and v2 define it. v3 is darwin_types::Forecast.
+
+use crate::darwin_types::Forecast;
+
+pub mod v1 {
+    use compact_str::CompactString;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    pub struct TsTimeData {
+        #[serde(rename = "@et")]
+        pub et: Option<CompactString>,
+        #[serde(rename = "@at")]
+        pub at: Option<CompactString>,
+        #[serde(rename = "@atRemoved", default)]
+        pub at_removed: bool,
+        #[serde(rename = "@etmin")]
+        pub etmin: Option<CompactString>,
+        #[serde(rename = "@etUnknown", default)]
+        pub et_unknown: bool,
+        #[serde(rename = "@delayed", default)]
+        pub delayed: bool,
+        #[serde(rename = "@src")]
+        pub src: Option<CompactString>,
+        #[serde(rename = "@srcInst")]
+        pub src_inst: Option<CompactString>,
+    }
+}
+
+// v2 added working estimates
+pub mod v2 {
+    use compact_str::CompactString;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    pub struct TsTimeData {
+        #[serde(rename = "@et")]
+        pub et: Option<CompactString>,
+        #[serde(rename = "@wet")]
+        pub wet: Option<CompactString>,
+        #[serde(rename = "@at")]
+        pub at: Option<CompactString>,
+        #[serde(rename = "@atRemoved", default)]
+        pub at_removed: bool,
+        #[serde(rename = "@etmin")]
+        pub etmin: Option<CompactString>,
+        #[serde(rename = "@etUnknown", default)]
+        pub et_unknown: bool,
+        #[serde(rename = "@delayed", default)]
+        pub delayed: bool,
+        #[serde(rename = "@src")]
+        pub src: Option<CompactString>,
+        #[serde(rename = "@srcInst")]
+        pub src_inst: Option<CompactString>,
+    }
+}
+
+impl From<v1::TsTimeData> for Forecast {
+    fn from(v1: v1::TsTimeData) -> Self {
+        Forecast {
+            et: v1.et,
+            wet: None,
+            at: v1.at,
+            at_removed: v1.at_removed,
+            at_class: None,
+            etmin: v1.etmin,
+            et_unknown: v1.et_unknown,
+            delayed: v1.delayed,
+            src: v1.src,
+            src_inst: v1.src_inst,
+        }
+    }
+}
+
+impl From<v2::TsTimeData> for Forecast {
+    fn from(v2: v2::TsTimeData) -> Self {
+        Forecast {
+            et: v2.et,
+            wet: v2.wet,
+            at: v2.at,
+            at_removed: v2.at_removed,
+            at_class: None,
+            etmin: v2.etmin,
+            et_unknown: v2.et_unknown,
+            delayed: v2.delayed,
+            src: v2.src,
+            src_inst: v2.src_inst,
+        }
+    }
+}
@@ -123,3 +123,42 @@ impl From<v2::CoachData> for v1::CoachData {
         }
     }
 }
+
+// v1 coaches have no toilets
+impl From<v1::ScheduleFormations> for v2::ScheduleFormations {
+    fn from(v1: v1::ScheduleFormations) -> Self {
+        v2::ScheduleFormations {
+            rid: v1.rid,
+            formations: v1.formations.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<v1::Formation> for v2::Formation {
+    fn from(v1: v1::Formation) -> Self {
+        v2::Formation {
+            fid: v1.fid,
+            src: v1.src,
+            src_inst: v1.src_inst,
+            coaches: v1.coaches.into(),
+        }
+    }
+}
+
+impl From<v1::CoachList> for v2::CoachList {
+    fn from(v1: v1::CoachList) -> Self {
+        v2::CoachList {
+            coaches: v1.coaches.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<v1::CoachData> for v2::CoachData {
+    fn from(v1: v1::CoachData) -> Self {
+        v2::CoachData {
+            coach_number: v1.coach_number,
+            coach_class: v1.coach_class,
+            toilet: None,
+        }
+    }
+}
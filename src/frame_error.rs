@@ -0,0 +1,87 @@
+// Why a Darwin message couldn't be turned into a Pport
+
+use crate::schema_version::UnsupportedVersion;
+use quick_xml::DeError;
+
+#[derive(Debug)]
+pub enum FrameError {
+    // No body at all
+    Empty,
+    // Neither gzip nor XML
+    UnknownEncoding,
+    // The body looked gzipped but didn't inflate
+    Gzip(std::io::Error),
+    Utf8(std::str::Utf8Error),
+    // Not well-formed XML, or cut short
+    XmlSyntax(String),
+    // Well-formed, but not the shape darwin_types expects (missing or mistyped values,
+    // unsupported Pport@version)
+    SchemaMismatch(String),
+    // An element where only known ones may appear, e.g. a new schedule location type
+    UnknownElement(String),
+    // A Kafka record whose JSON envelope didn't parse
+    Envelope(String),
+}
+
+impl FrameError {
+    // Short name for logs and the dead-letter listing
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FrameError::Empty => "empty",
+            FrameError::UnknownEncoding => "unknown_encoding",
+            FrameError::Gzip(_) => "gzip",
+            FrameError::Utf8(_) => "utf8",
+            FrameError::XmlSyntax(_) => "xml_syntax",
+            FrameError::SchemaMismatch(_) => "schema_mismatch",
+            FrameError::UnknownElement(_) => "unknown_element",
+            FrameError::Envelope(_) => "envelope",
+        }
+    }
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Empty => write!(f, "Body is empty"),
+            FrameError::UnknownEncoding => write!(f, "Body is neither gzip nor XML"),
+            FrameError::Gzip(e) => write!(f, "Failed to inflate gzipped body: {}", e),
+            FrameError::Utf8(e) => write!(f, "Body is not UTF-8: {}", e),
+            FrameError::XmlSyntax(message) => write!(f, "Malformed XML: {}", message),
+            FrameError::SchemaMismatch(message) => {
+                write!(f, "XML doesn't match the Push Port schema: {}", message)
+            }
+            FrameError::UnknownElement(message) => write!(f, "Unknown element: {}", message),
+            FrameError::Envelope(message) => write!(f, "Malformed Kafka envelope: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<std::str::Utf8Error> for FrameError {
+    fn from(e: std::str::Utf8Error) -> Self {
+        FrameError::Utf8(e)
+    }
+}
+
+impl From<DeError> for FrameError {
+    fn from(e: DeError) -> Self {
+        match e {
+            DeError::InvalidXml(_) | DeError::UnexpectedEof => FrameError::XmlSyntax(e.to_string()),
+            // serde's wording for a variant or field name it doesn't know
+            DeError::Custom(message)
+                if message.starts_with("unknown variant")
+                    || message.starts_with("unknown field") =>
+            {
+                FrameError::UnknownElement(message)
+            }
+            other => FrameError::SchemaMismatch(other.to_string()),
+        }
+    }
+}
+
+impl From<UnsupportedVersion> for FrameError {
+    fn from(e: UnsupportedVersion) -> Self {
+        FrameError::SchemaMismatch(e.to_string())
+    }
+}
@@ -1,15 +1,16 @@
 use crate::state::AppState;
 use chrono::Utc;
-use compact_str::CompactString;
+use compact_str::{CompactString, format_compact};
 use std::time::Duration;
 
 pub fn cleanup_old_trips(state: &AppState, threshold: Duration) {
     let now = Utc::now().timestamp();
     let threshold_secs = threshold.as_secs() as i64;
 
-    // We want to find trips where the LAST stop time update + threshold < now.
-    // Or if there are no stop time updates, maybe use start_date?
-    // Let's iterate over trip_updates.
+    // Trips go once their last stop time update is more than `threshold` in the past,
+    // or, when they carry no times at all, once their start_date is well past. A trip's
+    // "{trip_id}_VP" vehicle entity goes with it; one left without a trip update ages out
+    // by its own start_date.
 
     let mut trips_to_remove: Vec<CompactString> = Vec::new();
 
@@ -21,15 +22,15 @@ pub fn cleanup_old_trips(state: &AppState, threshold: Duration) {
 
         if let Some(tu) = &entity.trip_update {
             for stu in &tu.stop_time_update {
-                if let Some(arrival) = &stu.arrival {
-                    if let Some(t) = arrival.time {
-                        max_time = Some(max_time.map_or(t, |m| m.max(t)));
-                    }
+                if let Some(arrival) = &stu.arrival
+                    && let Some(t) = arrival.time
+                {
+                    max_time = Some(max_time.map_or(t, |m| m.max(t)));
                 }
-                if let Some(departure) = &stu.departure {
-                    if let Some(t) = departure.time {
-                        max_time = Some(max_time.map_or(t, |m| m.max(t)));
-                    }
+                if let Some(departure) = &stu.departure
+                    && let Some(t) = departure.time
+                {
+                    max_time = Some(max_time.map_or(t, |m| m.max(t)));
                 }
             }
         }
@@ -39,13 +40,24 @@ pub fn cleanup_old_trips(state: &AppState, threshold: Duration) {
             if last_activity + threshold_secs < now {
                 trips_to_remove.push(trip_id.clone());
             }
-        } else {
-            // If no time data, maybe it's very old? Or very new?
-            // Safest is to keep it unless we can prove it's old.
-            // But if we have start_date, we could use that.
-            // For now, let's only remove if we have explicit time updates that are old.
-            // Actually, if a trip has NO updates, it might be zombie.
-            // But let's stick to the "ended" logic (has times, and they are in the past).
+        } else if let Some(start_date) = entity
+            .trip_update
+            .as_ref()
+            .map(|tu| &tu.trip)
+            .or_else(|| entity.vehicle.as_ref()?.trip.as_ref())
+            .and_then(|trip| trip.start_date.as_deref())
+            .and_then(|d| chrono::NaiveDate::parse_from_str(d, "%Y%m%d").ok())
+        {
+            // No times (cancelled, or only SKIPPED calls), so age it out by start_date instead.
+            // Allow 48 hours so services running past midnight are well clear.
+            let start_timestamp = start_date
+                .and_hms_opt(0, 0, 0)
+                .unwrap_or_default()
+                .and_utc()
+                .timestamp();
+            if start_timestamp + 172800 + threshold_secs < now {
+                trips_to_remove.push(trip_id.clone());
+            }
         }
     }
 
@@ -56,7 +68,11 @@ pub fn cleanup_old_trips(state: &AppState, threshold: Duration) {
         // Remove from trip_updates
         for trip_id in &trips_to_remove {
             state.trip_updates.remove(trip_id);
+            state
+                .trip_updates
+                .remove(&format_compact!("{}_VP", trip_id));
             state.platforms_v2.remove(trip_id);
+            state.unknown_delays.remove(trip_id);
         }
 
         // Clean up rid_to_trip_id
@@ -79,30 +95,12 @@ pub fn cleanup_old_trips(state: &AppState, threshold: Duration) {
 
     // GC for formations
     // "only if the trip has already passed and the departures were more than 24hrs ago"
-    // RIDs start with YYYYMMDD. We can use this to reliably determine if it's an old schedule.
-    let mut formations_to_remove: Vec<CompactString> = Vec::new();
-    for r in state.formations.iter() {
-        let rid = r.key();
-        if rid.len() >= 8 {
-            if let Ok(schedule_date) = chrono::NaiveDate::parse_from_str(&rid[0..8], "%Y%m%d") {
-                // schedule_date is the YYYYMMDD of the trip.
-                // The trip could depart at 23:59 that day.
-                // So max departure could be schedule date + 24 hours.
-                // We want to remove if those departures were > 48 hours ago.
-                // Therefore, if now > schedule_date at midnight + 72 hours.
-                let schedule_timestamp = schedule_date
-                    .and_hms_opt(0, 0, 0)
-                    .unwrap_or_default()
-                    .and_utc()
-                    .timestamp();
-
-                // 3 days * 24 hours * 3600 seconds = 259200
-                if now > schedule_timestamp + 259200 {
-                    formations_to_remove.push(rid.clone());
-                }
-            }
-        }
-    }
+    let formations_to_remove: Vec<CompactString> = state
+        .formations
+        .iter()
+        .filter(|r| rid_expired(r.key(), now))
+        .map(|r| r.key().clone())
+        .collect();
 
     let f_count = formations_to_remove.len();
     if f_count > 0 {
@@ -111,6 +109,107 @@ pub fn cleanup_old_trips(state: &AppState, threshold: Duration) {
             state.formations.remove(&rid);
         }
     }
+
+    // GC for schedules, same rule as formations
+    let schedules_to_remove: Vec<CompactString> = state
+        .schedules
+        .iter()
+        .filter(|r| rid_expired(r.key(), now))
+        .map(|r| r.key().clone())
+        .collect();
+
+    let s_count = schedules_to_remove.len();
+    if s_count > 0 {
+        println!("GC: Found {} expired schedules. Cleaning up...", s_count);
+        for rid in schedules_to_remove {
+            state.schedules.remove(&rid);
+        }
+    }
+
+    // GC for loadings, same rule as formations
+    let loadings_to_remove: Vec<CompactString> = state
+        .loadings
+        .iter()
+        .filter(|r| rid_expired(r.key(), now))
+        .map(|r| r.key().clone())
+        .collect();
+
+    let l_count = loadings_to_remove.len();
+    if l_count > 0 {
+        println!("GC: Found {} expired loadings. Cleaning up...", l_count);
+        for rid in loadings_to_remove {
+            state.loadings.remove(&rid);
+        }
+    }
+
+    // GC for associations and forecast markers, same rule as formations
+    let associations_to_remove: Vec<CompactString> = state
+        .associations
+        .iter()
+        .filter(|r| rid_expired(r.key(), now))
+        .map(|r| r.key().clone())
+        .collect();
+
+    let as_count = associations_to_remove.len();
+    if as_count > 0 {
+        println!(
+            "GC: Found {} expired association entries. Cleaning up...",
+            as_count
+        );
+        for rid in associations_to_remove {
+            state.associations.remove(&rid);
+        }
+    }
+    state.forecast_rids.retain(|rid| !rid_expired(rid, now));
+
+    // GC for train alerts, once every trip they inform is past the same cutoff.
+    // start_date is YYYYMMDD like the start of a RID.
+    let alerts_to_remove: Vec<CompactString> = state
+        .alerts
+        .iter()
+        .filter(|r| {
+            let Some(alert) = &r.value().alert else {
+                return false;
+            };
+            let mut dates = alert
+                .informed_entity
+                .iter()
+                .filter_map(|e| e.trip.as_ref()?.start_date.as_deref())
+                .peekable();
+            dates.peek().is_some() && dates.all(|d| rid_expired(d, now))
+        })
+        .map(|r| r.key().clone())
+        .collect();
+
+    let a_count = alerts_to_remove.len();
+    if a_count > 0 {
+        println!("GC: Found {} expired train alerts. Cleaning up...", a_count);
+        for id in alerts_to_remove {
+            state.alerts.remove(&id);
+        }
+    }
+}
+
+// RIDs start with YYYYMMDD. We can use this to reliably determine if it's an old schedule.
+fn rid_expired(rid: &str, now: i64) -> bool {
+    if rid.len() >= 8
+        && let Ok(schedule_date) = chrono::NaiveDate::parse_from_str(&rid[0..8], "%Y%m%d")
+    {
+        // schedule_date is the YYYYMMDD of the trip.
+        // The trip could depart at 23:59 that day.
+        // So max departure could be schedule date + 24 hours.
+        // We want to remove if those departures were > 48 hours ago.
+        // Therefore, if now > schedule_date at midnight + 72 hours.
+        let schedule_timestamp = schedule_date
+            .and_hms_opt(0, 0, 0)
+            .unwrap_or_default()
+            .and_utc()
+            .timestamp();
+
+        // 3 days * 24 hours * 3600 seconds = 259200
+        return now > schedule_timestamp + 259200;
+    }
+    false
 }
 
 #[cfg(test)]
@@ -119,7 +218,7 @@ mod tests {
     use crate::state::AppState;
     use gtfs_realtime::{
         FeedEntity, TripUpdate,
-        trip_update::{StopTimeEvent, StopTimeUpdate},
+        trip_update::{StopTimeEvent, StopTimeUpdate, stop_time_update::ScheduleRelationship},
     };
 
     #[test]
@@ -135,35 +234,45 @@ mod tests {
 
         // 1. Create Active Trip (Current time)
         let active_trip_id = CompactString::from("trip_active");
-        let mut active_fe = FeedEntity::default();
-        active_fe.id = active_trip_id.to_string();
-        let mut active_tu = TripUpdate::default();
-        let mut active_stu = StopTimeUpdate::default();
-        active_stu.departure = Some(StopTimeEvent {
-            time: Some(now),
-            delay: None,
-            uncertainty: None,
-            scheduled_time: None,
-        });
-        active_tu.stop_time_update.push(active_stu);
-        active_fe.trip_update = Some(active_tu);
+        let active_stu = StopTimeUpdate {
+            departure: Some(StopTimeEvent {
+                time: Some(now),
+                delay: None,
+                uncertainty: None,
+                scheduled_time: None,
+            }),
+            ..Default::default()
+        };
+        let active_fe = FeedEntity {
+            id: active_trip_id.to_string(),
+            trip_update: Some(TripUpdate {
+                stop_time_update: vec![active_stu],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
 
         state.trip_updates.insert(active_trip_id.clone(), active_fe);
 
         // 2. Create Old Trip (2 hours ago)
         let old_trip_id = CompactString::from("trip_old");
-        let mut old_fe = FeedEntity::default();
-        old_fe.id = old_trip_id.to_string();
-        let mut old_tu = TripUpdate::default();
-        let mut old_stu = StopTimeUpdate::default();
-        old_stu.departure = Some(StopTimeEvent {
-            time: Some(now - 7200),
-            delay: None,
-            uncertainty: None,
-            scheduled_time: None,
-        }); // 2 hours ago
-        old_tu.stop_time_update.push(old_stu);
-        old_fe.trip_update = Some(old_tu);
+        let old_stu = StopTimeUpdate {
+            departure: Some(StopTimeEvent {
+                time: Some(now - 7200),
+                delay: None,
+                uncertainty: None,
+                scheduled_time: None,
+            }), // 2 hours ago
+            ..Default::default()
+        };
+        let old_fe = FeedEntity {
+            id: old_trip_id.to_string(),
+            trip_update: Some(TripUpdate {
+                stop_time_update: vec![old_stu],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
 
         state.trip_updates.insert(old_trip_id.clone(), old_fe);
 
@@ -197,4 +306,93 @@ mod tests {
             "Old RID should be removed"
         );
     }
+    #[test]
+    fn trips_without_times_age_out_by_start_date() {
+        let state = AppState::new("http://localhost".to_string());
+        let today = Utc::now().date_naive();
+        // Only a SKIPPED call, so no times to go by
+        let skipped_only = |start_date: chrono::NaiveDate| FeedEntity {
+            trip_update: Some(TripUpdate {
+                trip: gtfs_realtime::TripDescriptor {
+                    start_date: Some(start_date.format("%Y%m%d").to_string()),
+                    ..Default::default()
+                },
+                stop_time_update: vec![StopTimeUpdate {
+                    stop_id: Some("RDG".to_string()),
+                    schedule_relationship: Some(ScheduleRelationship::Skipped as i32),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        state
+            .trip_updates
+            .insert("trip_today".into(), skipped_only(today));
+        state.trip_updates.insert(
+            "trip_last_week".into(),
+            skipped_only(today - chrono::Duration::days(7)),
+        );
+
+        cleanup_old_trips(&state, Duration::from_secs(3600));
+
+        assert!(state.trip_updates.contains_key("trip_today"));
+        assert!(!state.trip_updates.contains_key("trip_last_week"));
+    }
+
+    #[test]
+    fn vehicle_entities_go_with_their_trip_or_their_start_date() {
+        let state = AppState::new("http://localhost".to_string());
+        let now = Utc::now().timestamp();
+        let today = Utc::now().date_naive();
+        let vehicle = |trip_id: &str, start_date: chrono::NaiveDate| FeedEntity {
+            id: format!("{}_VP", trip_id),
+            vehicle: Some(gtfs_realtime::VehiclePosition {
+                trip: Some(gtfs_realtime::TripDescriptor {
+                    trip_id: Some(trip_id.to_string()),
+                    start_date: Some(start_date.format("%Y%m%d").to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let last_week = today - chrono::Duration::days(7);
+
+        // Finished two hours ago, with today's date on its vehicle
+        state.trip_updates.insert(
+            "trip_old".into(),
+            FeedEntity {
+                trip_update: Some(TripUpdate {
+                    stop_time_update: vec![StopTimeUpdate {
+                        arrival: Some(StopTimeEvent {
+                            time: Some(now - 7200),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        state
+            .trip_updates
+            .insert("trip_old_VP".into(), vehicle("trip_old", today));
+        // No trip update of their own
+        state
+            .trip_updates
+            .insert("orphan_today_VP".into(), vehicle("orphan_today", today));
+        state.trip_updates.insert(
+            "orphan_last_week_VP".into(),
+            vehicle("orphan_last_week", last_week),
+        );
+
+        cleanup_old_trips(&state, Duration::from_secs(3600));
+
+        assert!(!state.trip_updates.contains_key("trip_old"));
+        assert!(!state.trip_updates.contains_key("trip_old_VP"));
+        assert!(state.trip_updates.contains_key("orphan_today_VP"));
+        assert!(!state.trip_updates.contains_key("orphan_last_week_VP"));
+    }
 }
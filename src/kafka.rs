@@ -0,0 +1,290 @@
+// Darwin Push Port from the Rail Data Marketplace Kafka (Confluent) topic.
+// Each record's value is a JSON envelope whose `bytes` field holds the Pport XML as text.
+
+use crate::frame_error::FrameError;
+use crate::source::Source;
+use crate::state::AppState;
+use crate::stomp::Headers;
+use crate::work_queue::{Ack, WorkQueue};
+use anyhow::Result;
+use chrono::Utc;
+use rdkafka::Message as _;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::types::RDKafkaErrorCode;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+// Kafka consumer settings, from the environment
+pub struct KafkaConfig {
+    // DARWIN_KAFKA_BROKERS, e.g. "pkc-xxxxx.westeurope.azure.confluent.cloud:9092"
+    pub brokers: String,
+    pub topic: String,
+    // The consumer group issued with the subscription (DARWIN_KAFKA_GROUP_ID)
+    pub group_id: String,
+    // SASL/PLAIN over TLS when set (DARWIN_KAFKA_USER, DARWIN_KAFKA_PASS)
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl KafkaConfig {
+    pub fn from_env() -> Self {
+        Self {
+            brokers: std::env::var("DARWIN_KAFKA_BROKERS").expect("DARWIN_KAFKA_BROKERS not set"),
+            topic: std::env::var("DARWIN_KAFKA_TOPIC").expect("DARWIN_KAFKA_TOPIC not set"),
+            group_id: std::env::var("DARWIN_KAFKA_GROUP_ID")
+                .expect("DARWIN_KAFKA_GROUP_ID not set"),
+            username: std::env::var("DARWIN_KAFKA_USER").ok(),
+            password: std::env::var("DARWIN_KAFKA_PASS").ok(),
+        }
+    }
+
+    fn client_config(&self) -> ClientConfig {
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", &self.brokers)
+            .set("group.id", &self.group_id)
+            .set("auto.offset.reset", "latest")
+            // Offsets are stored once a message is applied and committed in the background
+            .set("enable.auto.commit", "true")
+            .set("enable.auto.offset.store", "false");
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            config
+                .set("security.protocol", "SASL_SSL")
+                .set("sasl.mechanisms", "PLAIN")
+                .set("sasl.username", username)
+                .set("sasl.password", password);
+        }
+        config
+    }
+}
+
+pub struct KafkaSource(pub KafkaConfig);
+
+impl Source for KafkaSource {
+    fn describe(&self) -> String {
+        format!("Darwin Kafka topic {} at {}", self.0.topic, self.0.brokers)
+    }
+
+    async fn listen(&self, state: &Arc<AppState>, queue: &WorkQueue) -> Result<()> {
+        let consumer: Arc<StreamConsumer> = Arc::new(self.0.client_config().create()?);
+        consumer.subscribe(&[&self.0.topic])?;
+        let offsets = Arc::new(Mutex::new(OffsetTracker::default()));
+
+        loop {
+            let message = consumer.recv().await?;
+            state
+                .last_message_at
+                .store(Utc::now().timestamp(), Ordering::Relaxed);
+
+            let (partition, offset) = (message.partition(), message.offset());
+            // Where the record came from, in place of STOMP headers should it be dead-lettered
+            let headers = Headers::new()
+                .with("kafka-topic", self.0.topic.as_str())
+                .with("kafka-partition", partition.to_string())
+                .with("kafka-offset", offset.to_string());
+            // Offsets after it still commit past a dead-lettered record
+            let Some(body) =
+                envelope_body(message.payload().unwrap_or_default(), &headers, state).await
+            else {
+                continue;
+            };
+            offsets.lock().unwrap().received(partition, offset);
+
+            let consumer = consumer.clone();
+            let offsets = offsets.clone();
+            let topic = self.0.topic.clone();
+            let ack: Ack = Box::pin(async move {
+                let Some(commit) = offsets.lock().unwrap().applied(partition, offset) else {
+                    return;
+                };
+                if let Err(e) = consumer.store_offset(&topic, partition, commit) {
+                    eprintln!(
+                        "Failed to store Kafka offset {}/{}: {}",
+                        partition, commit, e
+                    );
+                }
+            });
+            queue.submit(state, headers, body, Some(ack)).await;
+        }
+    }
+
+    fn is_login_rejected(&self, error: &anyhow::Error) -> bool {
+        matches!(
+            error
+                .downcast_ref::<KafkaError>()
+                .and_then(KafkaError::rdkafka_error_code),
+            Some(RDKafkaErrorCode::Authentication | RDKafkaErrorCode::SaslAuthenticationFailed)
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+    bytes: Option<String>,
+    text: Option<String>,
+}
+
+// The Pport XML inside a record value. Values that aren't JSON are passed on as they are.
+fn unwrap_envelope(payload: &[u8]) -> Result<Vec<u8>, serde_json::Error> {
+    if payload.trim_ascii_start().first() != Some(&b'{') {
+        return Ok(payload.to_vec());
+    }
+    let envelope: Envelope = serde_json::from_slice(payload)?;
+    Ok(envelope
+        .bytes
+        .or(envelope.text)
+        .unwrap_or_default()
+        .into_bytes())
+}
+
+// The Pport XML to queue, or None once a record whose envelope won't parse is dead-lettered.
+// The dead letter is written on a blocking thread, as the parser threads do for STOMP.
+async fn envelope_body(
+    payload: &[u8],
+    headers: &Headers,
+    state: &Arc<AppState>,
+) -> Option<Vec<u8>> {
+    match unwrap_envelope(payload) {
+        Ok(body) => Some(body),
+        Err(e) => {
+            let e = FrameError::Envelope(e.to_string());
+            eprintln!(
+                "Dropping Kafka record {}/{} ({}): {}",
+                headers.get("kafka-partition").unwrap_or_default(),
+                headers.get("kafka-offset").unwrap_or_default(),
+                e.kind(),
+                e
+            );
+            state
+                .frames
+                .received(crate::Encoding::sniff(payload).name());
+            state.frames.dropped(e.kind());
+            let (state, headers, payload) = (state.clone(), headers.clone(), payload.to_vec());
+            let stored = tokio::task::spawn_blocking(move || {
+                state.dead_letters.store(&headers, &payload, &e);
+            });
+            if let Err(e) = stored.await {
+                eprintln!("Failed to store dead letter: {}", e);
+            }
+            None
+        }
+    }
+}
+
+// Workers finish out of order, but a stored offset commits everything before it.
+// Only move a partition's offset up to just below its oldest message still in the queue.
+#[derive(Default)]
+struct OffsetTracker {
+    partitions: HashMap<i32, PartitionOffsets>,
+}
+
+#[derive(Default)]
+struct PartitionOffsets {
+    first_received: Option<i64>,
+    pending: BTreeSet<i64>,
+    highest_applied: Option<i64>,
+    stored: Option<i64>,
+}
+
+impl OffsetTracker {
+    fn received(&mut self, partition: i32, offset: i64) {
+        let p = self.partitions.entry(partition).or_default();
+        p.first_received.get_or_insert(offset);
+        p.pending.insert(offset);
+    }
+
+    // The offset to store now, if it has moved on
+    fn applied(&mut self, partition: i32, offset: i64) -> Option<i64> {
+        let p = self.partitions.get_mut(&partition)?;
+        p.pending.remove(&offset);
+        p.highest_applied = p.highest_applied.max(Some(offset));
+
+        let safe = match p.pending.first() {
+            Some(oldest) => oldest - 1,
+            None => p.highest_applied?,
+        };
+        // Nothing from before this session is ours to commit
+        if p.first_received.is_none_or(|first| safe < first)
+            || p.stored.is_some_and(|stored| stored >= safe)
+        {
+            return None;
+        }
+        p.stored = Some(safe);
+        Some(safe)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dead_letter::DeadLetters;
+
+    #[test]
+    fn envelope_yields_the_pport_xml() {
+        let value = br#"{"destination":{"name":"darwin.pushport-v16"},"messageID":"ID:1","properties":{"MessageType":{"string":"TS"}},"bytes":"<?xml version=\"1.0\"?><Pport ts=\"T\"/>","text":null}"#;
+        assert_eq!(
+            unwrap_envelope(value).unwrap(),
+            br#"<?xml version="1.0"?><Pport ts="T"/>"#
+        );
+        assert_eq!(
+            unwrap_envelope(br#" {"text":"<Pport/>"}"#).unwrap(),
+            b"<Pport/>"
+        );
+        assert_eq!(unwrap_envelope(b"<Pport/>").unwrap(), b"<Pport/>");
+        assert!(unwrap_envelope(b"{not json").is_err());
+    }
+
+    #[tokio::test]
+    async fn bad_envelope_is_dead_lettered_with_its_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = AppState::new(String::new());
+        state.dead_letters = DeadLetters::open(dir.path(), 10).await.unwrap();
+        let state = Arc::new(state);
+        let headers = Headers::new()
+            .with("kafka-topic", "darwin-push-port")
+            .with("kafka-partition", "0")
+            .with("kafka-offset", "42");
+
+        assert_eq!(
+            envelope_body(br#"{"bytes":"<Pport/>"}"#, &headers, &state)
+                .await
+                .unwrap(),
+            b"<Pport/>"
+        );
+        assert!(state.dead_letters.list().is_empty());
+
+        let payload = br#"{"bytes":"<Pport"#;
+        assert_eq!(envelope_body(payload, &headers, &state).await, None);
+        assert_eq!(state.frames.drops(), [("envelope", 1)].into());
+        let letters = state.dead_letters.list();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].error_kind, "envelope");
+        assert_eq!(letters[0].headers[2], ("kafka-offset".into(), "42".into()));
+        assert_eq!(
+            state.dead_letters.body(&letters[0].id).await.unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn offsets_only_advance_past_applied_messages() {
+        let mut tracker = OffsetTracker::default();
+        for offset in 10..=13 {
+            tracker.received(0, offset);
+        }
+        tracker.received(1, 0);
+
+        // 11 and 12 finish before 10
+        assert_eq!(tracker.applied(0, 11), None);
+        assert_eq!(tracker.applied(0, 12), None);
+        assert_eq!(tracker.applied(0, 10), Some(12));
+        assert_eq!(tracker.applied(0, 13), Some(13));
+        // Other partitions are independent
+        assert_eq!(tracker.applied(1, 0), Some(0));
+        assert_eq!(tracker.applied(2, 0), None);
+    }
+}
@@ -1 +1,11 @@
+// Push Port parsing, shared by the server and the benchmarks in benches/
+
+pub mod darwin_types;
+pub mod forecasts;
 pub mod formations;
+pub mod frame_error;
+pub mod parse;
+pub mod schedules;
+pub mod schema_version;
+#[doc(hidden)]
+pub mod test_fixtures;
@@ -1,46 +1,96 @@
-pub mod formations;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::Utc;
-use flate2::read::GzDecoder;
 use gtfs_realtime::{FeedHeader, FeedMessage};
 
 use prost::Message;
-use quick_xml::de::from_str;
-use std::collections::HashMap;
-use std::io::Read;
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
-use tokio::net::TcpStream;
 use warp::Filter;
 
-mod darwin_types;
+mod alerts;
+mod darwin_time;
+mod dead_letter;
 mod gc;
+mod kafka;
 mod persistence;
 mod processor;
+mod recorder;
+mod reference;
+mod replay;
+mod snapshot;
+mod source;
 mod state;
 mod static_data;
+mod stomp;
+mod work_queue;
 
-use darwin_types::Pport;
+use darwin_to_gtfs_realtime::parse::{Encoding, parse_frame_bytes, xml_reader};
+use darwin_to_gtfs_realtime::{darwin_types, formations, frame_error};
+use dead_letter::DeadLetters;
+use kafka::{KafkaConfig, KafkaSource};
 use persistence::{load_state, save_state};
-use processor::process_pmap;
+use recorder::Recorder;
+use source::StompSource;
 use state::AppState;
-
- use std::sync::LazyLock;
+use stomp::StompConfig;
+use work_queue::WorkQueue;
 
 // GTFS URL provided by Catenary
 const GTFS_URL: &str = "https://github.com/catenarytransit/pfaedled-gtfs-actions/releases/download/latest/nationalrailuk.zip";
 const DATA_DIR: &str = "./data";
 
-static NS_RE: LazyLock<regex::Regex> =
-    LazyLock::new(|| regex::Regex::new(r"ns\d+:").unwrap());
-
 #[tokio::main]
 async fn main() -> Result<()> {
+    // `replay <recording>...` rebuilds the feed from recorded traffic instead of serving it
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("replay") {
+        return replay::run(&args[1..]).await;
+    }
+
     // 1. Initialize State
     println!("Initializing Application State...");
-    let state = Arc::new(AppState::new(GTFS_URL.to_string()));
+    let mut state = AppState::new(GTFS_URL.to_string());
+
+    // Keep messages that fail to parse (DARWIN_DEAD_LETTER_DIR, the newest DARWIN_DEAD_LETTER_MAX)
+    let dead_letter_dir = std::env::var("DARWIN_DEAD_LETTER_DIR")
+        .unwrap_or_else(|_| format!("{}/dead-letters", DATA_DIR));
+    let dead_letter_max: usize = std::env::var("DARWIN_DEAD_LETTER_MAX")
+        .unwrap_or_else(|_| "500".to_string())
+        .parse()
+        .expect("Invalid DARWIN_DEAD_LETTER_MAX");
+    match DeadLetters::open(&dead_letter_dir, dead_letter_max).await {
+        Ok(dead_letters) => state.dead_letters = dead_letters,
+        Err(e) => eprintln!("Warning: Not keeping dead letters: {:#}", e),
+    }
+
+    // Record raw traffic for replay (DARWIN_RECORD_DIR, a new file every
+    // DARWIN_RECORD_ROTATE_MINUTES, keeping the newest DARWIN_RECORD_KEEP_FILES)
+    if let Ok(record_dir) = std::env::var("DARWIN_RECORD_DIR") {
+        let rotate_minutes: u64 = std::env::var("DARWIN_RECORD_ROTATE_MINUTES")
+            .unwrap_or_else(|_| "60".to_string())
+            .parse()
+            .expect("Invalid DARWIN_RECORD_ROTATE_MINUTES");
+        // A week of hourly files; 0 keeps everything
+        let keep_files: usize = std::env::var("DARWIN_RECORD_KEEP_FILES")
+            .unwrap_or_else(|_| "168".to_string())
+            .parse()
+            .expect("Invalid DARWIN_RECORD_KEEP_FILES");
+        match Recorder::start(
+            &record_dir,
+            Duration::from_secs(rotate_minutes * 60),
+            keep_files,
+        ) {
+            Ok(recorder) => {
+                println!("Recording Darwin traffic to {}", record_dir);
+                state.recorder = recorder;
+            }
+            Err(e) => eprintln!("Warning: Not recording Darwin traffic: {:#}", e),
+        }
+    }
+    let state = Arc::new(state);
 
     // 2. Load Persistence (Recovery)
     if let Err(e) = load_state(&state, DATA_DIR) {
@@ -56,6 +106,38 @@ async fn main() -> Result<()> {
     }
     state.gtfs.start_updater();
 
+    // Darwin reference data (DARWIN_REFERENCE, path or URL) for reason texts and CRS codes
+    if let Ok(location) = std::env::var("DARWIN_REFERENCE")
+        && let Err(e) = reference::load(&location, &state).await
+    {
+        eprintln!("Warning: Darwin reference data load failed: {:#}", e);
+    }
+
+    // Darwin's daily timetable (DARWIN_TIMETABLE, path or URL), reloaded once a day, for
+    // services the static GTFS doesn't know
+    if let Ok(location) = std::env::var("DARWIN_TIMETABLE") {
+        if let Err(e) = load_timetable(&location, &state).await {
+            eprintln!("Warning: Darwin timetable load failed: {:#}", e);
+        }
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(24 * 3600)).await;
+                if let Err(e) = load_timetable(&location, &state).await {
+                    eprintln!("Failed to update Darwin timetable: {:#}", e);
+                }
+            }
+        });
+    }
+
+    // Bootstrap from a Darwin snapshot (DARWIN_SNAPSHOT, path or URL) so a cold start
+    // reflects current state rather than whatever was last persisted
+    if let Ok(location) = std::env::var("DARWIN_SNAPSHOT")
+        && let Err(e) = snapshot::bootstrap(&location, state.clone()).await
+    {
+        eprintln!("Warning: Darwin snapshot bootstrap failed: {:#}", e);
+    }
+
     // 4. Persistence Loop
     let state_clone_persist = state.clone();
     tokio::spawn(async move {
@@ -83,18 +165,29 @@ async fn main() -> Result<()> {
     let state_filter_base = state.clone();
     let state_filter = warp::any().map(move || state_filter_base.clone()).boxed();
 
+    // Alerts are served on /alerts; set GTFS_RT_INCLUDE_ALERTS=true to also merge them into /gtfs-rt
+    let include_alerts = std::env::var("GTFS_RT_INCLUDE_ALERTS")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false);
+
     // GET /gtfs-rt
     let gtfs_rt_route = warp::path("gtfs-rt")
         .and(warp::get())
         .and(state_filter.clone())
-        .map(|state: Arc<AppState>| {
-            let mut msg = FeedMessage::default();
-            let mut header = FeedHeader::default();
-            header.gtfs_realtime_version = "2.0".to_string();
-            header.timestamp = Some(Utc::now().timestamp() as u64);
-            msg.header = header;
+        .map(move |state: Arc<AppState>| {
+            let msg = gtfs_rt_feed(&state, include_alerts);
+            let mut buf = Vec::new();
+            msg.encode(&mut buf).unwrap();
+            warp::reply::with_header(buf, "content-type", "application/x-protobuf")
+        });
 
-            for r in state.trip_updates.iter() {
+    // GET /alerts
+    let alerts_route = warp::path("alerts")
+        .and(warp::get())
+        .and(state_filter.clone())
+        .map(|state: Arc<AppState>| {
+            let mut msg = new_feed_message();
+            for r in state.alerts.iter() {
                 msg.entity.push(r.value().clone());
             }
 
@@ -117,6 +210,18 @@ async fn main() -> Result<()> {
             warp::reply::json(&data)
         });
 
+    // GET /unknown-delays
+    let unknown_delays_route = warp::path("unknown-delays")
+        .and(warp::get())
+        .and(state_filter.clone())
+        .map(|state: Arc<AppState>| {
+            let mut data = std::collections::HashMap::new();
+            for r in state.unknown_delays.iter() {
+                data.insert(r.key().clone(), r.value().clone());
+            }
+            warp::reply::json(&data)
+        });
+
     // GET /formations
     let formations_route = warp::path("formations")
         .and(warp::get())
@@ -155,12 +260,77 @@ async fn main() -> Result<()> {
             warp::reply::json(&data)
         });
 
+    // GET /associations
+    let associations_route = warp::path("associations")
+        .and(warp::get())
+        .and(state_filter.clone())
+        .map(|state: Arc<AppState>| {
+            let mut data = std::collections::HashMap::new();
+            for r in state.associations.iter() {
+                data.insert(r.key().clone(), r.value().clone());
+            }
+            warp::reply::json(&data)
+        });
+
+    // GET /reference (Darwin reference data, when loaded)
+    let reference_route = warp::path("reference")
+        .and(warp::get())
+        .and(state_filter.clone())
+        .map(|state: Arc<AppState>| warp::reply::json(&*state.reference.read().unwrap()));
+
+    // GET /dead-letters (messages that failed to parse, oldest first)
+    let dead_letters_route = warp::path("dead-letters")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(state_filter.clone())
+        .map(|state: Arc<AppState>| warp::reply::json(&state.dead_letters.list()));
+
+    // GET /dead-letters/{id} (the stored STOMP frame) and /dead-letters/{id}/body
+    let dead_letter_frame_route = warp::path!("dead-letters" / String)
+        .and(warp::get())
+        .and(state_filter.clone())
+        .and_then(|id: String, state: Arc<AppState>| async move {
+            let frame = state.dead_letters.frame(&id).await;
+            dead_letter_reply(frame, format!("{}.stomp", id))
+        });
+    let dead_letter_body_route = warp::path!("dead-letters" / String / "body")
+        .and(warp::get())
+        .and(state_filter.clone())
+        .and_then(|id: String, state: Arc<AppState>| async move {
+            let body = state.dead_letters.body(&id).await;
+            dead_letter_reply(body, format!("{}.body", id))
+        });
+
+    // GET /status
+    let status_route = warp::path("status")
+        .and(warp::get())
+        .and(state_filter.clone())
+        .map(|state: Arc<AppState>| {
+            let last = state.last_message_at.load(Ordering::Relaxed);
+            let last_message_at = (last > 0).then_some(last);
+            warp::reply::json(&FeedStatus {
+                last_message_at,
+                seconds_since_last_message: last_message_at.map(|t| Utc::now().timestamp() - t),
+                queue_depth: state.queue_depth.load(Ordering::Relaxed),
+                encodings: state.frames.encodings(),
+                dropped: state.frames.drops(),
+            })
+        });
+
     let routes = gtfs_rt_route
         // .or(platforms_route) REMOVED
         .or(platforms_v2_route)
+        .or(unknown_delays_route)
         .or(formations_route)
         .or(formations_v1_route)
         .or(rid_to_trip_id_route)
+        .or(alerts_route)
+        .or(associations_route)
+        .or(reference_route)
+        .or(status_route)
+        .or(dead_letters_route)
+        .or(dead_letter_frame_route)
+        .or(dead_letter_body_route)
         .boxed();
 
     let server_port: u16 = std::env::var("PORT")
@@ -170,190 +340,95 @@ async fn main() -> Result<()> {
     tokio::spawn(warp::serve(routes).run(([0, 0, 0, 0], server_port)));
     println!("Server running at http://localhost:{}", server_port);
 
-    // 6. Connect to Darwin Push Port (Manual STOMP Implementation)
-    let username = std::env::var("DARWIN_USER").expect("DARWIN_USER not set");
-    let password = std::env::var("DARWIN_PASS").expect("DARWIN_PASS not set");
-    let host = std::env::var("DARWIN_HOST")
-        .unwrap_or_else(|_| "darwin-dist-44ae45.nationalrail.co.uk".to_string());
-    let port_str = std::env::var("DARWIN_PORT").unwrap_or_else(|_| "61613".to_string());
-    let port: u16 = port_str.parse().expect("Invalid DARWIN_PORT");
-    let destination = "/topic/darwin.pushport-v16";
-    let state_clone_stomp = state.clone();
-
-    tokio::spawn(async move {
-        loop {
-            println!("Connecting to Darwin STOMP at {}:{}...", host, port);
-            match connect_and_listen(
-                &host,
-                port,
-                &username,
-                &password,
-                &destination,
-                &state_clone_stomp,
-            )
-            .await
-            {
-                Ok(_) => eprintln!("STOMP connection closed unexpectedly."),
-                Err(e) => eprintln!("STOMP error: {}", e),
-            }
-            tokio::time::sleep(Duration::from_secs(10)).await;
+    // 6. Connect to Darwin: Push Port over STOMP, or the Kafka topic (DARWIN_SOURCE=kafka)
+    let queue_capacity: usize = std::env::var("DARWIN_QUEUE_CAPACITY")
+        .unwrap_or_else(|_| "1024".to_string())
+        .parse()
+        .expect("Invalid DARWIN_QUEUE_CAPACITY");
+    let workers: usize = std::env::var("DARWIN_WORKERS")
+        .ok()
+        .map(|w| w.parse().expect("Invalid DARWIN_WORKERS"))
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4)
+        });
+    // Outlives each connection so nothing already received is lost or reordered on reconnect
+    let work_queue = WorkQueue::start(state.clone(), queue_capacity, workers);
+
+    let source_kind = std::env::var("DARWIN_SOURCE").unwrap_or_else(|_| "stomp".to_string());
+    match source_kind.as_str() {
+        "stomp" => {
+            let source = StompSource(StompConfig::from_env());
+            tokio::spawn(source::run(source, state.clone(), work_queue));
         }
-    });
+        "kafka" => {
+            let source = KafkaSource(KafkaConfig::from_env());
+            tokio::spawn(source::run(source, state.clone(), work_queue));
+        }
+        other => panic!("Unknown DARWIN_SOURCE {} (expected stomp or kafka)", other),
+    }
 
     loop {
         tokio::time::sleep(Duration::from_secs(60)).await;
     }
 }
 
-async fn connect_and_listen(
-    host: &str,
-    port: u16,
-    user: &str,
-    pass: &str,
-    dest: &str,
-    state: &AppState,
-) -> Result<()> {
-    let mut stream = TcpStream::connect((host, port)).await?;
-    let (reader, mut writer) = stream.split();
-    let mut reader = BufReader::new(reader);
-
-    // 1. Send CONNECT
-    let connect_frame = format!(
-        "CONNECT\naccept-version:1.2\nlogin:{}\npasscode:{}\n\n\0",
-        user, pass
-    );
-    writer.write_all(connect_frame.as_bytes()).await?;
-
-    // 2. Read CONNECTED
-    let _ = read_frame(&mut reader).await?;
-    println!("Logged in to Darwin.");
-
-    // 3. Send SUBSCRIBE
-    let subscribe_frame = format!(
-        "SUBSCRIBE\nid:0\ndestination:{}\nack:client-individual\n\n\0",
-        dest
-    );
-    writer.write_all(subscribe_frame.as_bytes()).await?;
-
-    // 4. Listen Loop
-    loop {
-        let (headers, frame_body) = read_frame(&mut reader).await?;
-
-        // Process body
-        if let Err(e) = process_frame_bytes(&frame_body, state) {
-            eprintln!(
-                "Error processing frame: {} Body: {}",
-                e,
-                String::from_utf8_lossy(&frame_body)
-            );
-        }
+// Lets monitoring alert when the Darwin feed goes quiet
+#[derive(serde::Serialize)]
+struct FeedStatus {
+    last_message_at: Option<i64>,
+    seconds_since_last_message: Option<i64>,
+    // Messages received but not yet applied; growth means processing can't keep up
+    queue_depth: usize,
+    // Frames received by body encoding (gzip, xml, empty, unknown)
+    encodings: BTreeMap<&'static str, u64>,
+    // Frames not applied, by reason
+    dropped: BTreeMap<&'static str, u64>,
+}
 
-        // 5. Send ACK
-        // Darwin sends 'ack' header in MESSAGE frame which we must echo back as 'id' in ACK frame?
-        // Or standard STOMP says we use 'ack' header from message.
-        // Darwin usually provides 'ack' header in the MESSAGE.
-        if let Some(ack_id) = headers.get("ack") {
-            let ack_frame = format!("ACK\nid:{}\n\n\0", ack_id);
-            writer.write_all(ack_frame.as_bytes()).await?;
-            // println!("Sent ACK for {}", ack_id);
-        } else if let Some(msg_id) = headers.get("message-id") {
-            // Some STOMP versions use message-id
-            let ack_frame = format!("ACK\nid:{}\n\n\0", msg_id);
-            writer.write_all(ack_frame.as_bytes()).await?;
-        }
-    }
+fn dead_letter_reply(
+    bytes: Option<Vec<u8>>,
+    filename: String,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let bytes = bytes.ok_or_else(warp::reject::not_found)?;
+    let reply = warp::reply::with_header(bytes, "content-type", "application/octet-stream");
+    Ok(warp::reply::with_header(
+        reply,
+        "content-disposition",
+        format!("attachment; filename=\"{}\"", filename),
+    ))
 }
 
-// Simple frame reader that returns headers and body
-async fn read_frame(
-    reader: &mut BufReader<tokio::net::tcp::ReadHalf<'_>>,
-) -> Result<(HashMap<String, String>, Vec<u8>)> {
-    // 1. Read Command
-    let mut command = String::new();
-    loop {
-        command.clear();
-        let bytes = reader.read_line(&mut command).await?;
-        if bytes == 0 {
-            return Err(anyhow::anyhow!("EOF"));
-        }
-        if command.trim().is_empty() {
-            continue;
-        }
-        break;
+// Trip updates, and alerts too if asked for
+fn gtfs_rt_feed(state: &AppState, include_alerts: bool) -> FeedMessage {
+    let mut msg = new_feed_message();
+    for r in state.trip_updates.iter() {
+        msg.entity.push(r.value().clone());
     }
-
-    // 2. Read Headers
-    let mut headers = HashMap::new();
-    let mut content_length = 0;
-    loop {
-        let mut header_line = String::new();
-        reader.read_line(&mut header_line).await?;
-        let trimmed = header_line.trim();
-        if trimmed.is_empty() {
-            break;
-        } // End of headers
-
-        if let Some((k, v)) = trimmed.split_once(':') {
-            let key = k.trim().to_lowercase();
-            let val = v.trim().to_string();
-            if key == "content-length" {
-                if let Ok(len) = val.parse::<usize>() {
-                    content_length = len;
-                }
-            }
-            headers.insert(key, val);
+    if include_alerts {
+        for r in state.alerts.iter() {
+            msg.entity.push(r.value().clone());
         }
     }
-
-    // 3. Read Body
-    let mut body = vec![0u8; content_length];
-    reader.read_exact(&mut body).await?;
-
-    // 4. Read NULL
-    let mut null_byte = [0u8; 1];
-    reader.read_exact(&mut null_byte).await?;
-    if null_byte[0] != 0 {
-        // Warning: Missing null byte or sync error
-    }
-
-    Ok((headers, body))
+    msg
 }
 
-fn process_frame_bytes(body: &[u8], state: &AppState) -> Result<()> {
-    if body.is_empty() {
-        return Ok(());
-    }
-    // GZip decode
-    let mut d = GzDecoder::new(body);
-    let mut xml_string = String::new();
-    if let Err(_) = d.read_to_string(&mut xml_string) {
-        // Maybe not gzipped? Or empty?
-        return Ok(());
+fn new_feed_message() -> FeedMessage {
+    FeedMessage {
+        header: FeedHeader {
+            gtfs_realtime_version: "2.0".to_string(),
+            timestamp: Some(Utc::now().timestamp() as u64),
+            ..Default::default()
+        },
+        ..Default::default()
     }
-    // Strip XML namespaces (e.g., ns5:Location -> Location) to satisfy Serde
-    let clean_xml = NS_RE.replace_all(&xml_string, "");
-
-    let pport: Pport = match from_str(&clean_xml) {
-        Ok(p) => p,
-        Err(e) => {
-            eprintln!("XML Parsing Error: {}", e);
-            eprintln!("Full XML: {}", xml_string); // Log original for debug
-            return Err(e.into());
-        }
-    };
-    process_pmap(pport, state);
-    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_ns_re_compiles_and_works() {
-        let input = r#"<ns5:Location wta="08:24"><ns5:Platform>1</ns5:Platform></ns5:Location>"#;
-        let expected = r#"<Location wta="08:24"><Platform>1</Platform></Location>"#;
-        let clean = NS_RE.replace_all(input, "");
-        assert_eq!(clean, expected);
-    }
+async fn load_timetable(location: &str, state: &Arc<AppState>) -> Result<()> {
+    let body = snapshot::fetch(location)
+        .await
+        .with_context(|| format!("Failed to fetch timetable {}", location))?;
+    let state = state.clone();
+    tokio::task::spawn_blocking(move || state.gtfs.load_timetable(&body)).await?
 }
@@ -0,0 +1,206 @@
+// Turning a Darwin frame body (gzipped or plain Push Port XML) into a Pport
+
+use crate::darwin_types::Pport;
+use crate::frame_error::FrameError;
+use crate::schema_version::SchemaVersion;
+use flate2::bufread::MultiGzDecoder;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use std::io::{BufRead, BufReader, Read};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+// How a frame's body is encoded, judged from its first bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Empty,
+    // One or more gzip members, inflated as one stream
+    Gzip,
+    // Plain XML: some brokers and replay files skip the compression
+    Xml,
+    Unknown,
+}
+
+impl Encoding {
+    pub fn sniff(body: &[u8]) -> Self {
+        if body.is_empty() {
+            Encoding::Empty
+        } else if body.starts_with(&GZIP_MAGIC) {
+            Encoding::Gzip
+        } else if body
+            .strip_prefix(UTF8_BOM)
+            .unwrap_or(body)
+            .trim_ascii_start()
+            .starts_with(b"<")
+        {
+            Encoding::Xml
+        } else {
+            Encoding::Unknown
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Empty => "empty",
+            Encoding::Gzip => "gzip",
+            Encoding::Xml => "xml",
+            Encoding::Unknown => "unknown",
+        }
+    }
+}
+
+// Darwin files and messages are gzipped XML, except on the Kafka feed
+pub fn xml_reader(body: &[u8]) -> Box<dyn BufRead + '_> {
+    match Encoding::sniff(body) {
+        Encoding::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(body))),
+        _ => Box::new(body.strip_prefix(UTF8_BOM).unwrap_or(body)),
+    }
+}
+
+// Deserialise a Push Port message. Elements and attributes are matched on their local
+// names, so whatever namespace prefixes Darwin binds (ns5:Location, fc:Location) make no
+// difference. Gzipped bodies are inflated into one buffer, sized up front, and parsed in
+// place like plain XML: deserialising off the gzip stream copies every XML event, which
+// costs more allocations than the inflated copy saves (benches/parse.rs).
+pub fn parse_frame_bytes(body: &[u8]) -> Result<Pport, FrameError> {
+    match Encoding::sniff(body) {
+        Encoding::Empty => Err(FrameError::Empty),
+        Encoding::Unknown => Err(FrameError::UnknownEncoding),
+        Encoding::Xml => parse_xml(body),
+        Encoding::Gzip => {
+            let mut xml = Vec::with_capacity(inflated_size_hint(body));
+            MultiGzDecoder::new(body)
+                .read_to_end(&mut xml)
+                .map_err(FrameError::Gzip)?;
+            parse_xml(&xml)
+        }
+    }
+}
+
+// The gzip trailer's ISIZE is the last member's inflated length, which for Darwin's
+// single-member bodies is the whole message. Deflate can't expand by more than 1032:1,
+// so a corrupt trailer can't ask for more than that.
+fn inflated_size_hint(body: &[u8]) -> usize {
+    let inflated = body
+        .last_chunk::<4>()
+        .map_or(0, |trailer| u32::from_le_bytes(*trailer) as usize);
+    inflated.min(body.len().saturating_mul(1032))
+}
+
+fn parse_xml(xml: &[u8]) -> Result<Pport, FrameError> {
+    let xml = std::str::from_utf8(xml)?;
+    let xml = xml.strip_prefix('\u{feff}').unwrap_or(xml);
+    Ok(root_version(xml)?.deserialise(xml)?)
+}
+
+// Pport@version, read off the root element so the rest can be deserialised in that
+// version's shapes
+fn root_version(xml: &str) -> Result<SchemaVersion, FrameError> {
+    let syntax = |e: quick_xml::Error| FrameError::XmlSyntax(e.to_string());
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event().map_err(syntax)? {
+            Event::Start(root) | Event::Empty(root) => {
+                let version = root
+                    .try_get_attribute("version")
+                    .map_err(|e| syntax(e.into()))?
+                    .map(|version| version.unescape_value())
+                    .transpose()
+                    .map_err(syntax)?;
+                return Ok(SchemaVersion::parse(version.as_deref())?);
+            }
+            Event::Eof => return Err(FrameError::XmlSyntax("No root element".to_string())),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::darwin_types::MessagePart;
+    use crate::test_fixtures::{gzip, train_status_message};
+
+    #[test]
+    fn namespace_prefixes_are_resolved_not_stripped() {
+        for prefix in ["ns5", "fc", "ns12"] {
+            let body = gzip(train_status_message(prefix, 3));
+            let pport = parse_frame_bytes(&body).unwrap();
+            let ts = &pport.update_record.unwrap().train_status[0];
+            assert_eq!(ts.locations.len(), 3, "prefix {}", prefix);
+            assert_eq!(ts.locations[2].tiploc.as_deref(), Some("TPL002"));
+            assert_eq!(
+                ts.late_reason.as_ref().unwrap().code.as_deref(),
+                Some("100")
+            );
+        }
+
+        // Text that looks like a prefix is left alone
+        let message = r#"<Pport xmlns="http://www.thalesgroup.com/rtti/PushPort/v16" xmlns:ns7="http://www.thalesgroup.com/rtti/PushPort/StationMessages/v1" ts="T" version="16.0"><uR updateOrigin="CIS"><OW id="7" cat="Misc" sev="0"><ns7:Msg>Use ns1: entrance</ns7:Msg></OW></uR></Pport>"#;
+        let pport = parse_frame_bytes(message.as_bytes()).unwrap();
+        let ow = &pport.update_record.unwrap().station_message[0];
+        assert!(matches!(
+            &ow.message.parts[..],
+            [MessagePart::Text(text)] if text == "Use ns1: entrance"
+        ));
+    }
+
+    #[test]
+    fn plain_and_multi_member_gzip_bodies_are_parsed() {
+        let xml = train_status_message("ns5", 3);
+        let (head, tail) = xml.split_at(xml.len() / 2);
+        let mut members = gzip(head);
+        members.extend(gzip(tail));
+        let plain = format!("\u{feff}\r\n  {}", xml);
+
+        for (body, encoding) in [
+            (gzip(&xml), Encoding::Gzip),
+            (members, Encoding::Gzip),
+            (xml.clone().into_bytes(), Encoding::Xml),
+            (plain.into_bytes(), Encoding::Xml),
+        ] {
+            assert_eq!(Encoding::sniff(&body), encoding);
+            let pport = parse_frame_bytes(&body).unwrap();
+            let locations = &pport.update_record.unwrap().train_status[0].locations;
+            assert_eq!(locations.len(), 3);
+        }
+
+        assert_eq!(Encoding::sniff(b""), Encoding::Empty);
+        assert_eq!(parse_frame_bytes(b"").unwrap_err().kind(), "empty");
+        assert_eq!(Encoding::sniff(b"PK\x03\x04"), Encoding::Unknown);
+        assert_eq!(
+            parse_frame_bytes(b"PK\x03\x04").unwrap_err().kind(),
+            "unknown_encoding"
+        );
+    }
+
+    #[test]
+    fn parse_failures_are_classified() {
+        let kind = |body: &[u8]| parse_frame_bytes(body).unwrap_err().kind();
+        let schedule = |location: &str| {
+            format!(
+                r#"<Pport ts="T" version="16.0"><uR updateOrigin="CIS"><schedule rid="202601157654321" uid="V54321" trainId="1P21" ssd="2026-01-15" toc="GW"><OR tpl="PADTON" wtd="10:00"/>{}</schedule></uR></Pport>"#,
+                location
+            )
+        };
+
+        let mut truncated = gzip(schedule(r#"<DT tpl="OXFD" wta="10:55"/>"#));
+        truncated.truncate(truncated.len() / 2);
+        assert_eq!(kind(&truncated), "gzip");
+        assert_eq!(kind(b"<Pport ts=\"\xff\"/>"), "utf8");
+        assert_eq!(kind(&gzip(b"<Pport ts=\"\xff\"/>")), "utf8");
+        assert_eq!(kind(b"<Pport><uR><schedule></uR></Pport>"), "xml_syntax");
+        // A schedule without its RID
+        assert_eq!(
+            kind(br#"<Pport><uR><schedule uid="V54321"/></uR></Pport>"#),
+            "schema_mismatch"
+        );
+        assert_eq!(kind(br#"<Pport version="10.0"/>"#), "schema_mismatch");
+        // A schedule location type this model doesn't know
+        assert_eq!(
+            kind(schedule(r#"<XX tpl="OXFD" wta="10:55"/>"#).as_bytes()),
+            "unknown_element"
+        );
+    }
+}
@@ -14,10 +14,14 @@ pub fn save_state(state: &AppState, dir: &str) -> Result<()> {
 
     // 1. Save Trips (Protobuf)
     let trips_path = format!("{}/trips.pb", dir);
-    let mut msg = FeedMessage::default();
-    msg.header = FeedHeader::default();
-    msg.header.gtfs_realtime_version = "2.0".to_string();
-    msg.header.timestamp = Some(chrono::Utc::now().timestamp() as u64);
+    let mut msg = FeedMessage {
+        header: FeedHeader {
+            gtfs_realtime_version: "2.0".to_string(),
+            timestamp: Some(chrono::Utc::now().timestamp() as u64),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
 
     for r in state.trip_updates.iter() {
         msg.entity.push(r.value().clone());
@@ -38,6 +42,14 @@ pub fn save_state(state: &AppState, dir: &str) -> Result<()> {
     let f = File::create(platforms_v2_path)?;
     bincode::serialize_into(f, &v2_map)?;
 
+    let unknown_delays_path = format!("{}/unknown_delays.bin", dir);
+    let mut unknown_delays_map = std::collections::HashMap::new();
+    for r in state.unknown_delays.iter() {
+        unknown_delays_map.insert(r.key().clone(), r.value().clone());
+    }
+    let f = File::create(unknown_delays_path)?;
+    bincode::serialize_into(f, &unknown_delays_map)?;
+
     // 3. Save Formations V2 (Bincode)
     let formations_path = format!("{}/formations.bin", dir);
     let mut formations_map = std::collections::HashMap::new();
@@ -59,6 +71,63 @@ pub fn save_state(state: &AppState, dir: &str) -> Result<()> {
     let f_v1 = File::create(formations_v1_path)?;
     bincode::serialize_into(f_v1, &formations_v1_map)?;
 
+    // 5. Save Schedules (Bincode)
+    let schedules_path = format!("{}/schedules.bin", dir);
+    let mut schedules_map = std::collections::HashMap::new();
+    for r in state.schedules.iter() {
+        schedules_map.insert(r.key().clone(), r.value().clone());
+    }
+    let f = File::create(schedules_path)?;
+    bincode::serialize_into(f, &schedules_map)?;
+
+    // 6. Save Loadings (Bincode)
+    let loadings_path = format!("{}/loadings.bin", dir);
+    let mut loadings_map = std::collections::HashMap::new();
+    for r in state.loadings.iter() {
+        loadings_map.insert(r.key().clone(), r.value().clone());
+    }
+    let f = File::create(loadings_path)?;
+    bincode::serialize_into(f, &loadings_map)?;
+
+    // 7. Save Associations (Bincode)
+    let associations_path = format!("{}/associations.bin", dir);
+    let mut associations_map = std::collections::HashMap::new();
+    for r in state.associations.iter() {
+        associations_map.insert(r.key().clone(), r.value().clone());
+    }
+    let f = File::create(associations_path)?;
+    bincode::serialize_into(f, &associations_map)?;
+
+    // 8. Save RIDs with forecasts of their own (Bincode)
+    let forecast_rids_path = format!("{}/forecast_rids.bin", dir);
+    let forecast_rids: Vec<CompactString> = state
+        .forecast_rids
+        .iter()
+        .map(|r| r.key().clone())
+        .collect();
+    let f = File::create(forecast_rids_path)?;
+    bincode::serialize_into(f, &forecast_rids)?;
+
+    // 9. Save Alerts (Protobuf)
+    let alerts_path = format!("{}/alerts.pb", dir);
+    let mut msg = FeedMessage {
+        header: FeedHeader {
+            gtfs_realtime_version: "2.0".to_string(),
+            timestamp: Some(chrono::Utc::now().timestamp() as u64),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    for r in state.alerts.iter() {
+        msg.entity.push(r.value().clone());
+    }
+
+    let mut buf = Vec::new();
+    msg.encode(&mut buf)?;
+    let mut f = File::create(alerts_path)?;
+    f.write_all(&buf)?;
+
     Ok(())
 }
 
@@ -97,6 +166,23 @@ pub fn load_state(state: &AppState, dir: &str) -> Result<()> {
         );
     }
 
+    let unknown_delays_path = format!("{}/unknown_delays.bin", dir);
+    if Path::new(&unknown_delays_path).exists() {
+        let f = File::open(unknown_delays_path)?;
+        let unknown_delays_map: std::collections::HashMap<
+            CompactString,
+            Vec<crate::state::UnknownDelayInfo>,
+        > = bincode::deserialize_from(f)?;
+
+        for (trip_id, delays) in unknown_delays_map {
+            state.unknown_delays.insert(trip_id, delays);
+        }
+        println!(
+            "Loaded unknown delays for {} trips.",
+            state.unknown_delays.len()
+        );
+    }
+
     // 3. Load Formations (Bincode)
     let formations_path = format!("{}/formations.bin", dir);
     if Path::new(&formations_path).exists() {
@@ -112,5 +198,75 @@ pub fn load_state(state: &AppState, dir: &str) -> Result<()> {
         println!("Loaded {} formations.", state.formations.len());
     }
 
+    // 4. Load Schedules (Bincode)
+    let schedules_path = format!("{}/schedules.bin", dir);
+    if Path::new(&schedules_path).exists() {
+        let f = File::open(schedules_path)?;
+        let schedules_map: std::collections::HashMap<CompactString, crate::darwin_types::Schedule> =
+            bincode::deserialize_from(f)?;
+
+        for (rid, schedule) in schedules_map {
+            state.schedules.insert(rid, schedule);
+        }
+        println!("Loaded {} schedules.", state.schedules.len());
+    }
+
+    // 5. Load Loadings (Bincode)
+    let loadings_path = format!("{}/loadings.bin", dir);
+    if Path::new(&loadings_path).exists() {
+        let f = File::open(loadings_path)?;
+        let loadings_map: std::collections::HashMap<CompactString, crate::darwin_types::Loading> =
+            bincode::deserialize_from(f)?;
+
+        for (rid, loading) in loadings_map {
+            state.loadings.insert(rid, loading);
+        }
+        println!("Loaded {} loadings.", state.loadings.len());
+    }
+
+    // 6. Load Associations (Bincode)
+    let associations_path = format!("{}/associations.bin", dir);
+    if Path::new(&associations_path).exists() {
+        let f = File::open(associations_path)?;
+        let associations_map: std::collections::HashMap<
+            CompactString,
+            Vec<crate::darwin_types::Association>,
+        > = bincode::deserialize_from(f)?;
+
+        for (rid, associations) in associations_map {
+            state.associations.insert(rid, associations);
+        }
+        println!("Loaded associations for {} RIDs.", state.associations.len());
+    }
+
+    // 7. Load RIDs with forecasts of their own (Bincode)
+    let forecast_rids_path = format!("{}/forecast_rids.bin", dir);
+    if Path::new(&forecast_rids_path).exists() {
+        let f = File::open(forecast_rids_path)?;
+        let forecast_rids: Vec<CompactString> = bincode::deserialize_from(f)?;
+
+        for rid in forecast_rids {
+            state.forecast_rids.insert(rid);
+        }
+        println!("Loaded {} RIDs with forecasts.", state.forecast_rids.len());
+    }
+
+    // 8. Load Alerts (Protobuf)
+    let alerts_path = format!("{}/alerts.pb", dir);
+    if Path::new(&alerts_path).exists() {
+        let mut f = File::open(alerts_path)?;
+        let mut buf = Vec::new();
+        f.read_to_end(&mut buf)?;
+
+        if let Ok(msg) = FeedMessage::decode(&buf[..]) {
+            for entity in msg.entity {
+                state
+                    .alerts
+                    .insert(CompactString::from(entity.id.clone()), entity);
+            }
+            println!("Loaded {} alerts from disk.", state.alerts.len());
+        }
+    }
+
     Ok(())
 }
@@ -1,91 +1,431 @@
-use crate::darwin_types::{Loading, Location, Pport, StationMessage, TrainOrder, TrainStatus};
+use crate::alerts;
+use crate::darwin_time::{DarwinClock, london_timestamp};
+use crate::darwin_types::{
+    AlertAudience, AlertService, Association, DisruptionReason, Loading, Location, Pport, Schedule,
+    SchedulePoint, StationMessage, TrainAlert, TrainOrder, TrainStatus, UpdateRecord,
+};
+use crate::reference::ReasonKind;
 use crate::state::AppState;
 use compact_str::CompactString;
 // use anyhow::Result;
 
 use chrono::{Duration, NaiveDate, TimeZone, Utc};
 use chrono_tz::Europe::London;
+use dashmap::mapref::one::RefMut;
 
 use gtfs_realtime::{
     FeedEntity, TripUpdate, VehiclePosition,
-    trip_update::{StopTimeEvent, StopTimeUpdate},
+    alert::Effect,
+    trip_descriptor::ScheduleRelationship as TripScheduleRelationship,
+    trip_update::{
+        StopTimeEvent, StopTimeUpdate,
+        stop_time_update::ScheduleRelationship as StopScheduleRelationship,
+    },
+    vehicle_position::{CarriageDetails, OccupancyStatus},
 };
 
 use std::collections::HashMap;
 
 pub fn process_pmap(pport: Pport, state: &AppState) {
     if let Some(ur) = pport.update_record {
-        for ts in ur.train_status {
-            update_trip(&ts, state);
-        }
-        for to in ur.train_order {
-            update_trip_from_order(&to, state);
-        }
-        for msg in ur.station_message {
-            process_station_message(&msg, state);
-        }
-        for load in ur.loading.iter().chain(ur.loading_alias.iter()) {
-            process_loading(load, state);
-        }
-        for schedule_formation in ur.schedule_formations {
-            process_formation(&schedule_formation, state);
-        }
+        process_data_response(ur, state);
+    }
+    // A snapshot carries the same records as an update, for every service Darwin knows
+    if let Some(sr) = pport.snapshot_record {
+        process_data_response(sr, state);
     }
-    // We currently ignore schedule_record (sR) as we rely on static GTFS for basic schedule
-    // and uR for updates.
 }
 
-fn process_formation(
-    schedule_formation: &crate::formations::v2::ScheduleFormations,
-    state: &AppState,
-) {
+fn process_data_response(record: UpdateRecord, state: &AppState) {
+    // Schedules first so cancellations are in place before forecasts land
+    for schedule in record.schedule {
+        process_schedule(&schedule, state);
+    }
+    for ts in record.train_status {
+        update_trip(&ts, state);
+    }
+    for to in record.train_order {
+        update_trip_from_order(&to, state);
+    }
+    for msg in record.station_message {
+        process_station_message(&msg, state);
+    }
+    for alert in record.train_alert {
+        process_train_alert(&alert, state);
+    }
+    for load in &record.loading {
+        process_loading(load, state);
+    }
+    for association in record.association {
+        process_association(&association, state);
+    }
+    for schedule_formation in record.schedule_formations {
+        process_formation(&schedule_formation, state);
+    }
+}
+
+fn process_schedule(schedule: &Schedule, state: &AppState) {
+    // Keep Darwin's own schedule regardless of whether static GTFS knows the trip
     state
-        .formations
-        .insert(schedule_formation.rid.clone(), schedule_formation.clone());
+        .schedules
+        .insert(schedule.rid.clone(), schedule.clone());
 
-    println!("Processed Formation for RID: {}", schedule_formation.rid);
-}
+    if !schedule.is_passenger_svc {
+        return;
+    }
 
-fn update_trip(ts: &TrainStatus, state: &AppState) {
-    // 1. Construct Trip ID: Try lookup, fallback to {uid}_{ssd}
-    let date_parsed =
-        NaiveDate::parse_from_str(&ts.ssd, "%Y-%m-%d").unwrap_or_else(|_| Utc::now().date_naive());
+    let date_parsed = NaiveDate::parse_from_str(&schedule.ssd, "%Y-%m-%d")
+        .unwrap_or_else(|_| Utc::now().date_naive());
 
-    let trip_id = if let Some(found_id) = state.gtfs.find_trip_id(&ts.uid, date_parsed) {
-        // println!("Match found: {} -> {}", ts.uid, found_id);
-        found_id
-    } else {
-        println!("No static match for UID: {} on {}", ts.uid, ts.ssd);
+    let Some(TripMatch {
+        trip_id,
+        trip_stops,
+        added,
+    }) = resolve_trip(&schedule.uid, &schedule.rid, date_parsed, state)
+    else {
         return;
     };
 
-    // Update RID mapping
-    state.rid_to_trip_id.insert(ts.rid.clone(), trip_id.clone());
+    state
+        .rid_to_trip_id
+        .insert(schedule.rid.clone(), trip_id.clone());
 
-    println!(
-        "Processed TrainStatus for RID: {}, Trip: {}",
-        ts.rid, trip_id
+    let fully_cancelled = is_fully_cancelled(schedule);
+    publish_reason(
+        alerts::cancel_reason_key(&schedule.rid),
+        ReasonKind::Cancellation,
+        schedule.cancel_reason.as_ref(),
+        if fully_cancelled {
+            Effect::NoService
+        } else {
+            Effect::ReducedService
+        },
+        &trip_id,
+        date_parsed,
+        state,
     );
 
-    // Fetch static stop sequence for loop handling
-    // We assume the static stops are sorted by sequence, or we iterate in order.
-    // Darwin locations usually come in order.
-    let trip_stops = state.gtfs.get_trip_stops(&trip_id).unwrap_or_default();
+    // A running trip that static GTFS already has is only published for what Darwin
+    // changes about it; untouched, it needs no entity
+    let skips_calls = schedule
+        .locations
+        .iter()
+        .any(|l| l.is_public_call() && l.point().cancelled);
+    let mut entity = if fully_cancelled || skips_calls || added {
+        trip_entity(state, &trip_id, date_parsed, added)
+    } else {
+        // Still clear an earlier cancellation, or SKIPPED calls that are back
+        let Some(entity) = state.trip_updates.get_mut(&trip_id) else {
+            return;
+        };
+        entity
+    };
+    let trip_update = entity.trip_update.as_mut().unwrap();
     let mut current_static_idx = 0;
 
-    // 2. Prepare GTFS-RT Entity
-    let mut entity = state
+    if fully_cancelled {
+        trip_update.trip.schedule_relationship = Some(TripScheduleRelationship::Canceled as i32);
+        // Consumers ignore stop-level updates on a cancelled trip
+        trip_update.stop_time_update.clear();
+        println!(
+            "Cancelled Trip {} (RID: {}, reason: {})",
+            trip_id,
+            schedule.rid,
+            schedule
+                .cancel_reason
+                .as_ref()
+                .and_then(|r| r.code.as_deref())
+                .unwrap_or("none")
+        );
+        return;
+    }
+
+    // Reinstated after an earlier cancellation
+    if trip_update.trip.schedule_relationship == Some(TripScheduleRelationship::Canceled as i32) {
+        trip_update.trip.schedule_relationship = Some(if added {
+            TripScheduleRelationship::New as i32
+        } else {
+            TripScheduleRelationship::Scheduled as i32
+        });
+    }
+
+    for loc in schedule.locations.iter().filter(|l| l.is_public_call()) {
+        let point = loc.point();
+        let Some(stop_id) = state.gtfs.get_stop_id(&point.tiploc) else {
+            continue;
+        };
+        let found_seq = match_stop_sequence(&trip_stops, &mut current_static_idx, &stop_id);
+
+        if point.cancelled {
+            let stu = StopTimeUpdate {
+                stop_id: Some(stop_id.to_string()),
+                stop_sequence: found_seq,
+                schedule_relationship: Some(StopScheduleRelationship::Skipped as i32),
+                ..Default::default()
+            };
+            upsert_stop_time_update(trip_update, stu);
+        } else if let Some(idx) = find_stop_time_update(trip_update, &stop_id, found_seq)
+            && trip_update.stop_time_update[idx].schedule_relationship
+                == Some(StopScheduleRelationship::Skipped as i32)
+        {
+            // Call reinstated, drop the stale SKIPPED marker
+            trip_update.stop_time_update.remove(idx);
+        }
+    }
+
+    if added {
+        let route_id = schedule
+            .toc
+            .as_deref()
+            .and_then(|toc| state.gtfs.get_route_for_toc(toc));
+        seed_added_trip(trip_update, schedule, &trip_stops, route_id, date_parsed);
+    }
+
+    trip_update
+        .stop_time_update
+        .sort_by_key(|u| u.stop_sequence.unwrap_or(0));
+    // Reinstated with nothing left to say
+    let nothing_left = !added && trip_update.stop_time_update.is_empty();
+    drop(entity);
+    if nothing_left {
+        state.trip_updates.remove(&trip_id);
+    }
+
+    println!(
+        "Processed Schedule for RID: {}, Trip: {}{}",
+        schedule.rid,
+        trip_id,
+        if added { " (added)" } else { "" }
+    );
+}
+
+// Where a Darwin service lands in the feed
+struct TripMatch {
+    trip_id: CompactString,
+    // (StopID, Sequence) in calling order
+    trip_stops: Vec<(CompactString, u32)>,
+    // Not in static GTFS, built from Darwin's own schedule
+    added: bool,
+}
+
+fn resolve_trip(uid: &str, rid: &str, date: NaiveDate, state: &AppState) -> Option<TripMatch> {
+    adopt_timetable_journey(rid, state, false);
+
+    if let Some(found_id) = state.gtfs.find_trip_id(uid, date) {
+        let trip_stops = state.gtfs.get_trip_stops(&found_id).unwrap_or_default();
+        return Some(TripMatch {
+            trip_id: found_id,
+            trip_stops,
+            added: false,
+        });
+    }
+
+    // VSTP, charters and other short-notice services never reach the static GTFS
+    let Some(schedule) = state.schedules.get(rid) else {
+        println!("No static match for UID: {} on {}", uid, date);
+        return None;
+    };
+    if !schedule.is_passenger_svc {
+        return None;
+    }
+    Some(TripMatch {
+        trip_id: added_trip_id(&schedule),
+        trip_stops: schedule_trip_stops(&schedule, state),
+        added: true,
+    })
+}
+
+// A RID the Push Port hasn't sent a schedule for (e.g. after a restart without a
+// snapshot) is applied from Darwin's daily timetable as if it had, with its associations.
+// Q-trains only run once activated, so they wait for a TS of their own (`activated`).
+fn adopt_timetable_journey(rid: &str, state: &AppState, activated: bool) {
+    if state.schedules.contains_key(rid) {
+        return;
+    }
+    let Some(journey) = state.gtfs.timetable_journey(rid) else {
+        return;
+    };
+    if journey.qtrain && !activated {
+        return;
+    }
+    process_schedule(&journey, state);
+
+    for assoc in state.gtfs.timetable_associations(rid) {
+        if !matches!(assoc.category.as_str(), "JJ" | "VV" | "NP") {
+            continue;
+        }
+        for rid in [&assoc.main.rid, &assoc.assoc.rid] {
+            let mut entry = state.associations.entry(rid.clone()).or_default();
+            if !entry.iter().any(|a| a.same_as(&assoc)) {
+                entry.push(assoc.clone());
+            }
+        }
+    }
+}
+
+fn added_trip_id(schedule: &Schedule) -> CompactString {
+    CompactString::from(format!("{}_{}", schedule.uid, schedule.ssd))
+}
+
+// Sequence is the position among passenger calls, so it stays stable as the TIPLOC map changes
+fn schedule_trip_stops(schedule: &Schedule, state: &AppState) -> Vec<(CompactString, u32)> {
+    schedule
+        .locations
+        .iter()
+        .filter(|l| l.is_public_call())
+        .enumerate()
+        .filter_map(|(idx, loc)| {
+            state
+                .gtfs
+                .get_stop_id(&loc.point().tiploc)
+                .map(|stop_id| (stop_id, idx as u32 + 1))
+        })
+        .collect()
+}
+
+// Added trips have no static stop_times, so publish booked times until forecasts replace them
+fn seed_added_trip(
+    trip_update: &mut TripUpdate,
+    schedule: &Schedule,
+    trip_stops: &[(CompactString, u32)],
+    route_id: Option<CompactString>,
+    date_parsed: NaiveDate,
+) {
+    trip_update.trip.schedule_relationship = Some(TripScheduleRelationship::New as i32);
+    // NEW trips aren't in the static feed, so consumers need the route to place them
+    trip_update.trip.route_id = route_id.map(String::from);
+
+    trip_update.trip.start_time = schedule
+        .locations
+        .iter()
+        .find(|l| l.is_public_call())
+        .and_then(|l| l.point().ptd.as_deref())
+        .map(|ptd| format!("{}:00", ptd));
+
+    // Walk every location, passes included, so the clock sees midnight
+    let mut clock = DarwinClock::new(date_parsed);
+    let mut public_idx = 0;
+    for loc in &schedule.locations {
+        let point = loc.point();
+        if let Some(anchor) = schedule_anchor(point) {
+            clock.advance(anchor);
+        }
+        if !loc.is_public_call() {
+            continue;
+        }
+        public_idx += 1;
+
+        let Some((stop_id, seq)) = trip_stops.iter().find(|(_, seq)| *seq == public_idx) else {
+            continue;
+        };
+        if point.cancelled || find_stop_time_update(trip_update, stop_id, Some(*seq)).is_some() {
+            continue;
+        }
+
+        let scheduled = |time: &Option<CompactString>| {
+            time.as_deref()
+                .and_then(|t| clock.resolve(t))
+                .and_then(london_timestamp)
+                .map(scheduled_event)
+        };
+        trip_update.stop_time_update.push(StopTimeUpdate {
+            stop_id: Some(stop_id.to_string()),
+            stop_sequence: Some(*seq),
+            arrival: scheduled(&point.pta),
+            departure: scheduled(&point.ptd),
+            ..Default::default()
+        });
+    }
+}
+
+// The earliest time Darwin gives for a location, used to track the journey across midnight
+fn schedule_anchor(point: &SchedulePoint) -> Option<&str> {
+    point
+        .wta
+        .as_deref()
+        .or(point.wtp.as_deref())
+        .or(point.wtd.as_deref())
+        .or(point.pta.as_deref())
+        .or(point.ptd.as_deref())
+}
+
+fn location_anchor(loc: &Location) -> Option<&str> {
+    loc.wta
+        .as_deref()
+        .or(loc.wtp.as_deref())
+        .or(loc.wtd.as_deref())
+        .or(loc.pta.as_deref())
+        .or(loc.ptd.as_deref())
+}
+
+// Position a clock at the journey origin, so a TS that only carries post-midnight
+// locations still lands on the right day
+fn journey_clock(
+    rid: &str,
+    trip_id: &str,
+    added: bool,
+    date_parsed: NaiveDate,
+    state: &AppState,
+) -> DarwinClock {
+    let mut clock = DarwinClock::new(date_parsed);
+    if let Some(schedule) = state.schedules.get(rid)
+        && let Some(origin) = schedule
+            .locations
+            .first()
+            .and_then(|l| schedule_anchor(l.point()))
+    {
+        clock.advance(origin);
+    } else if !added
+        && let Some(start_secs) = state.gtfs.get_trip_start_time(trip_id)
+        && let Some(midnight) = date_parsed.and_hms_opt(0, 0, 0)
+    {
+        clock = DarwinClock::starting_at(midnight + Duration::seconds(start_secs as i64));
+    }
+    clock
+}
+
+fn scheduled_event(ts: i64) -> StopTimeEvent {
+    StopTimeEvent {
+        time: Some(ts),
+        scheduled_time: Some(ts),
+        delay: Some(0),
+        ..Default::default()
+    }
+}
+
+// A schedule is cancelled outright when it is deleted or cancelled as a whole, or every
+// passenger call is cancelled.
+fn is_fully_cancelled(schedule: &Schedule) -> bool {
+    if schedule.deleted || schedule.cancelled {
+        return true;
+    }
+    let mut public_calls = schedule
+        .locations
+        .iter()
+        .filter(|l| l.is_public_call())
+        .peekable();
+    public_calls.peek().is_some() && public_calls.all(|l| l.point().cancelled)
+}
+
+fn trip_entity<'a>(
+    state: &'a AppState,
+    trip_id: &CompactString,
+    date_parsed: NaiveDate,
+    added: bool,
+) -> RefMut<'a, CompactString, FeedEntity> {
+    state
         .trip_updates
         .entry(trip_id.clone())
         .or_insert_with(|| {
-            let mut fe = FeedEntity::default();
-            fe.id = trip_id.to_string();
             let mut tu = TripUpdate::default();
             tu.trip.trip_id = Some(trip_id.to_string());
+            if added {
+                tu.trip.schedule_relationship = Some(TripScheduleRelationship::New as i32);
+            }
 
             // Correct Start Date Calculation
             // 1. Get trip start time from static GTFS (seconds from midnight)
-            let start_secs = state.gtfs.get_trip_start_time(&trip_id).unwrap_or(0);
+            let start_secs = state.gtfs.get_trip_start_time(trip_id).unwrap_or(0);
 
             // 2. Construct naive datetime (Local/London time) based on SSD + StartTime
             // SSD is the "Schedule Date", adding start_secs gives the actual Start Time in UK Local.
@@ -104,19 +444,146 @@ fn update_trip(ts: &TrainStatus, state: &AppState) {
                 .to_string();
 
             tu.trip.start_date = Some(correct_date_str);
-            // route_id? We don't have it easily. gtfs-rt spec says optional if trip_id is unique.
-            fe.trip_update = Some(tu);
-            fe
-        });
+            // Static trips are identified by trip_id alone; added trips get their
+            // operator's route_id in seed_added_trip
+            FeedEntity {
+                id: trip_id.to_string(),
+                trip_update: Some(tu),
+                ..Default::default()
+            }
+        })
+}
+
+// Forward greedy match against the static stop list, so loops resolve to the right visit
+fn match_stop_sequence(
+    trip_stops: &[(CompactString, u32)],
+    current_static_idx: &mut usize,
+    stop_id: &str,
+) -> Option<u32> {
+    let offset = trip_stops
+        .iter()
+        .skip(*current_static_idx)
+        .position(|(id, _)| id == stop_id)?;
+    let idx = *current_static_idx + offset;
+    *current_static_idx = idx + 1; // Advance
+    Some(trip_stops[idx].1)
+}
+
+// Prefer sequence match if available, fall back to stop_id
+fn find_stop_time_update(
+    trip_update: &TripUpdate,
+    stop_id: &str,
+    seq: Option<u32>,
+) -> Option<usize> {
+    match seq {
+        Some(seq) => trip_update
+            .stop_time_update
+            .iter()
+            .position(|u| u.stop_sequence == Some(seq)),
+        None => trip_update
+            .stop_time_update
+            .iter()
+            .position(|u| u.stop_id.as_deref() == Some(stop_id)),
+    }
+}
+
+fn upsert_stop_time_update(trip_update: &mut TripUpdate, stu: StopTimeUpdate) {
+    let stop_id = stu.stop_id.clone().unwrap_or_default();
+    match find_stop_time_update(trip_update, &stop_id, stu.stop_sequence) {
+        Some(idx) => trip_update.stop_time_update[idx] = stu,
+        None => trip_update.stop_time_update.push(stu),
+    }
+}
+
+fn process_formation(
+    schedule_formation: &crate::formations::v2::ScheduleFormations,
+    state: &AppState,
+) {
+    state
+        .formations
+        .insert(schedule_formation.rid.clone(), schedule_formation.clone());
+
+    println!("Processed Formation for RID: {}", schedule_formation.rid);
+
+    // Loading may have arrived before the formation it refers to
+    publish_coaches(&schedule_formation.rid, state);
+
+    // A portion's coaches label the main train it joins or divides from
+    let mains: Vec<CompactString> = state
+        .associations
+        .get(&schedule_formation.rid)
+        .map(|a| {
+            a.iter()
+                .filter(|a| a.assoc.rid == schedule_formation.rid)
+                .map(|a| a.main.rid.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    for main_rid in mains {
+        publish_coaches(&main_rid, state);
+    }
+}
+
+fn update_trip(ts: &TrainStatus, state: &AppState) {
+    // 1. Construct Trip ID: Try lookup, fallback to {uid}_{ssd}
+    let date_parsed =
+        NaiveDate::parse_from_str(&ts.ssd, "%Y-%m-%d").unwrap_or_else(|_| Utc::now().date_naive());
+
+    // A forecast of its own means the service is running, Q-train or not
+    adopt_timetable_journey(&ts.rid, state, true);
+    let Some(TripMatch {
+        trip_id,
+        trip_stops,
+        added,
+    }) = resolve_trip(&ts.uid, &ts.rid, date_parsed, state)
+    else {
+        return;
+    };
+
+    // Update RID mapping
+    state.rid_to_trip_id.insert(ts.rid.clone(), trip_id.clone());
+    state.forecast_rids.insert(ts.rid.clone());
+    publish_reason(
+        alerts::late_reason_key(&ts.rid),
+        ReasonKind::LateRunning,
+        ts.late_reason.as_ref(),
+        Effect::SignificantDelays,
+        &trip_id,
+        date_parsed,
+        state,
+    );
+
+    println!(
+        "Processed TrainStatus for RID: {}, Trip: {}",
+        ts.rid, trip_id
+    );
+
+    // Static stop sequence for loop handling
+    // We assume the static stops are sorted by sequence, or we iterate in order.
+    // Darwin locations usually come in order.
+    let mut current_static_idx = 0;
+    let mut clock = journey_clock(&ts.rid, &trip_id, added, date_parsed, state);
+
+    // 2. Prepare GTFS-RT Entity
+    let mut entity = trip_entity(state, &trip_id, date_parsed, added);
 
     let trip_update = entity.trip_update.as_mut().unwrap();
+    let is_cancelled =
+        trip_update.trip.schedule_relationship == Some(TripScheduleRelationship::Canceled as i32);
 
     // 3. Process Locations
     let mut platform_updates = HashMap::new();
     // Map Sequence -> (StopID, Platform)
     let mut platform_v2_updates: HashMap<u32, (CompactString, CompactString)> = HashMap::new();
+    // Map Sequence -> (StopID, unknown delay)
+    let mut unknown_delay_updates: HashMap<u32, (CompactString, bool)> = HashMap::new();
 
     for loc in &ts.locations {
+        // Every location moves the clock, mapped or not
+        if let Some(anchor) = location_anchor(loc) {
+            clock.advance(anchor);
+        }
+
         // Check if tiploc exists
         if let Some(tiploc) = &loc.tiploc {
             // Map TIPLOC -> Stop ID
@@ -124,16 +591,7 @@ fn update_trip(ts: &TrainStatus, state: &AppState) {
 
             if let Some(stop_id) = stop_id_opt {
                 // Find matching sequence (Forward greedy match)
-                let mut found_seq = None;
-                if !trip_stops.is_empty() {
-                    for i in current_static_idx..trip_stops.len() {
-                        if trip_stops[i].0 == stop_id {
-                            found_seq = Some(trip_stops[i].1);
-                            current_static_idx = i + 1; // Advance
-                            break;
-                        }
-                    }
-                }
+                let found_seq = match_stop_sequence(&trip_stops, &mut current_static_idx, &stop_id);
 
                 // Platform Logic
                 if let Some(plat) = &loc.platform {
@@ -158,30 +616,29 @@ fn update_trip(ts: &TrainStatus, state: &AppState) {
                 }
 
                 // Delay / Time Logic
-                if has_time_data(loc) {
-                    let stu = build_stop_time_update(loc, &stop_id, &ts.ssd, found_seq);
-
-                    // Update Logic: Prefer sequence match if available
-                    if let Some(seq) = found_seq {
-                        if let Some(idx) = trip_update
-                            .stop_time_update
-                            .iter()
-                            .position(|u| u.stop_sequence == Some(seq))
-                        {
-                            trip_update.stop_time_update[idx] = stu;
-                        } else {
-                            trip_update.stop_time_update.push(stu);
-                        }
-                    } else {
-                        // Fallback to stop_id match
-                        if let Some(existing_idx) = trip_update
-                            .stop_time_update
-                            .iter()
-                            .position(|u| u.stop_id.as_deref() == Some(stop_id.as_str()))
-                        {
-                            trip_update.stop_time_update[existing_idx] = stu;
-                        } else {
-                            trip_update.stop_time_update.push(stu);
+                // Cancelled trips and SKIPPED calls keep their schedule status
+                if has_time_data(loc) && !is_cancelled {
+                    let skipped = find_stop_time_update(trip_update, &stop_id, found_seq)
+                        .is_some_and(|idx| {
+                            trip_update.stop_time_update[idx].schedule_relationship
+                                == Some(StopScheduleRelationship::Skipped as i32)
+                        });
+                    if !skipped {
+                        let static_secs = found_seq
+                            .filter(|_| !added)
+                            .and_then(|seq| state.gtfs.get_stop_time_secs(&trip_id, seq));
+                        let stu = build_stop_time_update(
+                            loc,
+                            &stop_id,
+                            found_seq,
+                            &clock,
+                            date_parsed,
+                            static_secs,
+                        );
+                        upsert_stop_time_update(trip_update, stu);
+                        if let Some(seq) = found_seq {
+                            unknown_delay_updates
+                                .insert(seq, (stop_id.clone(), has_unknown_delay(loc)));
                         }
                     }
                 }
@@ -215,95 +672,498 @@ fn update_trip(ts: &TrainStatus, state: &AppState) {
         // Keep sorted by sequence
         platforms_entry.sort_by_key(|p| p.sequence);
     }
+
+    // Unknown delays are listed once flagged, and cleared in place once Darwin has a time
+    if unknown_delay_updates.values().any(|(_, unknown)| *unknown)
+        || state.unknown_delays.contains_key(&trip_id)
+    {
+        use crate::state::UnknownDelayInfo;
+        let mut delays_entry = state.unknown_delays.entry(trip_id.clone()).or_default();
+
+        for (seq, (stop_id, unknown_delay)) in unknown_delay_updates {
+            if let Some(existing) = delays_entry.iter_mut().find(|d| d.sequence == seq) {
+                existing.unknown_delay = unknown_delay;
+                existing.stop_id = stop_id;
+            } else if unknown_delay {
+                delays_entry.push(UnknownDelayInfo {
+                    stop_id,
+                    sequence: seq,
+                    unknown_delay,
+                });
+            }
+        }
+        delays_entry.sort_by_key(|d| d.sequence);
+    }
+
+    drop(entity);
+    propagate_divides(&ts.rid, state);
 }
 
 fn update_trip_from_order(to: &TrainOrder, state: &AppState) {
     if let Some(set) = &to.set {
-        let items = vec![&set.first, &set.second, &set.third];
+        let items = [&set.first, &set.second, &set.third];
 
         for (idx, item_opt) in items.iter().enumerate() {
-            if let Some(item) = item_opt {
-                if let Some(rid_data) = &item.rid {
-                    if let Some(trip_id) = state.rid_to_trip_id.get(&rid_data.value) {
-                        // 1. Update Platform (existing logic) REMOVED
-                        if let Some(_stop_id) = state.gtfs.get_stop_id(&to.tiploc) {
-                            if let Some(_platform) = &to.platform {
-                                // state
-                                //     .platforms
-                                //     .entry(trip_id.clone())
-                                //     .or_default()
-                                //     .insert(stop_id.clone(), platform.clone());
-                            }
-                        }
+            if let Some(item) = item_opt
+                && let Some(rid_data) = &item.rid
+                && let Some(trip_id) = state.rid_to_trip_id.get(&rid_data.value)
+            {
+                // 1. Update Platform (existing logic) REMOVED
+                if let Some(_stop_id) = state.gtfs.get_stop_id(&to.tiploc)
+                    && let Some(_platform) = &to.platform
+                {
+                    // state
+                    //     .platforms
+                    //     .entry(trip_id.clone())
+                    //     .or_default()
+                    //     .insert(stop_id.clone(), platform.clone());
+                }
 
-                        // 2. Update VehiclePosition for Consist
-                        let vp_key = CompactString::from(format!("{}_VP", trip_id.as_str()));
-                        let mut entity =
-                            state.trip_updates.entry(vp_key.clone()).or_insert_with(|| {
-                                let mut fe = FeedEntity::default();
-                                fe.id = vp_key.to_string();
-                                let mut vp = VehiclePosition::default();
-
-                                // Library TripUpdate has TripDescriptor direct, but VehiclePosition?
-                                // VehiclePosition typically has optional TripDescriptor.
-                                // Let's check typical usage. Usually `vp.trip = Some(...)`
-                                // I'll assume standard usage.
-                                let mut td = gtfs_realtime::TripDescriptor::default();
-                                td.trip_id = Some(trip_id.to_string());
-                                vp.trip = Some(td);
-
-                                fe.vehicle = Some(vp);
-                                fe
-                            });
-
-                        let vp = entity.vehicle.as_mut().unwrap();
-
-                        if let Some(stop_id) = state.gtfs.get_stop_id(&to.tiploc) {
-                            vp.stop_id = Some(stop_id.to_string());
-                        }
+                // 2. Update VehiclePosition for Consist
+                let mut entity = vehicle_entity(state, &trip_id, &rid_data.value);
+                let vp = entity.vehicle.as_mut().unwrap();
 
-                        // Populate CarriageDetails
-                        // Reuse existing entry if sequence matches, else create new.
-                        let seq = (idx + 1) as u32;
-                        let mut cd = gtfs_realtime::vehicle_position::CarriageDetails::default();
-                        cd.id = Some(rid_data.value.to_string());
-                        cd.label = item.train_id.clone().map(|s| s.into_string());
-                        cd.carriage_sequence = Some(seq);
-
-                        if let Some(existing) = vp
-                            .multi_carriage_details
-                            .iter_mut()
-                            .find(|c| c.carriage_sequence == Some(seq))
-                        {
-                            *existing = cd;
-                        } else {
-                            vp.multi_carriage_details.push(cd);
-                        }
-                        // Keep sorted
-                        vp.multi_carriage_details
-                            .sort_by_key(|c| c.carriage_sequence.unwrap_or(0));
+                if let Some(stop_id) = state.gtfs.get_stop_id(&to.tiploc) {
+                    vp.stop_id = Some(stop_id.to_string());
+                }
 
-                        println!("Updated VP Consist for Trip {}", trip_id.as_str());
-                    }
+                // Coach-by-coach details from the formation are more useful than the order slot
+                if has_coach_details(vp) {
+                    continue;
                 }
+
+                // Populate CarriageDetails
+                // Reuse existing entry if sequence matches, else create new.
+                let seq = (idx + 1) as u32;
+                let cd = CarriageDetails {
+                    id: Some(rid_data.value.to_string()),
+                    label: item.train_id.clone().map(|s| s.into_string()),
+                    carriage_sequence: Some(seq),
+                    ..Default::default()
+                };
+
+                if let Some(existing) = vp
+                    .multi_carriage_details
+                    .iter_mut()
+                    .find(|c| c.carriage_sequence == Some(seq))
+                {
+                    *existing = cd;
+                } else {
+                    vp.multi_carriage_details.push(cd);
+                }
+                // Keep sorted
+                vp.multi_carriage_details
+                    .sort_by_key(|c| c.carriage_sequence.unwrap_or(0));
+
+                println!("Updated VP Consist for Trip {}", trip_id.as_str());
             }
         }
     }
 }
 
 fn process_station_message(msg: &StationMessage, state: &AppState) {
-    let full_msg = format!("{}: {}", msg.category, msg.message);
-    state.station_messages.insert(msg.id.clone(), full_msg);
-    println!("Processed StationMessage: {} ({})", msg.id, msg.category);
+    let key = alerts::station_message_key(&msg.id);
+    match alerts::station_message_alert(msg, state) {
+        Some(entity) => {
+            state.alerts.insert(key, entity);
+            println!("Processed StationMessage: {} ({})", msg.id, msg.category);
+        }
+        None => {
+            // An empty or suppressed message withdraws a previous one with the same id
+            state.alerts.remove(&key);
+            println!("Removed StationMessage: {}", msg.id);
+        }
+    }
+}
+
+fn process_train_alert(ta: &TrainAlert, state: &AppState) {
+    let key = alerts::train_alert_key(&ta.id);
+
+    // Staff and operations alerts are not for passengers
+    if ta.is_withdrawn() || ta.audience != AlertAudience::Customer {
+        if state.alerts.remove(&key).is_some() {
+            println!("Removed TrainAlert: {}", ta.id);
+        }
+        return;
+    }
+
+    let trips: Vec<alerts::AlertedTrip> = ta
+        .services
+        .services
+        .iter()
+        .filter_map(|service| alerted_trip(service, state))
+        .collect();
+
+    match alerts::train_alert_entity(ta, &trips) {
+        Some(entity) => {
+            state.alerts.insert(key, entity);
+            println!("Processed TrainAlert: {} ({} trips)", ta.id, trips.len());
+        }
+        None => {
+            state.alerts.remove(&key);
+            println!("No matching trips for TrainAlert: {}", ta.id);
+        }
+    }
+}
+
+fn alerted_trip(service: &AlertService, state: &AppState) -> Option<alerts::AlertedTrip> {
+    let rid = service.rid.as_deref().unwrap_or_default();
+    let date_parsed = service
+        .ssd
+        .as_deref()
+        .and_then(|ssd| NaiveDate::parse_from_str(ssd, "%Y-%m-%d").ok())
+        .or_else(|| NaiveDate::parse_from_str(rid.get(..8)?, "%Y%m%d").ok())?;
+
+    let mapped = state.rid_to_trip_id.get(rid).map(|id| id.clone());
+    let trip_id = match mapped {
+        Some(trip_id) => trip_id,
+        None => resolve_trip(service.uid.as_deref()?, rid, date_parsed, state)?.trip_id,
+    };
+
+    let start_date = trip_start_date(&trip_id, date_parsed, state);
+
+    let stop_ids = service
+        .locations
+        .iter()
+        .filter_map(|tiploc| state.gtfs.get_stop_id(tiploc))
+        .collect();
+
+    Some(alerts::AlertedTrip {
+        trip_id,
+        start_date,
+        stop_ids,
+    })
+}
+
+// Match the start_date already published on the TripUpdate where there is one
+fn trip_start_date(trip_id: &str, date: NaiveDate, state: &AppState) -> String {
+    state
+        .trip_updates
+        .get(trip_id)
+        .and_then(|fe| fe.trip_update.as_ref()?.trip.start_date.clone())
+        .unwrap_or_else(|| date.format("%Y%m%d").to_string())
+}
+
+// Publish Darwin's reason as text on the trip, or withdraw it once Darwin drops the reason.
+// Without reference data there is no text, so nothing is published.
+fn publish_reason(
+    key: CompactString,
+    kind: ReasonKind,
+    reason: Option<&DisruptionReason>,
+    effect: Effect,
+    trip_id: &CompactString,
+    date: NaiveDate,
+    state: &AppState,
+) {
+    let text = reason.and_then(|r| state.reference.read().unwrap().reason_text(kind, r));
+    let Some(text) = text else {
+        state.alerts.remove(&key);
+        return;
+    };
+    let trip = alerts::AlertedTrip {
+        trip_id: trip_id.clone(),
+        start_date: trip_start_date(trip_id, date, state),
+        stop_ids: Vec::new(),
+    };
+    state
+        .alerts
+        .insert(key.clone(), alerts::reason_alert(&key, text, effect, &trip));
 }
 
 fn process_loading(load: &Loading, state: &AppState) {
-    // Need Loading fields to be useful.
-    if let Some(_trip_id) = state.rid_to_trip_id.get(&load.rid) {
-        // TODO: Update OccupancyStatus if Loading struct has fields.
-        // For now, valid placeholder.
-        println!("Processed Loading for RID: {}", load.rid);
+    state.loadings.insert(load.rid.clone(), load.clone());
+    publish_coaches(&load.rid, state);
+}
+
+// Publish the formation's coaches on the trip's VehiclePosition, with the latest
+// loading joined by fid and any join/divide linkage in the labels
+fn publish_coaches(rid: &str, state: &AppState) {
+    let load = state.loadings.get(rid).map(|l| l.clone());
+    let formation = {
+        let Some(schedule_formations) = state.formations.get(rid) else {
+            if load.is_some() {
+                println!("No formation yet for Loading RID: {}", rid);
+            }
+            return;
+        };
+        let formation = match &load {
+            Some(load) => schedule_formations
+                .formations
+                .iter()
+                .find(|f| f.fid == load.fid),
+            None => schedule_formations.formations.first(),
+        };
+        let Some(formation) = formation else {
+            println!("No matching formation for Loading RID: {}", rid);
+            return;
+        };
+        formation.clone()
+    };
+    let Some(trip_id) = state.rid_to_trip_id.get(rid).map(|id| id.clone()) else {
+        return;
+    };
+    let links = portion_links(rid, state);
+
+    let mut entity = vehicle_entity(state, &trip_id, rid);
+    let vp = entity.vehicle.as_mut().unwrap();
+
+    // Formation coaches are listed front to back
+    vp.multi_carriage_details = formation
+        .coaches
+        .coaches
+        .iter()
+        .enumerate()
+        .map(|(idx, coach)| {
+            let percentage = load
+                .as_ref()
+                .and_then(|l| {
+                    l.coaches
+                        .iter()
+                        .find(|c| c.coach_number == coach.coach_number)
+                })
+                .map(|c| c.percentage.min(100));
+
+            CarriageDetails {
+                id: Some(coach.coach_number.to_string()),
+                label: Some(match links.get(&coach.coach_number) {
+                    Some(link) => format!("{} ({})", coach.coach_number, link),
+                    None => coach.coach_number.to_string(),
+                }),
+                carriage_sequence: Some(idx as u32 + 1),
+                occupancy_percentage: Some(percentage.map_or(-1, |p| p as i32)),
+                occupancy_status: Some(occupancy_status(percentage) as i32),
+            }
+        })
+        .collect();
+
+    // Whole train: average of the coaches that reported
+    let reported: Vec<u32> = vp
+        .multi_carriage_details
+        .iter()
+        .filter_map(|c| u32::try_from(c.occupancy_percentage?).ok())
+        .collect();
+    if reported.is_empty() {
+        vp.occupancy_percentage = None;
+        vp.occupancy_status = Some(OccupancyStatus::NoDataAvailable as i32);
+    } else {
+        let average = reported.iter().sum::<u32>() / reported.len() as u32;
+        vp.occupancy_percentage = Some(average);
+        vp.occupancy_status = Some(occupancy_status(Some(average)) as i32);
+    }
+
+    println!(
+        "Updated Coaches for Trip {} ({} with loading)",
+        trip_id,
+        reported.len()
+    );
+}
+
+// Coach number -> where that coach goes (divide) or comes from (join),
+// for coaches that also appear in the other portion's formation
+fn portion_links(rid: &str, state: &AppState) -> HashMap<CompactString, String> {
+    let associations = state
+        .associations
+        .get(rid)
+        .map(|a| a.clone())
+        .unwrap_or_default();
+
+    let mut links = HashMap::new();
+    for assoc in associations
+        .iter()
+        .filter(|a| a.main.rid == rid && !a.is_cancelled)
+    {
+        let link = match assoc.category.as_str() {
+            "VV" => portion_end_name(&assoc.assoc.rid, true, state).map(|n| format!("to {}", n)),
+            "JJ" => portion_end_name(&assoc.assoc.rid, false, state).map(|n| format!("from {}", n)),
+            _ => None,
+        };
+        let Some(link) = link else {
+            continue;
+        };
+        let Some(portion) = state.formations.get(&assoc.assoc.rid).map(|f| f.clone()) else {
+            continue;
+        };
+        for coach in portion.formations.iter().flat_map(|f| &f.coaches.coaches) {
+            links.insert(coach.coach_number.clone(), link.clone());
+        }
     }
+    links
+}
+
+// Destination (or origin) of a service, by stop name where static GTFS has one
+fn portion_end_name(rid: &str, destination: bool, state: &AppState) -> Option<String> {
+    let schedule = state.schedules.get(rid)?;
+    let mut calls = schedule.locations.iter().filter(|l| l.is_public_call());
+    let end = if destination {
+        calls.next_back()?
+    } else {
+        calls.next()?
+    };
+    let tiploc = &end.point().tiploc;
+    let name = state
+        .gtfs
+        .get_stop_id(tiploc)
+        .and_then(|stop_id| state.gtfs.get_stop_name(&stop_id))
+        .unwrap_or_else(|| tiploc.clone());
+    Some(name.to_string())
+}
+
+fn process_association(assoc: &Association, state: &AppState) {
+    if !matches!(assoc.category.as_str(), "JJ" | "VV" | "NP") {
+        return;
+    }
+
+    for rid in [&assoc.main.rid, &assoc.assoc.rid] {
+        let mut entry = state.associations.entry(rid.clone()).or_default();
+        entry.retain(|a| !a.same_as(assoc));
+        if !assoc.is_deleted {
+            entry.push(assoc.clone());
+        }
+        if entry.is_empty() {
+            drop(entry);
+            state.associations.remove(rid);
+        }
+    }
+
+    println!(
+        "Processed Association {} at {}: {} -> {}",
+        assoc.category, assoc.tiploc, assoc.main.rid, assoc.assoc.rid
+    );
+
+    publish_coaches(&assoc.main.rid, state);
+    propagate_divides(&assoc.main.rid, state);
+}
+
+// The portion that divides off runs as part of the main train until the divide,
+// so its departure there follows the main train's until it has a TS of its own
+fn propagate_divides(rid: &str, state: &AppState) {
+    let associations = state
+        .associations
+        .get(rid)
+        .map(|a| a.clone())
+        .unwrap_or_default();
+
+    for assoc in associations
+        .iter()
+        .filter(|a| a.category == "VV" && a.main.rid == rid && !a.is_cancelled)
+    {
+        if state.forecast_rids.contains(&assoc.assoc.rid) {
+            continue;
+        }
+        let Some(stop_id) = state.gtfs.get_stop_id(&assoc.tiploc) else {
+            continue;
+        };
+        let Some(main_trip_id) = state.rid_to_trip_id.get(rid).map(|id| id.clone()) else {
+            continue;
+        };
+        let departure = state.trip_updates.get(&main_trip_id).and_then(|fe| {
+            let tu = fe.trip_update.as_ref()?;
+            tu.stop_time_update
+                .iter()
+                .find(|u| u.stop_id.as_deref() == Some(stop_id.as_str()))?
+                .departure
+        });
+        let Some(departure) = departure else {
+            continue;
+        };
+
+        adopt_timetable_journey(&assoc.assoc.rid, state, false);
+        let Some((uid, ssd)) = state
+            .schedules
+            .get(&assoc.assoc.rid)
+            .map(|s| (s.uid.clone(), s.ssd.clone()))
+        else {
+            continue;
+        };
+        let Ok(date_parsed) = NaiveDate::parse_from_str(&ssd, "%Y-%m-%d") else {
+            continue;
+        };
+        let Some(TripMatch {
+            trip_id,
+            trip_stops,
+            added,
+        }) = resolve_trip(&uid, &assoc.assoc.rid, date_parsed, state)
+        else {
+            continue;
+        };
+        state
+            .rid_to_trip_id
+            .insert(assoc.assoc.rid.clone(), trip_id.clone());
+
+        let mut entity = trip_entity(state, &trip_id, date_parsed, added);
+        let trip_update = entity.trip_update.as_mut().unwrap();
+        if trip_update.trip.schedule_relationship == Some(TripScheduleRelationship::Canceled as i32)
+        {
+            continue;
+        }
+
+        let stu = StopTimeUpdate {
+            stop_sequence: trip_stops
+                .iter()
+                .find(|(id, _)| *id == stop_id)
+                .map(|(_, seq)| *seq),
+            stop_id: Some(stop_id.to_string()),
+            departure: Some(departure),
+            ..Default::default()
+        };
+        upsert_stop_time_update(trip_update, stu);
+        trip_update
+            .stop_time_update
+            .sort_by_key(|u| u.stop_sequence.unwrap_or(0));
+
+        println!(
+            "Propagated divide at {} from RID {} onto Trip {}",
+            assoc.tiploc, rid, trip_id
+        );
+    }
+}
+
+fn occupancy_status(percentage: Option<u32>) -> OccupancyStatus {
+    match percentage {
+        None => OccupancyStatus::NoDataAvailable,
+        Some(0) => OccupancyStatus::Empty,
+        Some(1..=49) => OccupancyStatus::ManySeatsAvailable,
+        Some(50..=79) => OccupancyStatus::FewSeatsAvailable,
+        Some(80..=94) => OccupancyStatus::StandingRoomOnly,
+        Some(95..=99) => OccupancyStatus::CrushedStandingRoomOnly,
+        Some(_) => OccupancyStatus::Full,
+    }
+}
+
+fn has_coach_details(vp: &VehiclePosition) -> bool {
+    vp.multi_carriage_details
+        .iter()
+        .any(|c| c.occupancy_percentage.is_some())
+}
+
+// Vehicle entities live alongside trip updates, keyed "{trip_id}_VP". They carry the
+// RID's start date so gc can age them out if their trip update goes first.
+fn vehicle_entity<'a>(
+    state: &'a AppState,
+    trip_id: &CompactString,
+    rid: &str,
+) -> RefMut<'a, CompactString, FeedEntity> {
+    let vp_key = CompactString::from(format!("{}_VP", trip_id.as_str()));
+    let mut entity = state
+        .trip_updates
+        .entry(vp_key.clone())
+        .or_insert_with(|| FeedEntity {
+            id: vp_key.to_string(),
+            vehicle: Some(VehiclePosition {
+                trip: Some(gtfs_realtime::TripDescriptor {
+                    trip_id: Some(trip_id.to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    if let Some(trip) = entity.vehicle.as_mut().and_then(|vp| vp.trip.as_mut())
+        && trip.start_date.is_none()
+    {
+        trip.start_date = rid.get(..8).map(str::to_string);
+    }
+    entity
 }
 
 fn has_time_data(loc: &Location) -> bool {
@@ -311,9 +1171,16 @@ fn has_time_data(loc: &Location) -> bool {
     check_forecast(&loc.arr) || check_forecast(&loc.dep) || check_forecast(&loc.pass)
 }
 
+fn has_unknown_delay(loc: &Location) -> bool {
+    [&loc.arr, &loc.dep]
+        .into_iter()
+        .flatten()
+        .any(|f| f.is_unknown_delay())
+}
+
 fn check_forecast(f: &Option<crate::darwin_types::Forecast>) -> bool {
     if let Some(f) = f {
-        f.et.is_some() || f.at.is_some()
+        f.et.is_some() || f.actual().is_some() || f.is_unknown_delay()
     } else {
         false
     }
@@ -322,73 +1189,124 @@ fn check_forecast(f: &Option<crate::darwin_types::Forecast>) -> bool {
 fn build_stop_time_update(
     loc: &Location,
     stop_id: &str,
-    ssd: &str,
     seq: Option<u32>,
+    clock: &DarwinClock,
+    service_date: NaiveDate,
+    static_secs: Option<(Option<u32>, Option<u32>)>,
 ) -> StopTimeUpdate {
-    let mut stu = StopTimeUpdate::default();
-    stu.stop_id = Some(stop_id.to_string());
-    stu.stop_sequence = seq;
+    let mut stu = StopTimeUpdate {
+        stop_id: Some(stop_id.to_string()),
+        stop_sequence: seq,
+        ..Default::default()
+    };
+
+    // Scheduled time: Darwin public time, then working time, then the static stop_time
+    let (static_arr, static_dep) = static_secs.unwrap_or((None, None));
 
     if let Some(arr) = &loc.arr {
-        stu.arrival = parse_time(arr, ssd);
+        let scheduled = loc
+            .pta
+            .as_deref()
+            .or(loc.wta.as_deref())
+            .and_then(|t| clock.resolve(t))
+            .and_then(london_timestamp)
+            .or_else(|| static_arr.and_then(|secs| service_day_timestamp(service_date, secs)));
+        stu.arrival = parse_time(arr, clock, scheduled);
     }
     if let Some(dep) = &loc.dep {
-        stu.departure = parse_time(dep, ssd);
+        let scheduled = loc
+            .ptd
+            .as_deref()
+            .or(loc.wtd.as_deref())
+            .and_then(|t| clock.resolve(t))
+            .and_then(london_timestamp)
+            .or_else(|| static_dep.and_then(|secs| service_day_timestamp(service_date, secs)));
+        stu.departure = parse_time(dep, clock, scheduled);
     } else if let Some(_pass) = &loc.pass {
         // Ignored
     }
 
+    // Nothing to say but "Delayed": without NO_DATA the update would be invalid, and
+    // consumers would carry an earlier stop's delay through this one
+    if stu.arrival.is_none() && stu.departure.is_none() && has_unknown_delay(loc) {
+        stu.schedule_relationship = Some(StopScheduleRelationship::NoData as i32);
+    }
+
     stu
 }
 
-fn parse_time(f: &crate::darwin_types::Forecast, ssd: &str) -> Option<StopTimeEvent> {
-    let time_str = f.at.as_ref().or(f.et.as_ref())?;
-
-    // format HH:MM
-    let hm: Vec<&str> = time_str.split(':').collect();
-    if hm.len() != 2 {
+fn parse_time(
+    f: &crate::darwin_types::Forecast,
+    clock: &DarwinClock,
+    scheduled: Option<i64>,
+) -> Option<StopTimeEvent> {
+    // "Delayed": no time Darwin would stand behind, so no event. /unknown-delays flags
+    // the stop instead.
+    if f.is_unknown_delay() {
         return None;
     }
-    let hour: u32 = hm[0].parse().ok()?;
-    let min: u32 = hm[1].parse().ok()?;
-
-    // We need complete date.
-    // SSD is YYYY-MM-DD
-    let date = NaiveDate::parse_from_str(ssd, "%Y-%m-%d").ok()?;
-    let dt = date.and_hms_opt(hour, min, 0)?;
-
-    // Darwin times are UK local time (Europe/London), not UTC.
-    // Convert local wall-clock time to a Unix timestamp with DST awareness.
-    let ts = match London.from_local_datetime(&dt) {
-        chrono::LocalResult::Single(local_dt) => local_dt.timestamp(),
-        chrono::LocalResult::Ambiguous(earliest, _) => earliest.timestamp(),
-        chrono::LocalResult::None => {
-            // Spring-forward DST gap: move forward one hour to the next valid local instant.
-            let shifted = dt + Duration::hours(1);
-            London.from_local_datetime(&shifted).earliest()?.timestamp()
-        }
-    };
 
-    let mut event = StopTimeEvent::default();
-    event.time = Some(ts);
-    // Remove uncertainty for now
+    let actual = f.actual();
+    let time_str = actual.or(f.et.as_deref())?;
+    let ts = london_timestamp(clock.resolve(time_str)?)?;
+
+    let mut event = StopTimeEvent {
+        time: Some(ts),
+        ..Default::default()
+    };
+    if let Some(scheduled) = scheduled {
+        event.scheduled_time = Some(scheduled);
+        event.delay = Some((ts - scheduled) as i32);
+    }
+    // Actuals are certain; estimates leave uncertainty unset (unknown)
+    if actual.is_some() {
+        event.uncertainty = Some(0);
+    }
     Some(event)
 }
 
+// GTFS stop_times count from noon minus 12h local time on the service day
+fn service_day_timestamp(date: NaiveDate, secs: u32) -> Option<i64> {
+    let noon = London
+        .from_local_datetime(&date.and_hms_opt(12, 0, 0)?)
+        .earliest()?;
+    Some(noon.timestamp() - 12 * 3600 + secs as i64)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::parse_time;
-    use crate::darwin_types::Forecast;
-    use chrono::{TimeZone, Utc};
+    use super::{
+        OccupancyStatus, StopScheduleRelationship, TripScheduleRelationship,
+        build_stop_time_update, is_fully_cancelled, parse_time, process_pmap,
+    };
+    use crate::darwin_time::DarwinClock;
+    use crate::darwin_types::{Forecast, Location, Pport, Schedule};
+    use crate::state::AppState;
+    use crate::static_data::{GTFSManager, GtfsData};
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use gtfs_structures::{CalendarDate, Exception, Trip};
+    use quick_xml::de::from_str;
+
+    // State whose static GTFS knows stops but no trips
+    fn state_with_stops(tiplocs: &[(&str, &str)]) -> AppState {
+        let mut data = GtfsData::default();
+        for (tiploc, stop_id) in tiplocs {
+            data.tiploc_map.insert((*tiploc).into(), (*stop_id).into());
+        }
+        let mut state = AppState::new(String::new());
+        state.gtfs = GTFSManager::from_data(data);
+        state
+    }
 
     #[test]
     fn parse_time_uses_london_timezone_in_winter() {
         let f = Forecast {
             et: Some("12:00".into()),
-            at: None,
+            ..Default::default()
         };
 
-        let event = parse_time(&f, "2026-01-15").expect("expected parsed event");
+        let clock = DarwinClock::new(NaiveDate::from_ymd_opt(2026, 1, 15).unwrap());
+        let event = parse_time(&f, &clock, None).expect("expected parsed event");
         let expected = Utc
             .with_ymd_and_hms(2026, 1, 15, 12, 0, 0)
             .single()
@@ -402,10 +1320,11 @@ mod tests {
     fn parse_time_uses_london_timezone_in_summer_bst() {
         let f = Forecast {
             et: Some("12:00".into()),
-            at: None,
+            ..Default::default()
         };
 
-        let event = parse_time(&f, "2026-07-15").expect("expected parsed event");
+        let clock = DarwinClock::new(NaiveDate::from_ymd_opt(2026, 7, 15).unwrap());
+        let event = parse_time(&f, &clock, None).expect("expected parsed event");
         // 12:00 in London during BST is 11:00 UTC.
         let expected = Utc
             .with_ymd_and_hms(2026, 7, 15, 11, 0, 0)
@@ -415,4 +1334,543 @@ mod tests {
 
         assert_eq!(event.time, Some(expected));
     }
+
+    #[test]
+    fn fully_cancelled_only_when_every_public_call_is_cancelled() {
+        let part: Schedule = from_str(
+            r#"<schedule rid="R" uid="U" ssd="2026-01-15"><OR tpl="A" ptd="08:00" can="true"/><IP tpl="B" pta="08:20" ptd="08:21"/><DT tpl="C" pta="08:40" can="true"/></schedule>"#,
+        )
+        .unwrap();
+        assert!(!is_fully_cancelled(&part));
+
+        // Operational calls don't keep a trip alive
+        let full: Schedule = from_str(
+            r#"<schedule rid="R" uid="U" ssd="2026-01-15"><OR tpl="A" ptd="08:00" can="true"/><OPIP tpl="B" wta="08:20" wtd="08:21"/><DT tpl="C" pta="08:40" can="true"/></schedule>"#,
+        )
+        .unwrap();
+        assert!(is_fully_cancelled(&full));
+
+        let deleted: Schedule = from_str(
+            r#"<schedule rid="R" uid="U" ssd="2026-01-15" deleted="true"><OR tpl="A" ptd="08:00"/><DT tpl="C" pta="08:40"/></schedule>"#,
+        )
+        .unwrap();
+        assert!(is_fully_cancelled(&deleted));
+
+        // The daily timetable cancels whole journeys
+        let journey: Schedule = from_str(
+            r#"<Journey rid="R" uid="U" ssd="2026-01-15" can="true"><OR tpl="A" ptd="08:00"/><DT tpl="C" pta="08:40"/></Journey>"#,
+        )
+        .unwrap();
+        assert!(is_fully_cancelled(&journey));
+    }
+
+    #[test]
+    fn schedule_missing_from_static_gtfs_is_published_as_new_trip() {
+        let mut data = GtfsData::default();
+        for (tiploc, stop_id) in [("PADTON", "PAD"), ("RDNGSTN", "RDG"), ("OXFD", "OXF")] {
+            data.tiploc_map.insert(tiploc.into(), stop_id.into());
+        }
+        data.toc_routes.insert("GW".into(), "GW_R1".into());
+        let mut state = AppState::new(String::new());
+        state.gtfs = GTFSManager::from_data(data);
+
+        let schedule_xml = r#"<Pport ts="T" version="16.0"><uR updateOrigin="CIS"><schedule rid="202601157654321" uid="V54321" trainId="5Z99" ssd="2026-01-15" toc="GW"><OR tpl="PADTON" ptd="10:00" wtd="10:00"/><PP tpl="SLOUGH" wtp="10:15"/><IP tpl="RDNGSTN" pta="10:25" ptd="10:27" wta="10:25" wtd="10:27"/><DT tpl="OXFD" pta="10:55" wta="10:55"/></schedule></uR></Pport>"#;
+        process_pmap(from_str::<Pport>(schedule_xml).unwrap(), &state);
+
+        let trip_id = "V54321_2026-01-15";
+        assert_eq!(
+            state
+                .rid_to_trip_id
+                .get("202601157654321")
+                .unwrap()
+                .as_str(),
+            trip_id
+        );
+        {
+            let entity = state.trip_updates.get(trip_id).expect("added trip");
+            let tu = entity.trip_update.as_ref().unwrap();
+            assert_eq!(
+                tu.trip.schedule_relationship,
+                Some(TripScheduleRelationship::New as i32)
+            );
+            assert_eq!(tu.trip.start_time.as_deref(), Some("10:00:00"));
+            assert_eq!(tu.trip.route_id.as_deref(), Some("GW_R1"));
+            let stops: Vec<_> = tu
+                .stop_time_update
+                .iter()
+                .map(|u| (u.stop_id.as_deref().unwrap(), u.stop_sequence.unwrap()))
+                .collect();
+            assert_eq!(stops, vec![("PAD", 1), ("RDG", 2), ("OXF", 3)]);
+        }
+
+        // Forecasts for the same RID overlay the booked times
+        let ts_xml = r#"<Pport ts="T" version="16.0"><uR updateOrigin="TD"><TS rid="202601157654321" uid="V54321" ssd="2026-01-15"><Location tpl="RDNGSTN" wta="10:25" wtd="10:27" pta="10:25" ptd="10:27"><arr et="10:31"/><dep et="10:33"/></Location></TS></uR></Pport>"#;
+        process_pmap(from_str::<Pport>(ts_xml).unwrap(), &state);
+
+        let entity = state.trip_updates.get(trip_id).unwrap();
+        let tu = entity.trip_update.as_ref().unwrap();
+        let reading = &tu.stop_time_update[1];
+        let expected = Utc
+            .with_ymd_and_hms(2026, 1, 15, 10, 31, 0)
+            .single()
+            .unwrap()
+            .timestamp();
+        assert_eq!(reading.stop_sequence, Some(2));
+        assert_eq!(reading.arrival.as_ref().unwrap().time, Some(expected));
+        assert_eq!(reading.arrival.as_ref().unwrap().delay, Some(360));
+        assert_eq!(reading.departure.as_ref().unwrap().delay, Some(360));
+        assert_eq!(tu.stop_time_update.len(), 3);
+    }
+
+    #[test]
+    fn running_static_trip_gets_no_entity_until_darwin_changes_it() {
+        let mut data = GtfsData::default();
+        for (tiploc, stop_id) in [("PADTON", "PAD"), ("RDNGSTN", "RDG"), ("OXFD", "OXF")] {
+            data.tiploc_map.insert(tiploc.into(), stop_id.into());
+        }
+        data.uid_index
+            .insert("C12345".into(), vec!["T1".to_string()]);
+        data.trips.insert(
+            "T1".into(),
+            Trip {
+                id: "T1".to_string(),
+                service_id: "S1".to_string(),
+                ..Default::default()
+            },
+        );
+        data.calendar_dates.insert(
+            "S1".into(),
+            vec![CalendarDate {
+                service_id: "S1".to_string(),
+                date: NaiveDate::from_ymd_opt(2026, 1, 15).unwrap(),
+                exception_type: Exception::Added,
+            }],
+        );
+        let mut state = AppState::new(String::new());
+        state.gtfs = GTFSManager::from_data(data);
+
+        let schedule = |rdg: &str| {
+            format!(
+                r#"<Pport ts="T" version="16.0"><uR updateOrigin="CIS"><schedule rid="202601151234567" uid="C12345" trainId="1A23" ssd="2026-01-15" toc="GW"><OR tpl="PADTON" ptd="10:00" wtd="10:00"/><IP tpl="RDNGSTN" pta="10:25" ptd="10:27" wta="10:25" wtd="10:27"{}/><DT tpl="OXFD" pta="10:55" wta="10:55"/></schedule></uR></Pport>"#,
+                rdg
+            )
+        };
+        process_pmap(from_str::<Pport>(&schedule("")).unwrap(), &state);
+        assert_eq!(
+            state
+                .rid_to_trip_id
+                .get("202601151234567")
+                .unwrap()
+                .as_str(),
+            "T1"
+        );
+        assert!(state.trip_updates.is_empty());
+
+        process_pmap(
+            from_str::<Pport>(&schedule(r#" can="true""#)).unwrap(),
+            &state,
+        );
+        {
+            let entity = state.trip_updates.get("T1").expect("skipped call");
+            let tu = entity.trip_update.as_ref().unwrap();
+            assert_eq!(tu.stop_time_update.len(), 1);
+            assert_eq!(tu.stop_time_update[0].stop_id.as_deref(), Some("RDG"));
+        }
+
+        // Reinstated, so there's nothing left to publish
+        process_pmap(from_str::<Pport>(&schedule("")).unwrap(), &state);
+        assert!(state.trip_updates.is_empty());
+    }
+
+    #[test]
+    fn stop_time_event_delay_prefers_public_then_working_time() {
+        let loc: Location = from_str(
+            r#"<Location tpl="RDNGSTN" wta="10:24" pta="10:25" wtd="10:26"><arr et="10:32"/><dep et="10:33"/></Location>"#,
+        )
+        .unwrap();
+        let date = NaiveDate::from_ymd_opt(2026, 1, 15).unwrap();
+        // Static times lose to Darwin's own
+        let stu = build_stop_time_update(
+            &loc,
+            "RDG",
+            Some(2),
+            &DarwinClock::new(date),
+            date,
+            Some((Some(0), Some(0))),
+        );
+
+        let arrival = stu.arrival.unwrap();
+        assert_eq!(arrival.delay, Some(7 * 60), "against pta, not wta");
+        assert_eq!(
+            arrival.scheduled_time.unwrap() + 7 * 60,
+            arrival.time.unwrap()
+        );
+
+        // No ptd, so the working departure is the schedule
+        assert_eq!(stu.departure.unwrap().delay, Some(7 * 60));
+    }
+
+    #[test]
+    fn overnight_forecast_lands_on_the_next_day() {
+        let state = state_with_stops(&[("EUSTON", "EUS"), ("CREWE", "CRE")]);
+
+        let schedule_xml = r#"<Pport ts="T" version="16.0"><uR updateOrigin="CIS"><schedule rid="202601151111111" uid="X11111" trainId="1S99" ssd="2026-01-15" toc="VT"><OR tpl="EUSTON" ptd="23:30" wtd="23:30"/><DT tpl="CREWE" pta="01:10" wta="01:09:30"/></schedule></uR></Pport>"#;
+        process_pmap(from_str::<Pport>(schedule_xml).unwrap(), &state);
+
+        let ts_xml = r#"<Pport ts="T" version="16.0"><uR updateOrigin="TD"><TS rid="202601151111111" uid="X11111" ssd="2026-01-15"><Location tpl="CREWE" wta="01:09:30" pta="01:10"><arr et="01:16"/></Location></TS></uR></Pport>"#;
+        process_pmap(from_str::<Pport>(ts_xml).unwrap(), &state);
+
+        let entity = state.trip_updates.get("X11111_2026-01-15").unwrap();
+        let crewe = &entity.trip_update.as_ref().unwrap().stop_time_update[1];
+        let arrival = crewe.arrival.as_ref().unwrap();
+        let expected = Utc
+            .with_ymd_and_hms(2026, 1, 16, 1, 16, 0)
+            .single()
+            .unwrap()
+            .timestamp();
+        assert_eq!(arrival.time, Some(expected));
+        assert_eq!(arrival.delay, Some(6 * 60));
+    }
+
+    #[test]
+    fn delayed_and_removed_actuals_are_not_reported_as_certain() {
+        let clock = DarwinClock::new(NaiveDate::from_ymd_opt(2026, 1, 15).unwrap());
+        let scheduled = Utc
+            .with_ymd_and_hms(2026, 1, 15, 12, 0, 0)
+            .single()
+            .unwrap()
+            .timestamp();
+
+        let delayed: Forecast =
+            from_str(r#"<arr et="12:01" etmin="12:01" delayed="true"/>"#).unwrap();
+        assert!(parse_time(&delayed, &clock, Some(scheduled)).is_none());
+        let unknown: Forecast = from_str(r#"<arr etUnknown="true"/>"#).unwrap();
+        assert!(parse_time(&unknown, &clock, None).is_none());
+
+        let actual: Forecast = from_str(r#"<arr at="12:04"/>"#).unwrap();
+        let event = parse_time(&actual, &clock, Some(scheduled)).unwrap();
+        assert_eq!(event.delay, Some(240));
+        assert_eq!(event.uncertainty, Some(0));
+
+        // atRemoved reverts to the estimate
+        let removed: Forecast =
+            from_str(r#"<arr et="12:06" at="12:04" atRemoved="true"/>"#).unwrap();
+        let event = parse_time(&removed, &clock, Some(scheduled)).unwrap();
+        assert_eq!(event.delay, Some(360));
+        assert_eq!(event.uncertainty, None);
+    }
+
+    #[test]
+    fn unknown_delays_leave_the_event_out_and_are_flagged() {
+        let state = state_with_stops(&[("EUSTON", "EUS"), ("CREWE", "CRE")]);
+        let schedule_xml = r#"<Pport ts="T" version="16.0"><uR updateOrigin="CIS"><schedule rid="202601151111111" uid="X11111" trainId="1S99" ssd="2026-01-15" toc="VT"><OR tpl="EUSTON" ptd="10:00" wtd="10:00"/><DT tpl="CREWE" pta="11:40" wta="11:40"/></schedule></uR></Pport>"#;
+        process_pmap(from_str::<Pport>(schedule_xml).unwrap(), &state);
+        let ts = |forecast: &str| {
+            format!(
+                r#"<Pport ts="T" version="16.0"><uR updateOrigin="TD"><TS rid="202601151111111" uid="X11111" ssd="2026-01-15"><Location tpl="CREWE" wta="11:40" pta="11:40">{}</Location></TS></uR></Pport>"#,
+                forecast
+            )
+        };
+        let crewe = |state: &AppState| {
+            let entity = state.trip_updates.get("X11111_2026-01-15").unwrap();
+            entity.trip_update.as_ref().unwrap().stop_time_update[1].clone()
+        };
+
+        process_pmap(
+            from_str::<Pport>(&ts(r#"<arr et="11:40" delayed="true"/>"#)).unwrap(),
+            &state,
+        );
+        let stu = crewe(&state);
+        assert!(stu.arrival.is_none());
+        assert_eq!(
+            stu.schedule_relationship,
+            Some(StopScheduleRelationship::NoData as i32)
+        );
+        let flagged = state.unknown_delays.get("X11111_2026-01-15").unwrap()[0].clone();
+        assert_eq!(flagged.stop_id, "CRE");
+        assert!(flagged.unknown_delay);
+
+        // A real estimate clears the flag
+        process_pmap(
+            from_str::<Pport>(&ts(r#"<arr et="11:52"/>"#)).unwrap(),
+            &state,
+        );
+        let stu = crewe(&state);
+        assert_eq!(stu.arrival.unwrap().delay, Some(12 * 60));
+        assert_eq!(stu.schedule_relationship, None);
+        assert!(!state.unknown_delays.get("X11111_2026-01-15").unwrap()[0].unknown_delay);
+    }
+
+    #[test]
+    fn formation_loading_joins_formation_by_fid() {
+        let state = state_with_stops(&[]);
+        state
+            .rid_to_trip_id
+            .insert("202601158001234".into(), "trip_1".into());
+
+        // Loading first: it waits for the formation
+        let loading_xml = r#"<Pport ts="T" version="16.0"><uR updateOrigin="CIS"><formationLoading fid="202601158001234-001" rid="202601158001234" tpl="HTCH" wtd="08:30"><loading coachNumber="A">12</loading><loading coachNumber="C">90</loading></formationLoading></uR></Pport>"#;
+        process_pmap(from_str::<Pport>(loading_xml).unwrap(), &state);
+        assert!(!state.trip_updates.contains_key("trip_1_VP"));
+
+        let formation_xml = r#"<Pport ts="T" version="16.0"><uR updateOrigin="CIS"><scheduleFormations rid="202601158001234"><formation fid="202601158001234-001"><coaches><coach coachNumber="A" coachClass="First"/><coach coachNumber="B" coachClass="Standard"/><coach coachNumber="C" coachClass="Standard"/></coaches></formation></scheduleFormations></uR></Pport>"#;
+        process_pmap(from_str::<Pport>(formation_xml).unwrap(), &state);
+
+        let entity = state.trip_updates.get("trip_1_VP").unwrap();
+        let vp = entity.vehicle.as_ref().unwrap();
+        let coaches: Vec<_> = vp
+            .multi_carriage_details
+            .iter()
+            .map(|c| {
+                (
+                    c.label.as_deref(),
+                    c.carriage_sequence,
+                    c.occupancy_percentage,
+                    c.occupancy_status,
+                )
+            })
+            .collect();
+        assert_eq!(
+            coaches,
+            vec![
+                (
+                    Some("A"),
+                    Some(1),
+                    Some(12),
+                    Some(OccupancyStatus::ManySeatsAvailable as i32)
+                ),
+                (
+                    Some("B"),
+                    Some(2),
+                    Some(-1),
+                    Some(OccupancyStatus::NoDataAvailable as i32)
+                ),
+                (
+                    Some("C"),
+                    Some(3),
+                    Some(90),
+                    Some(OccupancyStatus::StandingRoomOnly as i32)
+                ),
+            ]
+        );
+        // Whole train averages the coaches that reported
+        assert_eq!(vp.occupancy_percentage, Some(51));
+        assert_eq!(
+            vp.occupancy_status,
+            Some(OccupancyStatus::FewSeatsAvailable as i32)
+        );
+    }
+
+    #[test]
+    fn divide_propagates_forecast_and_labels_the_portion_coaches() {
+        let mut data = GtfsData::default();
+        for (tiploc, stop_id) in [
+            ("KNGX", "KGX"),
+            ("HTCH", "HIT"),
+            ("CAMB", "CBG"),
+            ("PBRO", "PBO"),
+        ] {
+            data.tiploc_map.insert(tiploc.into(), stop_id.into());
+        }
+        data.stop_names.insert("CBG".into(), "Cambridge".into());
+        let mut state = AppState::new(String::new());
+        state.gtfs = GTFSManager::from_data(data);
+
+        // Main train runs to Peterborough and divides at Hitchin; the rear portion goes to Cambridge
+        let setup_xml = r#"<Pport ts="T" version="16.0"><uR updateOrigin="CIS"><schedule rid="202601151000001" uid="M00001" ssd="2026-01-15" toc="GN"><OR tpl="KNGX" ptd="10:00" wtd="10:00"/><IP tpl="HTCH" pta="10:30" ptd="10:35" wta="10:30" wtd="10:35"/><DT tpl="PBRO" pta="11:00" wta="11:00"/></schedule><schedule rid="202601151000002" uid="P00002" ssd="2026-01-15" toc="GN"><OR tpl="HTCH" ptd="10:35" wtd="10:35"/><DT tpl="CAMB" pta="11:10" wta="11:10"/></schedule><association tiploc="HTCH" category="VV"><main rid="202601151000001" wtd="10:35"/><assoc rid="202601151000002" wtd="10:35"/></association><scheduleFormations rid="202601151000001"><formation fid="202601151000001-001"><coaches><coach coachNumber="A"/><coach coachNumber="B"/><coach coachNumber="C"/><coach coachNumber="D"/></coaches></formation></scheduleFormations><scheduleFormations rid="202601151000002"><formation fid="202601151000002-001"><coaches><coach coachNumber="C"/><coach coachNumber="D"/></coaches></formation></scheduleFormations></uR></Pport>"#;
+        process_pmap(from_str::<Pport>(setup_xml).unwrap(), &state);
+
+        let portion_departure = |state: &AppState| {
+            let entity = state.trip_updates.get("P00002_2026-01-15").unwrap();
+            entity
+                .trip_update
+                .as_ref()
+                .unwrap()
+                .stop_time_update
+                .iter()
+                .find(|u| u.stop_id.as_deref() == Some("HIT"))
+                .and_then(|u| u.departure)
+                .and_then(|d| d.delay)
+        };
+
+        let ts_xml = |rid: &str, uid: &str, et: &str| {
+            format!(
+                r#"<Pport ts="T" version="16.0"><uR updateOrigin="TD"><TS rid="{}" uid="{}" ssd="2026-01-15"><Location tpl="HTCH" wta="10:30" wtd="10:35" pta="10:30" ptd="10:35"><dep et="{}"/></Location></TS></uR></Pport>"#,
+                rid, uid, et
+            )
+        };
+
+        // The portion has no TS, so it follows the main train out of Hitchin
+        process_pmap(
+            from_str::<Pport>(&ts_xml("202601151000001", "M00001", "10:45")).unwrap(),
+            &state,
+        );
+        assert_eq!(portion_departure(&state), Some(600));
+
+        // Once the portion has its own TS, the main train no longer overrides it
+        process_pmap(
+            from_str::<Pport>(&ts_xml("202601151000002", "P00002", "10:40")).unwrap(),
+            &state,
+        );
+        process_pmap(
+            from_str::<Pport>(&ts_xml("202601151000001", "M00001", "10:50")).unwrap(),
+            &state,
+        );
+        assert_eq!(portion_departure(&state), Some(300));
+
+        // Coaches shared with the portion say where they go
+        let entity = state.trip_updates.get("M00001_2026-01-15_VP").unwrap();
+        let labels: Vec<_> = entity
+            .vehicle
+            .as_ref()
+            .unwrap()
+            .multi_carriage_details
+            .iter()
+            .map(|c| c.label.clone().unwrap())
+            .collect();
+        assert_eq!(
+            labels,
+            vec!["A", "B", "C (to Cambridge)", "D (to Cambridge)"]
+        );
+        drop(entity);
+
+        assert_eq!(state.associations.get("202601151000002").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn late_running_and_cancellation_reasons_become_trip_alerts() {
+        let state = state_with_stops(&[("PADTON", "PAD"), ("OXFD", "OXF")]);
+        {
+            let mut reference = state.reference.write().unwrap();
+            reference.late_running_reasons.insert(
+                100,
+                "This train has been delayed by a broken down train".into(),
+            );
+            reference.cancellation_reasons.insert(
+                104,
+                "This train has been cancelled because of a fault on this train".into(),
+            );
+        }
+        let schedule = |cancelled: &str| {
+            format!(
+                r#"<Pport ts="T" version="16.0"><uR updateOrigin="CIS"><schedule rid="202601157654321" uid="V54321" ssd="2026-01-15" toc="GW"><OR tpl="PADTON" ptd="10:00" wtd="10:00"{0}/><DT tpl="OXFD" pta="10:55" wta="10:55"{0}/>{1}</schedule></uR></Pport>"#,
+                cancelled,
+                if cancelled.is_empty() {
+                    ""
+                } else {
+                    r#"<cancelReason>104</cancelReason>"#
+                }
+            )
+        };
+        let ts = |late_reason: &str| {
+            format!(
+                r#"<Pport ts="T" version="16.0"><uR updateOrigin="TD"><TS rid="202601157654321" uid="V54321" ssd="2026-01-15">{}<Location tpl="PADTON" wtd="10:00" ptd="10:00"><dep et="10:20"/></Location></TS></uR></Pport>"#,
+                late_reason
+            )
+        };
+        let header = |state: &AppState, key: &str| {
+            let entity = state.alerts.get(key)?;
+            let alert = entity.alert.as_ref()?;
+            Some(alert.header_text.as_ref()?.translation[0].text.clone())
+        };
+
+        process_pmap(from_str::<Pport>(&schedule("")).unwrap(), &state);
+        process_pmap(
+            from_str::<Pport>(&ts(r#"<LateReason near="true">100</LateReason>"#)).unwrap(),
+            &state,
+        );
+        assert_eq!(
+            header(&state, "LR_202601157654321").as_deref(),
+            Some("This train has been delayed by a broken down train")
+        );
+        let entity = state.alerts.get("LR_202601157654321").unwrap();
+        let trip = entity.alert.as_ref().unwrap().informed_entity[0]
+            .trip
+            .clone()
+            .unwrap();
+        assert_eq!(trip.trip_id.as_deref(), Some("V54321_2026-01-15"));
+        assert_eq!(trip.start_date.as_deref(), Some("20260115"));
+        drop(entity);
+
+        // Darwin drops the reason once it no longer applies
+        process_pmap(from_str::<Pport>(&ts("")).unwrap(), &state);
+        assert!(!state.alerts.contains_key("LR_202601157654321"));
+
+        process_pmap(
+            from_str::<Pport>(&schedule(r#" can="true""#)).unwrap(),
+            &state,
+        );
+        assert_eq!(
+            header(&state, "CR_202601157654321").as_deref(),
+            Some("This train has been cancelled because of a fault on this train")
+        );
+    }
+
+    #[test]
+    fn timetable_journey_stands_in_for_a_missing_schedule() {
+        let state = state_with_stops(&[("PADTON", "PAD"), ("RDNGSTN", "RDG"), ("OXFD", "OXF")]);
+        let timetable = r#"<?xml version="1.0" encoding="utf-8"?>
+<PportTimetable timetableID="20260115020500" xmlns="http://www.thalesgroup.com/rtti/XmlTimetable/v8">
+  <Journey rid="202601157654321" uid="V54321" trainId="1P21" ssd="2026-01-15" toc="GW">
+    <OR tpl="PADTON" act="TB" ptd="10:00" wtd="10:00" />
+    <PP tpl="SLOUGH" wtp="10:15" />
+    <IP tpl="RDNGSTN" act="T " pta="10:25" ptd="10:27" wta="10:25" wtd="10:27" />
+    <DT tpl="OXFD" act="TF" pta="10:55" wta="10:55" />
+  </Journey>
+  <Journey rid="202601157654322" uid="V54322" trainId="1P22" ssd="2026-01-15" toc="GW" qtrain="true">
+    <OR tpl="RDNGSTN" act="TB" ptd="10:30" wtd="10:30" />
+    <DT tpl="OXFD" act="TF" pta="10:58" wta="10:58" />
+  </Journey>
+  <Association tiploc="RDNGSTN" category="VV">
+    <main rid="202601157654321" wta="10:25" wtd="10:27" />
+    <assoc rid="202601157654322" wtd="10:30" />
+  </Association>
+</PportTimetable>"#;
+        state.gtfs.load_timetable(timetable.as_bytes()).unwrap();
+        assert!(
+            state
+                .gtfs
+                .timetable_journey("202601157654322")
+                .unwrap()
+                .qtrain
+        );
+
+        // A TS with no schedule from the Push Port, and no static trip for the UID
+        let ts_xml = r#"<Pport ts="T" version="16.0"><uR updateOrigin="TD"><TS rid="202601157654321" uid="V54321" ssd="2026-01-15"><Location tpl="RDNGSTN" wta="10:25" wtd="10:27" pta="10:25" ptd="10:27"><arr et="10:31"/><dep et="10:33"/></Location></TS></uR></Pport>"#;
+        process_pmap(from_str::<Pport>(ts_xml).unwrap(), &state);
+
+        assert!(state.schedules.contains_key("202601157654321"));
+        assert_eq!(state.associations.get("202601157654322").unwrap().len(), 1);
+        let entity = state
+            .trip_updates
+            .get("V54321_2026-01-15")
+            .expect("added trip");
+        let tu = entity.trip_update.as_ref().unwrap();
+        assert_eq!(
+            tu.trip.schedule_relationship,
+            Some(TripScheduleRelationship::New as i32)
+        );
+        // Booked times from the timetable, with the forecast on top
+        let stops: Vec<_> = tu
+            .stop_time_update
+            .iter()
+            .map(|u| (u.stop_id.as_deref().unwrap(), u.stop_sequence.unwrap()))
+            .collect();
+        assert_eq!(stops, vec![("PAD", 1), ("RDG", 2), ("OXF", 3)]);
+        assert_eq!(
+            tu.stop_time_update[1].arrival.as_ref().unwrap().delay,
+            Some(360)
+        );
+        drop(entity);
+
+        // The Q-train portion isn't published on the main train's forecast alone
+        assert!(!state.schedules.contains_key("202601157654322"));
+        assert!(!state.trip_updates.contains_key("V54322_2026-01-15"));
+
+        // Its own TS activates it
+        let ts_xml = r#"<Pport ts="T" version="16.0"><uR updateOrigin="TD"><TS rid="202601157654322" uid="V54322" ssd="2026-01-15"><Location tpl="OXFD" wta="10:58" pta="10:58"><arr et="11:02"/></Location></TS></uR></Pport>"#;
+        process_pmap(from_str::<Pport>(ts_xml).unwrap(), &state);
+        assert!(state.schedules.contains_key("202601157654322"));
+        assert!(state.trip_updates.contains_key("V54322_2026-01-15"));
+    }
 }
@@ -0,0 +1,266 @@
+// Records raw Darwin traffic so it can be replayed offline (see replay.rs).
+// Each MESSAGE is written as its STOMP frame plus a recorded-at header (Unix ms), gzipped
+// as a member of its own, so a recording cut short by a crash is readable up to its last
+// whole frame. A new file is started every `rotate_every`, and the oldest recordings are
+// then deleted so no more than `keep_files` remain (0 keeps them all).
+
+use crate::stomp::{Frame, Headers, read_frame};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use flate2::Compression;
+use flate2::bufread::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError, sync_channel};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+pub const EXTENSION: &str = "stomp.gz";
+
+// Frames waiting to be written before the recorder starts skipping them
+const BACKLOG: usize = 4096;
+
+// Disabled (records nothing) unless started on a directory
+#[derive(Default)]
+pub struct Recorder {
+    frames: Option<SyncSender<Frame>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Recorder {
+    pub fn start(
+        dir: impl Into<PathBuf>,
+        rotate_every: Duration,
+        keep_files: usize,
+    ) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let (frames, received) = sync_channel(BACKLOG);
+        let writer = std::thread::Builder::new()
+            .name("recorder".to_string())
+            .spawn(move || write_frames(received, &dir, rotate_every, keep_files))?;
+        Ok(Self {
+            frames: Some(frames),
+            writer: Some(writer),
+        })
+    }
+
+    // Queue a frame for writing. Never blocks the reader: if the disk can't keep up the
+    // frame is left out of the recording, not held back from processing.
+    pub fn record(&self, headers: &Headers, body: &[u8]) {
+        let Some(frames) = &self.frames else {
+            return;
+        };
+        let headers = headers
+            .clone()
+            .with("recorded-at", Utc::now().timestamp_millis().to_string());
+        match frames.try_send(Frame::Message(headers, body.to_vec())) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => eprintln!("Recorder is behind, skipping a frame"),
+            Err(TrySendError::Disconnected(_)) => eprintln!("Recorder has stopped"),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    // Let the writer finish what's queued
+    fn drop(&mut self) {
+        self.frames.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn write_frames(frames: Receiver<Frame>, dir: &Path, rotate_every: Duration, keep_files: usize) {
+    let mut current: Option<(File, Instant)> = None;
+    for frame in frames {
+        if current
+            .as_ref()
+            .is_none_or(|(_, opened)| opened.elapsed() >= rotate_every)
+        {
+            let path = dir.join(format!(
+                "darwin-{}.{}",
+                Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+                EXTENSION
+            ));
+            current = match File::create(&path) {
+                Ok(file) => Some((file, Instant::now())),
+                Err(e) => {
+                    eprintln!("Failed to create recording {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            if keep_files > 0 {
+                prune(dir, keep_files);
+            }
+        }
+        let Some((file, _)) = &mut current else {
+            continue;
+        };
+        let mut member = GzEncoder::new(file, Compression::fast());
+        if let Err(e) = member
+            .write_all(&frame.encode())
+            .and_then(|_| member.try_finish())
+        {
+            eprintln!("Failed to write recording: {}", e);
+        }
+    }
+}
+
+// Delete the oldest recordings in `dir` (by modification time, then name) until `keep`
+// remain
+fn prune(dir: &Path, keep: usize) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to list recordings in {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    let suffix = format!(".{}", EXTENSION);
+    let mut recordings: Vec<_> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if !path.file_name()?.to_str()?.ends_with(&suffix) {
+                return None;
+            }
+            let modified = path.metadata().and_then(|m| m.modified()).ok()?;
+            Some((modified, path))
+        })
+        .collect();
+    recordings.sort();
+    let excess = recordings.len().saturating_sub(keep);
+    for (_, path) in &recordings[..excess] {
+        match std::fs::remove_file(path) {
+            Ok(()) => println!("Deleted old recording {}", path.display()),
+            Err(e) => eprintln!("Failed to delete recording {}: {}", path.display(), e),
+        }
+    }
+}
+
+// A recorded MESSAGE
+pub struct Recorded {
+    // Unix milliseconds
+    pub recorded_at: i64,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+// Every frame in a recording, in the order received
+pub async fn read(path: &Path) -> Result<Vec<Recorded>> {
+    let compressed = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut bytes = Vec::new();
+    // What inflated before a torn final member is still good
+    if let Err(e) = MultiGzDecoder::new(compressed.as_slice()).read_to_end(&mut bytes) {
+        eprintln!("{} ends early: {}", path.display(), e);
+    }
+
+    let mut frames = Vec::new();
+    let mut reader = bytes.as_slice();
+    while !reader.is_empty() {
+        match read_frame(&mut reader).await {
+            Ok(Frame::Message(headers, body)) => frames.push(Recorded {
+                recorded_at: headers
+                    .get("recorded-at")
+                    .and_then(|t| t.parse().ok())
+                    .unwrap_or_default(),
+                headers,
+                body,
+            }),
+            Ok(other) => eprintln!("Skipping {} frame in {}", other.command(), path.display()),
+            Err(e) => {
+                eprintln!("{} ends with a partial frame: {}", path.display(), e);
+                break;
+            }
+        }
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn frames_survive_a_torn_final_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = Recorder::start(dir.path(), Duration::from_secs(3600), 0).unwrap();
+        let gzipped_body = [0x1f, 0x8b, 0x00, b'\n', 0x00];
+        recorder.record(
+            &Headers::new().with("message-id", "ID:1"),
+            b"<Pport ts=\"T\"/>",
+        );
+        recorder.record(&Headers::new().with("message-id", "ID:2"), &gzipped_body);
+        drop(recorder);
+
+        let files: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].to_str().unwrap().ends_with(".stomp.gz"));
+
+        let frames = read(&files[0]).await.unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].headers.get("message-id"), Some("ID:1"));
+        assert_eq!(frames[0].body, b"<Pport ts=\"T\"/>");
+        assert_eq!(frames[1].body, gzipped_body);
+        assert!(frames[0].recorded_at > 0 && frames[0].recorded_at <= frames[1].recorded_at);
+
+        // Cut off just after the second member's gzip header
+        let whole = std::fs::read(&files[0]).unwrap();
+        let second = whole
+            .windows(3)
+            .skip(1)
+            .position(|w| w == [0x1f, 0x8b, 0x08])
+            .unwrap()
+            + 1;
+        std::fs::write(&files[0], &whole[..second + 12]).unwrap();
+        let frames = read(&files[0]).await.unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].headers.get("message-id"), Some("ID:1"));
+    }
+    #[test]
+    fn rotation_keeps_only_the_newest_recordings() {
+        let dir = tempfile::tempdir().unwrap();
+        let touch = |name: &str, age_secs: u64| {
+            let path = dir.path().join(name);
+            let file = File::create(&path).unwrap();
+            file.set_modified(std::time::SystemTime::now() - Duration::from_secs(age_secs))
+                .unwrap();
+            path
+        };
+        let oldest = touch("darwin-20260113T090000.000Z.stomp.gz", 3 * 3600);
+        let older = touch("darwin-20260113T100000.000Z.stomp.gz", 2 * 3600);
+        let newer = touch("darwin-20260113T110000.000Z.stomp.gz", 3600);
+        let notes = touch("notes.txt", 4 * 3600);
+
+        // The writer prunes right after opening a new file
+        let recorder = Recorder::start(dir.path(), Duration::from_secs(3600), 2).unwrap();
+        recorder.record(&Headers::new().with("message-id", "ID:1"), b"<Pport/>");
+        drop(recorder);
+
+        assert!(!oldest.exists());
+        assert!(!older.exists());
+        assert!(newer.exists());
+        assert!(notes.exists(), "only recordings are deleted");
+        let recordings = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .path()
+                    .to_str()
+                    .unwrap()
+                    .ends_with(".stomp.gz")
+            })
+            .count();
+        assert_eq!(recordings, 2);
+    }
+}
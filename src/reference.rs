@@ -0,0 +1,283 @@
+// Darwin timetable reference data (PportTimetableRef, rttiCTTReferenceSchema_v3.xsd):
+// locations, operators, late running and cancellation reasons, via texts and CIS sources.
+
+use crate::darwin_types::DisruptionReason;
+use crate::state::AppState;
+use anyhow::{Context, Result};
+use compact_str::CompactString;
+use quick_xml::de::from_reader;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize)]
+pub struct PportTimetableRef {
+    #[serde(rename = "@timetableId")]
+    pub timetable_id: CompactString,
+    #[serde(rename = "LocationRef", default)]
+    pub locations: Vec<LocationRef>,
+    #[serde(rename = "TocRef", default)]
+    pub tocs: Vec<TocRef>,
+    #[serde(rename = "LateRunningReasons", default)]
+    pub late_running_reasons: Reasons,
+    #[serde(rename = "CancellationReasons", default)]
+    pub cancellation_reasons: Reasons,
+    #[serde(rename = "Via", default)]
+    pub vias: Vec<Via>,
+    #[serde(rename = "CISSource", default)]
+    pub cis_sources: Vec<CisSource>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LocationRef {
+    #[serde(rename = "@tpl")]
+    pub tiploc: CompactString,
+    #[serde(rename = "@crs")]
+    pub crs: Option<CompactString>,
+    #[serde(rename = "@toc")]
+    pub toc: Option<CompactString>,
+    #[serde(rename = "@locname")]
+    pub name: CompactString,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TocRef {
+    #[serde(rename = "@toc")]
+    pub toc: CompactString,
+    #[serde(rename = "@tocname")]
+    pub name: CompactString,
+    #[serde(rename = "@url")]
+    pub url: Option<CompactString>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct Reasons {
+    #[serde(rename = "Reason", default)]
+    pub reasons: Vec<Reason>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Reason {
+    #[serde(rename = "@code")]
+    pub code: i32,
+    #[serde(rename = "@reasontext")]
+    pub text: CompactString,
+}
+
+// Via text shown at `at` for trains to `dest` that call at loc1 (then loc2)
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Via {
+    #[serde(rename = "@at")]
+    pub at: CompactString,
+    #[serde(rename = "@dest")]
+    pub dest: CompactString,
+    #[serde(rename = "@loc1")]
+    pub loc1: CompactString,
+    #[serde(rename = "@loc2")]
+    pub loc2: Option<CompactString>,
+    #[serde(rename = "@viatext")]
+    pub text: CompactString,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CisSource {
+    #[serde(rename = "@code")]
+    pub code: CompactString,
+    #[serde(rename = "@name")]
+    pub name: CompactString,
+}
+
+#[derive(Clone, Copy)]
+pub enum ReasonKind {
+    LateRunning,
+    Cancellation,
+}
+
+// The reference file indexed for lookups, and served as JSON on /reference
+#[derive(Default, Serialize)]
+pub struct ReferenceData {
+    pub timetable_id: CompactString,
+    pub locations: HashMap<CompactString, LocationRef>, // TIPLOC -> Location
+    pub tocs: HashMap<CompactString, TocRef>,
+    pub late_running_reasons: HashMap<i32, CompactString>,
+    pub cancellation_reasons: HashMap<i32, CompactString>,
+    pub vias: Vec<Via>,
+    pub cis_sources: HashMap<CompactString, CompactString>,
+}
+
+impl From<PportTimetableRef> for ReferenceData {
+    fn from(file: PportTimetableRef) -> Self {
+        Self {
+            timetable_id: file.timetable_id,
+            locations: file
+                .locations
+                .into_iter()
+                .map(|l| (l.tiploc.clone(), l))
+                .collect(),
+            tocs: file.tocs.into_iter().map(|t| (t.toc.clone(), t)).collect(),
+            late_running_reasons: file
+                .late_running_reasons
+                .reasons
+                .into_iter()
+                .map(|r| (r.code, r.text))
+                .collect(),
+            cancellation_reasons: file
+                .cancellation_reasons
+                .reasons
+                .into_iter()
+                .map(|r| (r.code, r.text))
+                .collect(),
+            vias: file.vias,
+            cis_sources: file
+                .cis_sources
+                .into_iter()
+                .map(|s| (s.code, s.name))
+                .collect(),
+        }
+    }
+}
+
+impl ReferenceData {
+    // Darwin's reason text with its location, e.g.
+    // "This train has been delayed by a broken down train near Hitchin"
+    pub fn reason_text(&self, kind: ReasonKind, reason: &DisruptionReason) -> Option<String> {
+        let code: i32 = reason.code.as_deref()?.trim().parse().ok()?;
+        let text = match kind {
+            ReasonKind::LateRunning => self.late_running_reasons.get(&code),
+            ReasonKind::Cancellation => self.cancellation_reasons.get(&code),
+        }?;
+
+        let location = reason
+            .tiploc
+            .as_deref()
+            .and_then(|tiploc| self.locations.get(tiploc));
+        Some(match location {
+            Some(location) => {
+                let preposition = if reason.near.unwrap_or(false) {
+                    "near"
+                } else {
+                    "at"
+                };
+                format!("{} {} {}", text, preposition, location.name)
+            }
+            None => text.to_string(),
+        })
+    }
+
+    // TIPLOC -> CRS for every location that is a station
+    pub fn crs_codes(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.locations
+            .values()
+            .filter_map(|l| Some((l.tiploc.as_str(), l.crs.as_deref()?)))
+    }
+}
+
+// Load the reference file (DARWIN_REFERENCE, path or URL, gzipped or not) into the state
+pub async fn load(location: &str, state: &AppState) -> Result<()> {
+    let body = crate::snapshot::fetch(location)
+        .await
+        .with_context(|| format!("Failed to fetch reference data {}", location))?;
+    let reference = parse(&body)?;
+
+    state.gtfs.set_crs_codes(reference.crs_codes());
+    println!(
+        "Loaded Darwin reference data {} ({} locations, {} late running and {} cancellation reasons).",
+        reference.timetable_id,
+        reference.locations.len(),
+        reference.late_running_reasons.len(),
+        reference.cancellation_reasons.len()
+    );
+    *state.reference.write().unwrap() = reference;
+    Ok(())
+}
+
+fn parse(body: &[u8]) -> Result<ReferenceData> {
+    let file: PportTimetableRef = from_reader(crate::xml_reader(body))?;
+    Ok(file.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::static_data::{GTFSManager, GtfsData};
+
+    const REF_XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<PportTimetableRef timetableId="20260115020500" xmlns="http://www.thalesgroup.com/rtti/XmlRefData/v3">
+  <LocationRef tpl="HTCH" crs="HIT" toc="GN" locname="Hitchin" />
+  <LocationRef tpl="KNGX" crs="KGX" toc="NR" locname="London Kings Cross" />
+  <LocationRef tpl="KNGXBEL" crs="KGX" toc="NR" locname="London Kings Cross" />
+  <LocationRef tpl="HTCHSJN" locname="Hitchin South Junction" />
+  <TocRef toc="GN" tocname="Great Northern" url="http://www.nationalrail.co.uk/tocs/GN" />
+  <LateRunningReasons>
+    <Reason code="100" reasontext="This train has been delayed by a broken down train" />
+  </LateRunningReasons>
+  <CancellationReasons>
+    <Reason code="100" reasontext="This train has been cancelled because of a broken down train" />
+  </CancellationReasons>
+  <Via at="KGX" dest="CAMB" loc1="HTCH" viatext="via Hitchin" />
+  <CISSource code="at01" name="Network Rail TD" />
+</PportTimetableRef>"#;
+
+    fn reason(code: &str, tiploc: Option<&str>, near: bool) -> DisruptionReason {
+        DisruptionReason {
+            code: Some(code.into()),
+            tiploc: tiploc.map(Into::into),
+            near: Some(near),
+        }
+    }
+
+    #[test]
+    fn reason_codes_become_text() {
+        let reference = parse(REF_XML.as_bytes()).unwrap();
+        assert_eq!(reference.timetable_id, "20260115020500");
+        assert_eq!(reference.tocs["GN"].name, "Great Northern");
+        assert_eq!(reference.vias[0].text, "via Hitchin");
+        assert_eq!(reference.cis_sources["at01"], "Network Rail TD");
+
+        assert_eq!(
+            reference
+                .reason_text(ReasonKind::LateRunning, &reason("100", Some("HTCH"), true))
+                .as_deref(),
+            Some("This train has been delayed by a broken down train near Hitchin")
+        );
+        assert_eq!(
+            reference
+                .reason_text(
+                    ReasonKind::Cancellation,
+                    &reason("100", Some("KNGX"), false)
+                )
+                .as_deref(),
+            Some(
+                "This train has been cancelled because of a broken down train at London Kings Cross"
+            )
+        );
+        assert_eq!(
+            reference
+                .reason_text(ReasonKind::LateRunning, &reason("100", None, false))
+                .as_deref(),
+            Some("This train has been delayed by a broken down train")
+        );
+        assert_eq!(
+            reference.reason_text(ReasonKind::LateRunning, &reason("999", None, false)),
+            None
+        );
+    }
+
+    #[test]
+    fn crs_codes_fill_stop_mapping_gaps() {
+        let reference = parse(REF_XML.as_bytes()).unwrap();
+        let mut data = GtfsData::default();
+        // Static feed knows Kings Cross by its main TIPLOC and CRS, Hitchin only by TIPLOC
+        data.tiploc_map.insert("KNGX".into(), "KGX_STOP".into());
+        data.tiploc_map.insert("KGX".into(), "KGX_STOP".into());
+        data.tiploc_map.insert("HTCH".into(), "HIT_STOP".into());
+        let gtfs = GTFSManager::from_data(data);
+        assert_eq!(gtfs.get_stop_id("KNGXBEL"), None);
+        assert_eq!(gtfs.get_stop_id_for_crs("HIT"), None);
+
+        gtfs.set_crs_codes(reference.crs_codes());
+        assert_eq!(gtfs.get_stop_id("KNGXBEL").as_deref(), Some("KGX_STOP"));
+        assert_eq!(gtfs.get_stop_id_for_crs("HIT").as_deref(), Some("HIT_STOP"));
+        // Junctions have no CRS
+        assert_eq!(gtfs.get_stop_id("HTCHSJN"), None);
+    }
+}
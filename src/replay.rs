@@ -0,0 +1,254 @@
+// `darwin-to-gtfs-realtime replay [--realtime] [--gtfs <path or URL>] [--output <file>] <recording>...`
+//
+// Feeds recordings (files, or directories of them, from DARWIN_RECORD_DIR) through the parser
+// and processor into a fresh state, in the order they were received, then writes the
+// resulting /gtfs-rt feed. DARWIN_REFERENCE, DARWIN_TIMETABLE and GTFS_RT_INCLUDE_ALERTS
+// apply as they do to the server. Entities are sorted by ID and the header timestamp is the
+// last frame's, so the same recording replayed gives the same feed.
+
+use crate::processor::process_pmap;
+use crate::recorder::{self, EXTENSION};
+use crate::state::AppState;
+use anyhow::{Context, Result, bail};
+use gtfs_realtime::FeedMessage;
+use prost::Message;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pace {
+    // Sleep out the gaps between frames as they were received
+    Realtime,
+    AsFastAsPossible,
+}
+
+struct Options {
+    pace: Pace,
+    gtfs: String,
+    output: Option<PathBuf>,
+    recordings: Vec<PathBuf>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut options = Options {
+            pace: Pace::AsFastAsPossible,
+            gtfs: crate::GTFS_URL.to_string(),
+            output: None,
+            recordings: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--realtime" => options.pace = Pace::Realtime,
+                "--gtfs" => options.gtfs = args.next().context("--gtfs needs a value")?.clone(),
+                "--output" => {
+                    options.output = Some(args.next().context("--output needs a file")?.into())
+                }
+                flag if flag.starts_with("--") => bail!("Unknown replay option {}", flag),
+                recording => options.recordings.push(recording.into()),
+            }
+        }
+        if options.recordings.is_empty() {
+            bail!(
+                "Usage: replay [--realtime] [--gtfs <path or URL>] [--output <file>] <recording>..."
+            );
+        }
+        Ok(options)
+    }
+}
+
+pub async fn run(args: &[String]) -> Result<()> {
+    let options = Options::parse(args)?;
+    let state = AppState::new(options.gtfs.clone());
+    if let Err(e) = state.gtfs.load_initial() {
+        eprintln!(
+            "Warning: GTFS load failed, trips will only match Darwin schedules: {}",
+            e
+        );
+    }
+    if let Ok(location) = std::env::var("DARWIN_REFERENCE")
+        && let Err(e) = crate::reference::load(&location, &state).await
+    {
+        eprintln!("Warning: Darwin reference data load failed: {:#}", e);
+    }
+    if let Ok(location) = std::env::var("DARWIN_TIMETABLE")
+        && let Err(e) = load_timetable(&location, &state).await
+    {
+        eprintln!("Warning: Darwin timetable load failed: {:#}", e);
+    }
+
+    let files = recording_files(&options.recordings)?;
+    let last_recorded_at = replay(&files, &state, options.pace).await?;
+
+    let include_alerts = std::env::var("GTFS_RT_INCLUDE_ALERTS")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false);
+    let feed = feed(&state, include_alerts, last_recorded_at);
+    let bytes = feed.encode_to_vec();
+    match &options.output {
+        Some(path) => std::fs::write(path, &bytes)
+            .with_context(|| format!("Failed to write {}", path.display()))?,
+        None => std::io::Write::write_all(&mut std::io::stdout(), &bytes)?,
+    }
+
+    eprintln!(
+        "Replayed {} files: {} trip updates, {} alerts. Frames by encoding {:?}, dropped {:?}",
+        files.len(),
+        state.trip_updates.len(),
+        state.alerts.len(),
+        state.frames.encodings(),
+        state.frames.drops()
+    );
+    Ok(())
+}
+
+async fn load_timetable(location: &str, state: &AppState) -> Result<()> {
+    let body = crate::snapshot::fetch(location)
+        .await
+        .with_context(|| format!("Failed to fetch timetable {}", location))?;
+    state.gtfs.load_timetable(&body)
+}
+
+// Directories stand for the recordings in them, oldest first
+fn recording_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut found: Vec<PathBuf> = std::fs::read_dir(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|p| p.to_str().is_some_and(|p| p.ends_with(EXTENSION)))
+                .collect();
+            // Named by creation time
+            found.sort();
+            files.extend(found);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+// Apply every frame in order, one at a time. Returns when the last frame was recorded.
+pub async fn replay(files: &[PathBuf], state: &AppState, pace: Pace) -> Result<Option<i64>> {
+    let mut previous: Option<i64> = None;
+    for file in files {
+        for frame in recorder::read(Path::new(file)).await? {
+            if pace == Pace::Realtime
+                && let Some(previous) = previous
+                && frame.recorded_at > previous
+            {
+                tokio::time::sleep(Duration::from_millis((frame.recorded_at - previous) as u64))
+                    .await;
+            }
+            previous = Some(frame.recorded_at);
+
+            state
+                .frames
+                .received(crate::Encoding::sniff(&frame.body).name());
+            match crate::parse_frame_bytes(&frame.body) {
+                Ok(pport) => process_pmap(pport, state),
+                Err(e) => {
+                    eprintln!(
+                        "Dropping message {} ({}): {}",
+                        frame.headers.get("message-id").unwrap_or("without an ID"),
+                        e.kind(),
+                        e
+                    );
+                    state.frames.dropped(e.kind());
+                }
+            }
+        }
+    }
+    Ok(previous)
+}
+
+// The /gtfs-rt feed, in a stable order
+pub fn feed(state: &AppState, include_alerts: bool, recorded_at: Option<i64>) -> FeedMessage {
+    let mut msg = crate::gtfs_rt_feed(state, include_alerts);
+    msg.entity.sort_by(|a, b| a.id.cmp(&b.id));
+    if let Some(recorded_at) = recorded_at {
+        msg.header.timestamp = Some((recorded_at / 1000) as u64);
+    }
+    msg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::Recorder;
+    use crate::static_data::{GTFSManager, GtfsData};
+    use crate::stomp::Headers;
+
+    fn state_with_stops() -> AppState {
+        let mut data = GtfsData::default();
+        for (tiploc, stop_id) in [("PADTON", "PAD"), ("RDNGSTN", "RDG"), ("OXFD", "OXF")] {
+            data.tiploc_map.insert(tiploc.into(), stop_id.into());
+        }
+        let mut state = AppState::new(String::new());
+        state.gtfs = GTFSManager::from_data(data);
+        state
+    }
+
+    #[tokio::test]
+    async fn recording_replays_to_the_same_feed() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = Recorder::start(dir.path(), Duration::from_secs(3600), 0).unwrap();
+        let messages = [
+            r#"<Pport ts="T" version="16.0"><uR updateOrigin="CIS"><schedule rid="202601157654321" uid="V54321" trainId="5Z99" ssd="2026-01-15" toc="GW"><OR tpl="PADTON" ptd="10:00" wtd="10:00"/><IP tpl="RDNGSTN" pta="10:25" ptd="10:27" wta="10:25" wtd="10:27"/><DT tpl="OXFD" pta="10:55" wta="10:55"/></schedule></uR></Pport>"#,
+            "not a Pport",
+            r#"<Pport ts="T" version="16.0"><uR updateOrigin="TD"><TS rid="202601157654321" uid="V54321" ssd="2026-01-15"><Location tpl="RDNGSTN" wta="10:25" wtd="10:27" pta="10:25" ptd="10:27"><arr et="10:31"/><dep et="10:33"/></Location></TS></uR></Pport>"#,
+        ];
+        for (n, message) in messages.iter().enumerate() {
+            let headers = Headers::new().with("message-id", format!("ID:{}", n));
+            recorder.record(&headers, message.as_bytes());
+        }
+        drop(recorder);
+
+        let mut feeds = Vec::new();
+        for _ in 0..2 {
+            let state = state_with_stops();
+            let files = recording_files(&[dir.path().to_path_buf()]).unwrap();
+            let last = replay(&files, &state, Pace::AsFastAsPossible)
+                .await
+                .unwrap();
+            assert_eq!(state.frames.drops(), [("unknown_encoding", 1)].into());
+            feeds.push(feed(&state, false, last));
+        }
+        assert_eq!(feeds[0], feeds[1]);
+
+        let feed = &feeds[0];
+        assert!(feed.header.timestamp.unwrap() > 0);
+        assert_eq!(feed.entity.len(), 1);
+        let trip_update = feed.entity[0].trip_update.as_ref().unwrap();
+        assert_eq!(
+            trip_update.trip.trip_id.as_deref(),
+            Some("V54321_2026-01-15")
+        );
+        assert_eq!(
+            trip_update.stop_time_update[1]
+                .arrival
+                .as_ref()
+                .unwrap()
+                .delay,
+            Some(360)
+        );
+    }
+
+    #[test]
+    fn options_need_a_recording() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let options = Options::parse(&args(&[
+            "--realtime",
+            "--output",
+            "feed.pb",
+            "rec.stomp.gz",
+        ]))
+        .unwrap();
+        assert_eq!(options.pace, Pace::Realtime);
+        assert_eq!(options.output, Some(PathBuf::from("feed.pb")));
+        assert!(Options::parse(&args(&["--realtime"])).is_err());
+        assert!(Options::parse(&args(&["--fast", "rec.stomp.gz"])).is_err());
+    }
+}
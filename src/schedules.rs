@@ -0,0 +1,216 @@
+// Schedule as rttiPPTSchedules_v1 and v2 define it. v3 is darwin_types::Schedule.
+
+use crate::darwin_types::{Schedule, ScheduleLocation, SchedulePoint};
+
+pub mod v1 {
+    use crate::darwin_types::DisruptionReason;
+    use compact_str::CompactString;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    pub struct Schedule {
+        #[serde(rename = "@rid")]
+        pub rid: CompactString,
+        #[serde(rename = "@uid")]
+        pub uid: CompactString,
+        #[serde(rename = "@trainId")]
+        pub train_id: Option<CompactString>,
+        #[serde(rename = "@ssd")]
+        pub ssd: CompactString,
+        #[serde(rename = "@toc")]
+        pub toc: Option<CompactString>,
+        #[serde(rename = "@status")]
+        pub status: Option<CompactString>,
+        #[serde(rename = "@trainCat")]
+        pub train_cat: Option<CompactString>,
+        #[serde(
+            rename = "@isPassengerSvc",
+            default = "crate::darwin_types::default_true"
+        )]
+        pub is_passenger_svc: bool,
+        #[serde(rename = "@isActive", default = "crate::darwin_types::default_true")]
+        pub is_active: bool,
+        #[serde(rename = "@deleted", default)]
+        pub deleted: bool,
+        #[serde(rename = "@isCharter", default)]
+        pub is_charter: bool,
+        #[serde(rename = "$value", default)]
+        pub locations: Vec<ScheduleLocation>,
+        #[serde(rename = "cancelReason")]
+        pub cancel_reason: Option<DisruptionReason>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub enum ScheduleLocation {
+        #[serde(rename = "OR")]
+        Origin(SchedulePoint),
+        #[serde(rename = "OPOR")]
+        OperationalOrigin(SchedulePoint),
+        #[serde(rename = "IP")]
+        Intermediate(SchedulePoint),
+        #[serde(rename = "OPIP")]
+        OperationalIntermediate(SchedulePoint),
+        #[serde(rename = "PP")]
+        Pass(SchedulePoint),
+        #[serde(rename = "DT")]
+        Destination(SchedulePoint),
+        #[serde(rename = "OPDT")]
+        OperationalDestination(SchedulePoint),
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct SchedulePoint {
+        #[serde(rename = "@tpl")]
+        pub tiploc: CompactString,
+        #[serde(rename = "@act")]
+        pub act: Option<CompactString>,
+        #[serde(rename = "@planAct")]
+        pub plan_act: Option<CompactString>,
+        #[serde(rename = "@can", default)]
+        pub cancelled: bool,
+        #[serde(rename = "@pta")]
+        pub pta: Option<CompactString>,
+        #[serde(rename = "@ptd")]
+        pub ptd: Option<CompactString>,
+        #[serde(rename = "@wta")]
+        pub wta: Option<CompactString>,
+        #[serde(rename = "@wtd")]
+        pub wtd: Option<CompactString>,
+        #[serde(rename = "@wtp")]
+        pub wtp: Option<CompactString>,
+        #[serde(rename = "@rdelay")]
+        pub rdelay: Option<i32>,
+        #[serde(rename = "@fd")]
+        pub false_destination: Option<CompactString>,
+    }
+}
+
+// v2 added the RSID; calling points are as v1
+pub mod v2 {
+    use super::v1::ScheduleLocation;
+    use crate::darwin_types::DisruptionReason;
+    use compact_str::CompactString;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    pub struct Schedule {
+        #[serde(rename = "@rid")]
+        pub rid: CompactString,
+        #[serde(rename = "@uid")]
+        pub uid: CompactString,
+        #[serde(rename = "@trainId")]
+        pub train_id: Option<CompactString>,
+        #[serde(rename = "@rsid")]
+        pub rsid: Option<CompactString>,
+        #[serde(rename = "@ssd")]
+        pub ssd: CompactString,
+        #[serde(rename = "@toc")]
+        pub toc: Option<CompactString>,
+        #[serde(rename = "@status")]
+        pub status: Option<CompactString>,
+        #[serde(rename = "@trainCat")]
+        pub train_cat: Option<CompactString>,
+        #[serde(
+            rename = "@isPassengerSvc",
+            default = "crate::darwin_types::default_true"
+        )]
+        pub is_passenger_svc: bool,
+        #[serde(rename = "@isActive", default = "crate::darwin_types::default_true")]
+        pub is_active: bool,
+        #[serde(rename = "@deleted", default)]
+        pub deleted: bool,
+        #[serde(rename = "@isCharter", default)]
+        pub is_charter: bool,
+        #[serde(rename = "$value", default)]
+        pub locations: Vec<ScheduleLocation>,
+        #[serde(rename = "cancelReason")]
+        pub cancel_reason: Option<DisruptionReason>,
+    }
+}
+
+// v3 added formation IDs and average loading to the calling points
+impl From<v1::Schedule> for Schedule {
+    fn from(v1: v1::Schedule) -> Self {
+        Schedule {
+            rid: v1.rid,
+            uid: v1.uid,
+            train_id: v1.train_id,
+            rsid: None,
+            ssd: v1.ssd,
+            toc: v1.toc,
+            status: v1.status,
+            train_cat: v1.train_cat,
+            is_passenger_svc: v1.is_passenger_svc,
+            is_active: v1.is_active,
+            deleted: v1.deleted,
+            is_charter: v1.is_charter,
+            qtrain: false,
+            cancelled: false,
+            locations: v1.locations.into_iter().map(Into::into).collect(),
+            cancel_reason: v1.cancel_reason,
+        }
+    }
+}
+
+impl From<v2::Schedule> for Schedule {
+    fn from(v2: v2::Schedule) -> Self {
+        Schedule {
+            rid: v2.rid,
+            uid: v2.uid,
+            train_id: v2.train_id,
+            rsid: v2.rsid,
+            ssd: v2.ssd,
+            toc: v2.toc,
+            status: v2.status,
+            train_cat: v2.train_cat,
+            is_passenger_svc: v2.is_passenger_svc,
+            is_active: v2.is_active,
+            deleted: v2.deleted,
+            is_charter: v2.is_charter,
+            qtrain: false,
+            cancelled: false,
+            locations: v2.locations.into_iter().map(Into::into).collect(),
+            cancel_reason: v2.cancel_reason,
+        }
+    }
+}
+
+impl From<v1::ScheduleLocation> for ScheduleLocation {
+    fn from(v1: v1::ScheduleLocation) -> Self {
+        match v1 {
+            v1::ScheduleLocation::Origin(p) => ScheduleLocation::Origin(p.into()),
+            v1::ScheduleLocation::OperationalOrigin(p) => {
+                ScheduleLocation::OperationalOrigin(p.into())
+            }
+            v1::ScheduleLocation::Intermediate(p) => ScheduleLocation::Intermediate(p.into()),
+            v1::ScheduleLocation::OperationalIntermediate(p) => {
+                ScheduleLocation::OperationalIntermediate(p.into())
+            }
+            v1::ScheduleLocation::Pass(p) => ScheduleLocation::Pass(p.into()),
+            v1::ScheduleLocation::Destination(p) => ScheduleLocation::Destination(p.into()),
+            v1::ScheduleLocation::OperationalDestination(p) => {
+                ScheduleLocation::OperationalDestination(p.into())
+            }
+        }
+    }
+}
+
+impl From<v1::SchedulePoint> for SchedulePoint {
+    fn from(v1: v1::SchedulePoint) -> Self {
+        SchedulePoint {
+            tiploc: v1.tiploc,
+            act: v1.act,
+            plan_act: v1.plan_act,
+            cancelled: v1.cancelled,
+            fid: None,
+            pta: v1.pta,
+            ptd: v1.ptd,
+            wta: v1.wta,
+            wtd: v1.wtd,
+            wtp: v1.wtp,
+            avg_loading: None,
+            rdelay: v1.rdelay,
+            false_destination: v1.false_destination,
+        }
+    }
+}
@@ -0,0 +1,621 @@
+// Push Port schema versions (rttiPPTSchema_v11.xsd to v16) and the sub-schemas each one
+// imports. Darwin has only ever added to its message shapes, but what it added lives in
+// versioned sub-schemas, so a payload is deserialised in its own version's shapes
+// (forecasts, schedules, formations) and converted to v16's, the one model the processor
+// sees whichever version a payload was written in.
+
+use crate::darwin_types::{Forecast, Loading, Pport, Schedule};
+use crate::formations;
+use quick_xml::DeError;
+use quick_xml::de::from_str;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SchemaVersion {
+    V11,
+    V12,
+    V13,
+    V14,
+    V15,
+    V16,
+}
+
+#[derive(Debug)]
+pub struct UnsupportedVersion(pub String);
+
+impl fmt::Display for UnsupportedVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unsupported Push Port schema version {:?}", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedVersion {}
+
+impl SchemaVersion {
+    pub const LATEST: SchemaVersion = SchemaVersion::V16;
+
+    // Pport@version, e.g. "16.0". Later versions are read as v16, whose shapes they extend;
+    // a payload without one is taken to be current.
+    pub fn parse(version: Option<&str>) -> Result<Self, UnsupportedVersion> {
+        let Some(version) = version else {
+            return Ok(Self::LATEST);
+        };
+        let major: u32 = version
+            .trim()
+            .split('.')
+            .next()
+            .and_then(|major| major.parse().ok())
+            .ok_or_else(|| UnsupportedVersion(version.to_string()))?;
+        Ok(match major {
+            11 => SchemaVersion::V11,
+            12 => SchemaVersion::V12,
+            13 => SchemaVersion::V13,
+            14 => SchemaVersion::V14,
+            15 => SchemaVersion::V15,
+            16.. => SchemaVersion::V16,
+            _ => return Err(UnsupportedVersion(version.to_string())),
+        })
+    }
+
+    // rttiPPTForecasts_vN: v2 added working estimates (wet), v3 the actual's class (atClass)
+    pub fn forecasts(self) -> u32 {
+        match self {
+            SchemaVersion::V11 => 1,
+            SchemaVersion::V12 => 2,
+            _ => 3,
+        }
+    }
+
+    // rttiPPTSchedules_vN: v2 added the RSID, v3 formation IDs and average loading
+    pub fn schedules(self) -> u32 {
+        match self {
+            SchemaVersion::V11 | SchemaVersion::V12 | SchemaVersion::V13 => 1,
+            SchemaVersion::V14 => 2,
+            _ => 3,
+        }
+    }
+
+    // rttiPPTFormations_vN: v1 brought formations and loading, v2 toilets
+    pub fn formations(self) -> Option<u32> {
+        match self {
+            SchemaVersion::V15 => Some(1),
+            SchemaVersion::V16 => Some(2),
+            _ => None,
+        }
+    }
+
+    // Deserialise a payload written in this version into the common model
+    pub fn deserialise(self, xml: &str) -> Result<Pport, DeError> {
+        Ok(match self {
+            SchemaVersion::V11 => from_str::<Pport<shapes::V11>>(xml)?.into_latest(),
+            SchemaVersion::V12 => from_str::<Pport<shapes::V12>>(xml)?.into_latest(),
+            SchemaVersion::V13 => from_str::<Pport<shapes::V13>>(xml)?.into_latest(),
+            SchemaVersion::V14 => from_str::<Pport<shapes::V14>>(xml)?.into_latest(),
+            SchemaVersion::V15 => from_str::<Pport<shapes::V15>>(xml)?.into_latest(),
+            SchemaVersion::V16 => from_str(xml)?,
+        })
+    }
+}
+
+// The sub-schema types a version's messages are written in. darwin_types is generic over
+// these and each converts to its v16 counterpart.
+pub trait SubSchemas {
+    // TSTimeData (rttiPPTForecasts_vN)
+    type Forecast: Into<Forecast> + DeserializeOwned + fmt::Debug;
+    // schedule (rttiPPTSchedules_vN)
+    type Schedule: Into<Schedule> + DeserializeOwned + fmt::Debug;
+    // scheduleFormations (rttiPPTFormations_vN)
+    type ScheduleFormations: Into<formations::v2::ScheduleFormations>
+        + DeserializeOwned
+        + fmt::Debug;
+    // formationLoading (rttiPPTFormations_v1)
+    type Loading: Into<Loading> + DeserializeOwned + fmt::Debug;
+}
+
+pub mod shapes {
+    use super::{NotInSchema, SubSchemas};
+    use crate::darwin_types::{Forecast, Loading, Schedule};
+    use crate::{forecasts, formations, schedules};
+
+    #[derive(Debug)]
+    pub struct V11;
+    #[derive(Debug)]
+    pub struct V12;
+    #[derive(Debug)]
+    pub struct V13;
+    #[derive(Debug)]
+    pub struct V14;
+    #[derive(Debug)]
+    pub struct V15;
+    #[derive(Debug)]
+    pub struct V16;
+
+    impl SubSchemas for V11 {
+        type Forecast = forecasts::v1::TsTimeData;
+        type Schedule = schedules::v1::Schedule;
+        type ScheduleFormations = NotInSchema;
+        type Loading = NotInSchema;
+    }
+
+    impl SubSchemas for V12 {
+        type Forecast = forecasts::v2::TsTimeData;
+        type Schedule = schedules::v1::Schedule;
+        type ScheduleFormations = NotInSchema;
+        type Loading = NotInSchema;
+    }
+
+    impl SubSchemas for V13 {
+        type Forecast = Forecast;
+        type Schedule = schedules::v1::Schedule;
+        type ScheduleFormations = NotInSchema;
+        type Loading = NotInSchema;
+    }
+
+    impl SubSchemas for V14 {
+        type Forecast = Forecast;
+        type Schedule = schedules::v2::Schedule;
+        type ScheduleFormations = NotInSchema;
+        type Loading = NotInSchema;
+    }
+
+    impl SubSchemas for V15 {
+        type Forecast = Forecast;
+        type Schedule = Schedule;
+        type ScheduleFormations = formations::v1::ScheduleFormations;
+        type Loading = Loading;
+    }
+
+    impl SubSchemas for V16 {
+        type Forecast = Forecast;
+        type Schedule = Schedule;
+        type ScheduleFormations = formations::v2::ScheduleFormations;
+        type Loading = Loading;
+    }
+}
+
+// An element the version doesn't define. Darwin never sends one, so meeting it means the
+// payload's version is wrong and the message is rejected rather than half-read.
+#[derive(Debug)]
+pub enum NotInSchema {}
+
+impl<'de> Deserialize<'de> for NotInSchema {
+    fn deserialize<D: Deserializer<'de>>(_: D) -> Result<Self, D::Error> {
+        Err(serde::de::Error::custom(
+            "element not defined in this schema version",
+        ))
+    }
+}
+
+impl From<NotInSchema> for formations::v2::ScheduleFormations {
+    fn from(not_in_schema: NotInSchema) -> Self {
+        match not_in_schema {}
+    }
+}
+
+impl From<NotInSchema> for Loading {
+    fn from(not_in_schema: NotInSchema) -> Self {
+        match not_in_schema {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::darwin_types::UpdateRecord;
+
+    const VERSIONS: [(u32, SchemaVersion); 6] = [
+        (11, SchemaVersion::V11),
+        (12, SchemaVersion::V12),
+        (13, SchemaVersion::V13),
+        (14, SchemaVersion::V14),
+        (15, SchemaVersion::V15),
+        (16, SchemaVersion::V16),
+    ];
+
+    // Highest version of a sub-schema that rttiPPTSchema_v{n}.xsd imports
+    fn imported(xsd: &str, schema: &str) -> Option<u32> {
+        let prefix = format!("schemaLocation=\"rttiPPT{}_v", schema);
+        xsd.match_indices(&prefix)
+            .filter_map(|(at, _)| {
+                let rest = &xsd[at + prefix.len()..];
+                rest[..rest.find('.')?].parse().ok()
+            })
+            .max()
+    }
+
+    #[test]
+    fn sub_schemas_match_the_xsd_imports() {
+        for (n, version) in VERSIONS {
+            let path = format!(
+                "{}/PushPort-v16/rttiPPTSchema_v{}.xsd",
+                env!("CARGO_MANIFEST_DIR"),
+                n
+            );
+            let xsd = std::fs::read_to_string(&path).unwrap();
+            assert_eq!(
+                imported(&xsd, "Forecasts"),
+                Some(version.forecasts()),
+                "{}",
+                path
+            );
+            assert_eq!(
+                imported(&xsd, "Schedules"),
+                Some(version.schedules()),
+                "{}",
+                path
+            );
+            assert_eq!(
+                imported(&xsd, "Formations"),
+                version.formations(),
+                "{}",
+                path
+            );
+        }
+    }
+
+    // Fixtures written against each rttiPPTSchema_v{n}.xsd, using only the elements and
+    // attributes that version's sub-schemas define. The core (schedule, deactivated, TS,
+    // trainOrder, trackingID, alarm) is the same service throughout.
+
+    // Forecasts v1 (which still holds trainOrder), Schedules v1, no updateOrigin
+    const V11: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Pport ts="2026-01-15T10:31:02.1234567+00:00" version="11.0"
+    xmlns="http://www.thalesgroup.com/rtti/PushPort/v11"
+    xmlns:sch="http://www.thalesgroup.com/rtti/PushPort/Schedules/v1"
+    xmlns:for="http://www.thalesgroup.com/rtti/PushPort/Forecasts/v1"
+    xmlns:td="http://www.thalesgroup.com/rtti/PushPort/TDData/v1"
+    xmlns:alm="http://www.thalesgroup.com/rtti/PushPort/Alarms/v1">
+  <uR requestSource="at01" requestID="0000000000012345">
+    <schedule rid="202601157654321" uid="V54321" trainId="1P21" ssd="2026-01-15" toc="GW">
+      <sch:OR tpl="PADTON" act="TB" ptd="10:00" wtd="10:00"/>
+      <sch:DT tpl="OXFD" act="TF" pta="10:55" wta="10:55"/>
+    </schedule>
+    <deactivated rid="202601157654000"/>
+    <TS rid="202601157654321" uid="V54321" ssd="2026-01-15">
+      <for:Location tpl="PADTON" wtd="10:00" ptd="10:00">
+        <for:dep et="10:02" at="10:02" src="TD"/>
+        <for:plat conf="true">1</for:plat>
+      </for:Location>
+    </TS>
+    <trainOrder tiploc="PADTON" crs="PAD" platform="1">
+      <for:set><for:first><for:rid wtd="10:00" ptd="10:00">202601157654321</for:rid></for:first></for:set>
+    </trainOrder>
+    <trackingID>
+      <td:berth area="D3">0107</td:berth>
+      <td:incorrectTrainID>1P20</td:incorrectTrainID>
+      <td:correctTrainID>1P21</td:correctTrainID>
+    </trackingID>
+    <alarm><alm:set id="1234"><alm:tdAreaFail>D3</alm:tdAreaFail></alm:set></alarm>
+  </uR>
+</Pport>"#;
+
+    // Forecasts v2: working estimates. trainOrder moves to TrainOrder v1.
+    const V12: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Pport ts="2026-01-15T10:31:02.1234567+00:00" version="12.0"
+    xmlns="http://www.thalesgroup.com/rtti/PushPort/v12"
+    xmlns:sch="http://www.thalesgroup.com/rtti/PushPort/Schedules/v1"
+    xmlns:for="http://www.thalesgroup.com/rtti/PushPort/Forecasts/v2"
+    xmlns:tor="http://www.thalesgroup.com/rtti/PushPort/TrainOrder/v1"
+    xmlns:td="http://www.thalesgroup.com/rtti/PushPort/TDData/v1"
+    xmlns:alm="http://www.thalesgroup.com/rtti/PushPort/Alarms/v1">
+  <uR updateOrigin="CIS">
+    <schedule rid="202601157654321" uid="V54321" trainId="1P21" ssd="2026-01-15" toc="GW">
+      <sch:OR tpl="PADTON" act="TB" ptd="10:00" wtd="10:00"/>
+      <sch:DT tpl="OXFD" act="TF" pta="10:55" wta="10:55"/>
+    </schedule>
+    <deactivated rid="202601157654000"/>
+    <TS rid="202601157654321" uid="V54321" ssd="2026-01-15">
+      <for:Location tpl="PADTON" wtd="10:00" ptd="10:00">
+        <for:dep et="10:02" wet="10:01" at="10:02" src="TD"/>
+        <for:plat conf="true">1</for:plat>
+      </for:Location>
+    </TS>
+    <trainOrder tiploc="PADTON" crs="PAD" platform="1">
+      <tor:set><tor:first><tor:rid wtd="10:00" ptd="10:00">202601157654321</tor:rid></tor:first></tor:set>
+    </trainOrder>
+    <trackingID>
+      <td:berth area="D3">0107</td:berth>
+      <td:incorrectTrainID>1P20</td:incorrectTrainID>
+      <td:correctTrainID>1P21</td:correctTrainID>
+    </trackingID>
+    <alarm><alm:set id="1234"><alm:tdAreaFail>D3</alm:tdAreaFail></alm:set></alarm>
+  </uR>
+</Pport>"#;
+
+    // Forecasts v3: the actual's class
+    const V13: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Pport ts="2026-01-15T10:31:02.1234567+00:00" version="13.0"
+    xmlns="http://www.thalesgroup.com/rtti/PushPort/v13"
+    xmlns:sch="http://www.thalesgroup.com/rtti/PushPort/Schedules/v1"
+    xmlns:for="http://www.thalesgroup.com/rtti/PushPort/Forecasts/v3"
+    xmlns:tor="http://www.thalesgroup.com/rtti/PushPort/TrainOrder/v1"
+    xmlns:td="http://www.thalesgroup.com/rtti/PushPort/TDData/v1"
+    xmlns:alm="http://www.thalesgroup.com/rtti/PushPort/Alarms/v1">
+  <uR updateOrigin="CIS">
+    <schedule rid="202601157654321" uid="V54321" trainId="1P21" ssd="2026-01-15" toc="GW">
+      <sch:OR tpl="PADTON" act="TB" ptd="10:00" wtd="10:00"/>
+      <sch:DT tpl="OXFD" act="TF" pta="10:55" wta="10:55"/>
+    </schedule>
+    <deactivated rid="202601157654000"/>
+    <TS rid="202601157654321" uid="V54321" ssd="2026-01-15">
+      <for:Location tpl="PADTON" wtd="10:00" ptd="10:00">
+        <for:dep et="10:02" wet="10:01" at="10:02" atClass="Automatic" src="TD"/>
+        <for:plat conf="true">1</for:plat>
+      </for:Location>
+    </TS>
+    <trainOrder tiploc="PADTON" crs="PAD" platform="1">
+      <tor:set><tor:first><tor:rid wtd="10:00" ptd="10:00">202601157654321</tor:rid></tor:first></tor:set>
+    </trainOrder>
+    <trackingID>
+      <td:berth area="D3">0107</td:berth>
+      <td:incorrectTrainID>1P20</td:incorrectTrainID>
+      <td:correctTrainID>1P21</td:correctTrainID>
+    </trackingID>
+    <alarm><alm:set id="1234"><alm:tdAreaFail>D3</alm:tdAreaFail></alm:set></alarm>
+  </uR>
+</Pport>"#;
+
+    // Schedules v2: the RSID
+    const V14: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Pport ts="2026-01-15T10:31:02.1234567+00:00" version="14.0"
+    xmlns="http://www.thalesgroup.com/rtti/PushPort/v14"
+    xmlns:sch="http://www.thalesgroup.com/rtti/PushPort/Schedules/v2"
+    xmlns:for="http://www.thalesgroup.com/rtti/PushPort/Forecasts/v3"
+    xmlns:tor="http://www.thalesgroup.com/rtti/PushPort/TrainOrder/v1"
+    xmlns:td="http://www.thalesgroup.com/rtti/PushPort/TDData/v1"
+    xmlns:alm="http://www.thalesgroup.com/rtti/PushPort/Alarms/v1">
+  <uR updateOrigin="CIS">
+    <schedule rid="202601157654321" uid="V54321" trainId="1P21" rsid="GW123400" ssd="2026-01-15" toc="GW">
+      <sch:OR tpl="PADTON" act="TB" ptd="10:00" wtd="10:00"/>
+      <sch:DT tpl="OXFD" act="TF" pta="10:55" wta="10:55"/>
+    </schedule>
+    <deactivated rid="202601157654000"/>
+    <TS rid="202601157654321" uid="V54321" ssd="2026-01-15">
+      <for:Location tpl="PADTON" wtd="10:00" ptd="10:00">
+        <for:dep et="10:02" wet="10:01" at="10:02" atClass="Automatic" src="TD"/>
+        <for:plat conf="true">1</for:plat>
+      </for:Location>
+    </TS>
+    <trainOrder tiploc="PADTON" crs="PAD" platform="1">
+      <tor:set><tor:first><tor:rid wtd="10:00" ptd="10:00">202601157654321</tor:rid></tor:first></tor:set>
+    </trainOrder>
+    <trackingID>
+      <td:berth area="D3">0107</td:berth>
+      <td:incorrectTrainID>1P20</td:incorrectTrainID>
+      <td:correctTrainID>1P21</td:correctTrainID>
+    </trackingID>
+    <alarm><alm:set id="1234"><alm:tdAreaFail>D3</alm:tdAreaFail></alm:set></alarm>
+  </uR>
+</Pport>"#;
+
+    // Schedules v3 (formation IDs, average loading), deactivated still from v2, and
+    // Formations v1
+    const V15: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Pport ts="2026-01-15T10:31:02.1234567+00:00" version="15.0"
+    xmlns="http://www.thalesgroup.com/rtti/PushPort/v15"
+    xmlns:sch2="http://www.thalesgroup.com/rtti/PushPort/Schedules/v2"
+    xmlns:sch3="http://www.thalesgroup.com/rtti/PushPort/Schedules/v3"
+    xmlns:for="http://www.thalesgroup.com/rtti/PushPort/Forecasts/v3"
+    xmlns:fm="http://www.thalesgroup.com/rtti/PushPort/Formations/v1"
+    xmlns:tor="http://www.thalesgroup.com/rtti/PushPort/TrainOrder/v1"
+    xmlns:td="http://www.thalesgroup.com/rtti/PushPort/TDData/v1"
+    xmlns:alm="http://www.thalesgroup.com/rtti/PushPort/Alarms/v1">
+  <uR updateOrigin="CIS">
+    <schedule rid="202601157654321" uid="V54321" trainId="1P21" rsid="GW123400" ssd="2026-01-15" toc="GW">
+      <sch3:OR tpl="PADTON" act="TB" ptd="10:00" wtd="10:00" fid="202601157654321-001"/>
+      <sch3:DT tpl="OXFD" act="TF" pta="10:55" wta="10:55" avgLoading="42"/>
+    </schedule>
+    <deactivated rid="202601157654000"/>
+    <scheduleFormations rid="202601157654321">
+      <fm:formation fid="202601157654321-001">
+        <fm:coaches>
+          <fm:coach coachNumber="A" coachClass="First"/>
+        </fm:coaches>
+      </fm:formation>
+    </scheduleFormations>
+    <TS rid="202601157654321" uid="V54321" ssd="2026-01-15">
+      <for:Location tpl="PADTON" wtd="10:00" ptd="10:00">
+        <for:dep et="10:02" wet="10:01" at="10:02" atClass="Automatic" src="TD"/>
+        <for:plat conf="true">1</for:plat>
+      </for:Location>
+    </TS>
+    <formationLoading fid="202601157654321-001" rid="202601157654321" tpl="PADTON" wtd="10:00" ptd="10:00">
+      <fm:loading coachNumber="A">30</fm:loading>
+    </formationLoading>
+    <trainOrder tiploc="PADTON" crs="PAD" platform="1">
+      <tor:set><tor:first><tor:rid wtd="10:00" ptd="10:00">202601157654321</tor:rid></tor:first></tor:set>
+    </trainOrder>
+    <trackingID>
+      <td:berth area="D3">0107</td:berth>
+      <td:incorrectTrainID>1P20</td:incorrectTrainID>
+      <td:correctTrainID>1P21</td:correctTrainID>
+    </trackingID>
+    <alarm><alm:set id="1234"><alm:tdAreaFail>D3</alm:tdAreaFail></alm:set></alarm>
+  </uR>
+</Pport>"#;
+
+    // Formations v2: toilets. Loading stays on Formations v1.
+    const V16: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Pport ts="2026-01-15T10:31:02.1234567+00:00" version="16.0"
+    xmlns="http://www.thalesgroup.com/rtti/PushPort/v16"
+    xmlns:sch2="http://www.thalesgroup.com/rtti/PushPort/Schedules/v2"
+    xmlns:sch3="http://www.thalesgroup.com/rtti/PushPort/Schedules/v3"
+    xmlns:for="http://www.thalesgroup.com/rtti/PushPort/Forecasts/v3"
+    xmlns:fm="http://www.thalesgroup.com/rtti/PushPort/Formations/v1"
+    xmlns:fm2="http://www.thalesgroup.com/rtti/PushPort/Formations/v2"
+    xmlns:tor="http://www.thalesgroup.com/rtti/PushPort/TrainOrder/v1"
+    xmlns:td="http://www.thalesgroup.com/rtti/PushPort/TDData/v1"
+    xmlns:alm="http://www.thalesgroup.com/rtti/PushPort/Alarms/v1">
+  <uR updateOrigin="CIS">
+    <schedule rid="202601157654321" uid="V54321" trainId="1P21" rsid="GW123400" ssd="2026-01-15" toc="GW">
+      <sch3:OR tpl="PADTON" act="TB" ptd="10:00" wtd="10:00" fid="202601157654321-001"/>
+      <sch3:DT tpl="OXFD" act="TF" pta="10:55" wta="10:55" avgLoading="42"/>
+    </schedule>
+    <deactivated rid="202601157654000"/>
+    <scheduleFormations rid="202601157654321">
+      <fm2:formation fid="202601157654321-001">
+        <fm2:coaches>
+          <fm2:coach coachNumber="A" coachClass="First"><fm2:toilet status="InService">Accessible</fm2:toilet></fm2:coach>
+        </fm2:coaches>
+      </fm2:formation>
+    </scheduleFormations>
+    <TS rid="202601157654321" uid="V54321" ssd="2026-01-15">
+      <for:Location tpl="PADTON" wtd="10:00" ptd="10:00">
+        <for:dep et="10:02" wet="10:01" at="10:02" atClass="Automatic" src="TD"/>
+        <for:plat conf="true">1</for:plat>
+      </for:Location>
+    </TS>
+    <formationLoading fid="202601157654321-001" rid="202601157654321" tpl="PADTON" wtd="10:00" ptd="10:00">
+      <fm:loading coachNumber="A">30</fm:loading>
+    </formationLoading>
+    <trainOrder tiploc="PADTON" crs="PAD" platform="1">
+      <tor:set><tor:first><tor:rid wtd="10:00" ptd="10:00">202601157654321</tor:rid></tor:first></tor:set>
+    </trainOrder>
+    <trackingID>
+      <td:berth area="D3">0107</td:berth>
+      <td:incorrectTrainID>1P20</td:incorrectTrainID>
+      <td:correctTrainID>1P21</td:correctTrainID>
+    </trackingID>
+    <alarm><alm:set id="1234"><alm:tdAreaFail>D3</alm:tdAreaFail></alm:set></alarm>
+  </uR>
+</Pport>"#;
+
+    fn parse(xml: &str) -> Pport {
+        crate::parse::parse_frame_bytes(xml.as_bytes()).unwrap()
+    }
+
+    fn record(pport: &Pport) -> &UpdateRecord {
+        pport.update_record.as_ref().unwrap()
+    }
+
+    fn departure(pport: &Pport) -> &Forecast {
+        record(pport).train_status[0].locations[0]
+            .dep
+            .as_ref()
+            .unwrap()
+    }
+
+    // Common to every version
+    fn assert_core(pport: &Pport) {
+        let record = record(pport);
+        assert_eq!(record.schedule[0].locations.len(), 2);
+        assert_eq!(record.deactivated[0].rid, "202601157654000");
+        assert_eq!(departure(pport).at.as_deref(), Some("10:02"));
+        assert_eq!(departure(pport).src.as_deref(), Some("TD"));
+        let order = record.train_order[0].set.as_ref().unwrap();
+        assert_eq!(
+            order.first.as_ref().unwrap().rid.as_ref().unwrap().value,
+            "202601157654321"
+        );
+        assert_eq!(record.tracking_id[0].berth.id, "0107");
+        assert_eq!(record.tracking_id[0].correct_train_id, "1P21");
+        assert_eq!(
+            record.rtti_alarm[0]
+                .set
+                .as_ref()
+                .unwrap()
+                .td_area_fail
+                .as_deref(),
+            Some("D3")
+        );
+    }
+
+    #[test]
+    fn v11_forecasts_v1_and_schedules_v1() {
+        let pport = parse(V11);
+        assert_core(&pport);
+        assert_eq!(record(&pport).update_origin, None);
+        assert_eq!(departure(&pport).et.as_deref(), Some("10:02"));
+        assert_eq!(departure(&pport).wet, None);
+        assert_eq!(departure(&pport).at_class, None);
+        let schedule = &record(&pport).schedule[0];
+        assert_eq!(schedule.rsid, None);
+        assert_eq!(schedule.locations[0].point().act.as_deref(), Some("TB"));
+        assert_eq!(schedule.locations[0].point().fid, None);
+        assert!(record(&pport).schedule_formations.is_empty());
+        assert!(record(&pport).loading.is_empty());
+    }
+
+    #[test]
+    fn v12_forecasts_v2_adds_working_estimates() {
+        let pport = parse(V12);
+        assert_core(&pport);
+        assert_eq!(record(&pport).update_origin.as_deref(), Some("CIS"));
+        assert_eq!(departure(&pport).wet.as_deref(), Some("10:01"));
+        assert_eq!(departure(&pport).at_class, None);
+    }
+
+    #[test]
+    fn v13_forecasts_v3_adds_the_actual_class() {
+        let pport = parse(V13);
+        assert_core(&pport);
+        assert_eq!(departure(&pport).wet.as_deref(), Some("10:01"));
+        assert_eq!(departure(&pport).at_class.as_deref(), Some("Automatic"));
+        assert_eq!(record(&pport).schedule[0].rsid, None);
+    }
+
+    #[test]
+    fn v14_schedules_v2_adds_rsids() {
+        let pport = parse(V14);
+        assert_core(&pport);
+        let schedule = &record(&pport).schedule[0];
+        assert_eq!(schedule.rsid.as_deref(), Some("GW123400"));
+        assert_eq!(schedule.locations[0].point().fid, None);
+        assert_eq!(schedule.locations[1].point().avg_loading, None);
+        assert!(record(&pport).schedule_formations.is_empty());
+    }
+
+    #[test]
+    fn v15_schedules_v3_and_formations_v1() {
+        let pport = parse(V15);
+        assert_core(&pport);
+        let schedule = &record(&pport).schedule[0];
+        assert_eq!(schedule.rsid.as_deref(), Some("GW123400"));
+        assert_eq!(
+            schedule.locations[0].point().fid.as_deref(),
+            Some("202601157654321-001")
+        );
+        assert_eq!(schedule.locations[1].point().avg_loading, Some(42));
+        let coach = &record(&pport).schedule_formations[0].formations[0]
+            .coaches
+            .coaches[0];
+        assert_eq!(coach.coach_number, "A");
+        assert_eq!(coach.coach_class.as_deref(), Some("First"));
+        assert!(coach.toilet.is_none());
+        assert_eq!(record(&pport).loading[0].coaches[0].percentage, 30);
+    }
+
+    #[test]
+    fn v16_formations_v2_adds_toilets() {
+        let pport = parse(V16);
+        assert_core(&pport);
+        let coach = &record(&pport).schedule_formations[0].formations[0]
+            .coaches
+            .coaches[0];
+        let toilet = coach.toilet.as_ref().unwrap();
+        assert_eq!(toilet.status.as_deref(), Some("Accessible"));
+        assert_eq!(toilet.status_attr.as_deref(), Some("InService"));
+        assert_eq!(record(&pport).loading[0].coaches[0].percentage, 30);
+    }
+
+    #[test]
+    fn elements_from_a_later_version_are_rejected() {
+        // Formations under a v14 header
+        let xml = V15.replacen(r#"version="15.0""#, r#"version="14.0""#, 1);
+        let error = crate::parse::parse_frame_bytes(xml.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), "schema_mismatch", "{}", error);
+    }
+
+    #[test]
+    fn versions_outside_v11_to_v16() {
+        assert_eq!(SchemaVersion::parse(None).unwrap(), SchemaVersion::V16);
+        assert_eq!(
+            SchemaVersion::parse(Some("17.0")).unwrap(),
+            SchemaVersion::V16
+        );
+        assert!(SchemaVersion::parse(Some("10.0")).is_err());
+        assert!(SchemaVersion::parse(Some("x")).is_err());
+    }
+}
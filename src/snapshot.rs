@@ -0,0 +1,63 @@
+// Cold start from a Darwin snapshot (pPortData: a gzipped Pport with one sR holding
+// current state), applied before the live feed starts.
+
+use crate::processor::process_pmap;
+use crate::state::AppState;
+use anyhow::{Context, Result};
+use std::sync::Arc;
+
+// `location` is a local path or an http(s) URL (DARWIN_SNAPSHOT)
+pub async fn bootstrap(location: &str, state: Arc<AppState>) -> Result<()> {
+    println!("Loading Darwin snapshot from {}...", location);
+    let body = fetch(location)
+        .await
+        .with_context(|| format!("Failed to fetch snapshot {}", location))?;
+
+    let services = tokio::task::spawn_blocking(move || -> Result<usize> {
+        let pport = crate::parse_frame_bytes(&body)?;
+        let services = pport.rids().len();
+        process_pmap(pport, &state);
+        Ok(services)
+    })
+    .await??;
+
+    println!("Applied Darwin snapshot ({} services).", services);
+    Ok(())
+}
+
+// A Darwin file from a local path or an http(s) URL
+pub async fn fetch(location: &str) -> Result<Vec<u8>> {
+    if location.starts_with("http://") || location.starts_with("https://") {
+        let response = reqwest::get(location).await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    } else {
+        Ok(tokio::fs::read(location).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compression, write::GzEncoder};
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn snapshot_file_is_applied() {
+        let xml = r#"<Pport ts="2026-01-15T08:00:00" version="16.0"><sR><schedule rid="202601157654321" uid="V54321" trainId="5Z99" ssd="2026-01-15" toc="GW"><OR tpl="PADTON" wtd="10:00"/><DT tpl="OXFD" wta="10:55"/></schedule><association tiploc="RDNGSTN" category="VV"><main rid="202601157654321" wtd="10:27"/><assoc rid="202601157654322" wtd="10:30"/></association><OW id="42" cat="PriorTrains" sev="1"><Msg>Engineering works this weekend.</Msg></OW></sR></Pport>"#;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(xml.as_bytes()).unwrap();
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&encoder.finish().unwrap()).unwrap();
+
+        let state = Arc::new(AppState::new(String::new()));
+        bootstrap(file.path().to_str().unwrap(), state.clone())
+            .await
+            .unwrap();
+
+        assert!(state.schedules.contains_key("202601157654321"));
+        assert_eq!(state.associations.get("202601157654322").unwrap().len(), 1);
+        assert!(state.alerts.contains_key("OW_42"));
+
+        assert!(bootstrap("/nonexistent/pPortData.gz", state).await.is_err());
+    }
+}
@@ -0,0 +1,322 @@
+// Where Darwin messages come from. A source runs one session (connection, consumer) at a
+// time and hands every message to the work queue; `run` reconnects with backoff when it ends.
+
+use crate::state::AppState;
+use crate::stomp::{self, StompConfig, StompError};
+use crate::work_queue::{Ack, WorkQueue};
+use anyhow::Result;
+use chrono::Utc;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+pub trait Source: Send + Sync + 'static {
+    // For logs, e.g. "Darwin STOMP at host:port"
+    fn describe(&self) -> String;
+
+    // Submit messages until the session fails. Each message's Ack must only confirm it
+    // to the broker, since the work queue runs it after the message is applied.
+    fn listen(
+        &self,
+        state: &Arc<AppState>,
+        queue: &WorkQueue,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    // Errors where retrying soon only risks getting the account locked
+    fn is_login_rejected(&self, _error: &anyhow::Error) -> bool {
+        false
+    }
+}
+
+pub async fn run<S: Source>(source: S, state: Arc<AppState>, queue: WorkQueue) {
+    let mut reconnect_backoff = Backoff::from_env();
+    let mut login_backoff = Backoff::new(Duration::from_secs(60), Duration::from_secs(1800));
+    loop {
+        println!("Connecting to {}...", source.describe());
+        let last_message_before = state.last_message_at.load(Ordering::Relaxed);
+        let result = source.listen(&state, &queue).await;
+
+        // A session that delivered messages was healthy, so start the backoff over
+        if state.last_message_at.load(Ordering::Relaxed) != last_message_before {
+            reconnect_backoff.reset();
+        }
+
+        let delay = match result {
+            Ok(_) => {
+                eprintln!("{} closed unexpectedly.", source.describe());
+                reconnect_backoff.next_delay()
+            }
+            Err(e) => {
+                eprintln!("{} error: {}", source.describe(), e);
+                if source.is_login_rejected(&e) {
+                    login_backoff.next_delay()
+                } else {
+                    login_backoff.reset();
+                    reconnect_backoff.next_delay()
+                }
+            }
+        };
+        println!("Reconnecting to Darwin in {:?}", delay);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+// Darwin Push Port over STOMP, acknowledging each MESSAGE once applied
+pub struct StompSource(pub StompConfig);
+
+impl Source for StompSource {
+    fn describe(&self) -> String {
+        format!("Darwin STOMP at {}:{}", self.0.host, self.0.port)
+    }
+
+    async fn listen(&self, state: &Arc<AppState>, queue: &WorkQueue) -> Result<()> {
+        let mut connection = stomp::connect(&self.0).await?;
+        let acker = connection.acker();
+        loop {
+            let message = connection.next_message().await?;
+            state
+                .last_message_at
+                .store(Utc::now().timestamp(), Ordering::Relaxed);
+
+            let ack = message.ack_id().map(|id| {
+                let acker = acker.clone();
+                let id = id.to_string();
+                Box::pin(async move {
+                    // A failed ACK means the connection is gone and Darwin will redeliver
+                    if let Err(e) = acker.ack(&id).await {
+                        eprintln!("Failed to ACK message {}: {}", id, e);
+                    }
+                }) as Ack
+            });
+            queue
+                .submit(state, message.headers, message.body, ack)
+                .await;
+        }
+    }
+
+    fn is_login_rejected(&self, error: &anyhow::Error) -> bool {
+        matches!(
+            error.downcast_ref::<StompError>(),
+            Some(StompError::LoginRejected(_))
+        )
+    }
+}
+
+// Exponential backoff between reconnects. Each delay is drawn from the upper half of
+// the current step so a fleet of instances doesn't reconnect in lockstep.
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    // DARWIN_BACKOFF_MIN_SECS, DARWIN_BACKOFF_MAX_SECS
+    pub fn from_env() -> Self {
+        let min_secs: u64 = std::env::var("DARWIN_BACKOFF_MIN_SECS")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse()
+            .expect("Invalid DARWIN_BACKOFF_MIN_SECS");
+        let max_secs: u64 = std::env::var("DARWIN_BACKOFF_MAX_SECS")
+            .unwrap_or_else(|_| "300".to_string())
+            .parse()
+            .expect("Invalid DARWIN_BACKOFF_MAX_SECS");
+        Self::new(Duration::from_secs(min_secs), Duration::from_secs(max_secs))
+    }
+
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max: max.max(min),
+            attempt: 0,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let step = self
+            .min
+            .saturating_mul(1 << self.attempt.min(16))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let half = step / 2;
+        half + half.mul_f64(jitter())
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+// Uniform in [0, 1), from the randomly keyed std hasher
+fn jitter() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::stomp::{Frame, Headers, read_frame, tls_connector};
+    use std::sync::Mutex;
+    use tokio::io::{AsyncWriteExt, BufReader};
+
+    // In-process source: delivers its messages once, recording which were acknowledged
+    pub(crate) struct MockSource {
+        pub messages: Vec<Vec<u8>>,
+        pub acked: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl Source for MockSource {
+        fn describe(&self) -> String {
+            "mock source".to_string()
+        }
+
+        async fn listen(&self, state: &Arc<AppState>, queue: &WorkQueue) -> Result<()> {
+            for (n, body) in self.messages.iter().enumerate() {
+                state
+                    .last_message_at
+                    .store(Utc::now().timestamp(), Ordering::Relaxed);
+                let acked = self.acked.clone();
+                let ack: Ack = Box::pin(async move { acked.lock().unwrap().push(n) });
+                queue
+                    .submit(state, Headers::new(), body.clone(), Some(ack))
+                    .await;
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn mock_source_feeds_the_processor() {
+        let state = Arc::new(AppState::new(String::new()));
+        let queue = WorkQueue::start(state.clone(), 4, 2);
+        let source = MockSource {
+            messages: vec![
+                br#"<Pport ts="T" version="16.0"><uR updateOrigin="CIS"><schedule rid="202601157654321" uid="V54321" trainId="5Z99" ssd="2026-01-15" toc="GW"><OR tpl="PADTON" wtd="10:00"/><DT tpl="OXFD" wta="10:55"/></schedule></uR></Pport>"#.to_vec(),
+                b"<Pport".to_vec(),
+            ],
+            acked: Arc::default(),
+        };
+        source.listen(&state, &queue).await.unwrap();
+
+        while source.acked.lock().unwrap().len() < 2 {
+            tokio::task::yield_now().await;
+        }
+        assert!(state.schedules.contains_key("202601157654321"));
+        assert_eq!(state.queue_depth.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn backoff_grows_with_jitter_and_resets() {
+        let mut backoff = Backoff::new(Duration::from_secs(5), Duration::from_secs(60));
+        let bounds = [(2500, 5000), (5000, 10000), (10000, 20000), (20000, 40000)];
+        for (low, high) in bounds {
+            let delay = backoff.next_delay().as_millis();
+            assert!(
+                (low..=high).contains(&delay),
+                "{} not in {}..={}",
+                delay,
+                low,
+                high
+            );
+        }
+        // Capped at max
+        for _ in 0..40 {
+            assert!(backoff.next_delay() <= Duration::from_secs(60));
+        }
+
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_secs(5));
+    }
+
+    // Local STOMP-over-TLS stub: accepts CONNECT and SUBSCRIBE, sends one MESSAGE, then an ERROR
+    #[tokio::test]
+    async fn connects_over_tls_with_custom_ca() {
+        use rcgen::{BasicConstraints, CertificateParams, IsCa, Issuer, KeyPair};
+        use std::io::Write;
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "Test STOMP CA");
+        let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+        let issuer = Issuer::new(ca_params, ca_key);
+
+        let server_key = KeyPair::generate().unwrap();
+        let server_cert = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&server_key, &issuer)
+            .unwrap();
+        let identity = native_tls::Identity::from_pkcs8(
+            server_cert.pem().as_bytes(),
+            server_key.serialize_pem().as_bytes(),
+        )
+        .unwrap();
+        let acceptor: tokio_native_tls::TlsAcceptor =
+            native_tls::TlsAcceptor::new(identity).unwrap().into();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stub = tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let stream = acceptor.accept(tcp).await.unwrap();
+            let (reader, mut writer) = tokio::io::split(stream);
+            let mut reader = BufReader::new(reader);
+
+            let connect = read_frame(&mut reader).await.unwrap();
+            writer
+                .write_all(b"CONNECTED\nversion:1.2\nheart-beat:0,0\n\n\0")
+                .await
+                .unwrap();
+            let subscribe = read_frame(&mut reader).await.unwrap();
+            writer
+                .write_all(b"MESSAGE\nmessage-id:1\nack:1\ncontent-length:0\n\n\0")
+                .await
+                .unwrap();
+            let ack = read_frame(&mut reader).await.unwrap();
+            writer
+                .write_all(b"ERROR\nmessage:closing\n\n\0")
+                .await
+                .unwrap();
+            (connect, subscribe, ack)
+        });
+
+        let mut ca_file = tempfile::NamedTempFile::new().unwrap();
+        ca_file.write_all(ca_cert.pem().as_bytes()).unwrap();
+        let config = StompConfig {
+            host: "localhost".to_string(),
+            port,
+            tls: Some(tls_connector(ca_file.path().to_str()).unwrap()),
+            ..crate::stomp::tests::test_config()
+        };
+        let state = Arc::new(AppState::new(String::new()));
+        let queue = WorkQueue::start(state.clone(), 8, 2);
+
+        let err = StompSource(config)
+            .listen(&state, &queue)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<StompError>(),
+            Some(StompError::Broker(_))
+        ));
+        assert!(state.last_message_at.load(Ordering::Relaxed) > 0);
+
+        let (connect, subscribe, ack) = stub.await.unwrap();
+        assert_eq!(connect.header("login"), Some("user"));
+        assert!(matches!(subscribe, Frame::Subscribe(_)));
+        assert!(matches!(ack, Frame::Ack(_)));
+        assert_eq!(ack.header("id"), Some("1"));
+    }
+}
@@ -1,9 +1,15 @@
+use crate::dead_letter::DeadLetters;
+use crate::recorder::Recorder;
+use crate::reference::ReferenceData;
 use crate::static_data::GTFSManager;
 use compact_str::CompactString;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use gtfs_realtime::FeedEntity;
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicI64, AtomicUsize};
+use std::sync::{Mutex, RwLock};
 // use std::collections::HashMap; REMOVED
 
 // Platform Map: StopID -> Platform Number REMOVED
@@ -16,6 +22,15 @@ pub struct PlatformInfo {
     pub platform: CompactString,
 }
 
+// A stop Darwin shows as "Delayed" with no estimate it stands behind. Its GTFS-RT
+// arrival/departure is left out, so this is where a client learns why.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UnknownDelayInfo {
+    pub stop_id: CompactString,
+    pub sequence: u32,
+    pub unknown_delay: bool,
+}
+
 pub struct AppState {
     // Map TripID -> GTFS-RT Entity (TripUpdate)
     pub trip_updates: DashMap<CompactString, FeedEntity>,
@@ -25,20 +40,51 @@ pub struct AppState {
     // Map TripID -> List of Platform Info (V2 Schema)
     pub platforms_v2: DashMap<CompactString, Vec<PlatformInfo>>,
 
+    // Map TripID -> stops that have shown an unknown delay, flag cleared once timed again
+    pub unknown_delays: DashMap<CompactString, Vec<UnknownDelayInfo>>,
+
     // Map RID -> Formations
     pub formations: DashMap<CompactString, crate::formations::v2::ScheduleFormations>,
 
-    // Map Station CRS -> List of Messages
-    // For simplicity, we might just store all messages, or map by ID.
-    // Let's map by Message ID for now to avoid duplications, or by Station CRS.
-    // User requirement: "Store".
-    // Let's store by ID.
-    pub station_messages: DashMap<CompactString, String>, // ID -> Msg Content
+    // Map RID -> Darwin Schedule (latest sR / uR schedule record)
+    pub schedules: DashMap<CompactString, crate::darwin_types::Schedule>,
+
+    // Map RID -> JJ/VV/NP associations it takes part in (as main or assoc)
+    pub associations: DashMap<CompactString, Vec<crate::darwin_types::Association>>,
+
+    // RIDs that have had a TS of their own. Divides only fill in portions without one.
+    pub forecast_rids: DashSet<CompactString>,
+
+    // Map RID -> latest formationLoading, joined to its formation by fid
+    pub loadings: DashMap<CompactString, crate::darwin_types::Loading>,
+
+    // Map Alert ID -> GTFS-RT Entity (Alert)
+    // Station messages are keyed "OW_{id}" and train alerts "TA_{id}",
+    // so Darwin's replacements overwrite in place.
+    pub alerts: DashMap<CompactString, FeedEntity>,
 
     // Map RID -> TripID (for TrainOrder and Loading lookups)
     pub rid_to_trip_id: DashMap<CompactString, CompactString>,
 
     pub gtfs: GTFSManager,
+
+    // Darwin reference data: reason texts, location names, operators
+    pub reference: RwLock<ReferenceData>,
+
+    // Unix seconds of the last MESSAGE from Darwin, 0 before the first
+    pub last_message_at: AtomicI64,
+
+    // Messages received but not yet applied, i.e. the ingest backlog
+    pub queue_depth: AtomicUsize,
+
+    // Frames by body encoding and, for those dropped, by reason
+    pub frames: FrameCounters,
+
+    // Messages that failed to parse, kept for /dead-letters
+    pub dead_letters: DeadLetters,
+
+    // Raw traffic, written out for replay when DARWIN_RECORD_DIR is set
+    pub recorder: Recorder,
 }
 
 impl AppState {
@@ -47,10 +93,47 @@ impl AppState {
             trip_updates: DashMap::new(),
             // platforms: DashMap::new(), REMOVED
             platforms_v2: DashMap::new(),
+            unknown_delays: DashMap::new(),
             formations: DashMap::new(),
-            station_messages: DashMap::new(),
+            schedules: DashMap::new(),
+            associations: DashMap::new(),
+            forecast_rids: DashSet::new(),
+            loadings: DashMap::new(),
+            alerts: DashMap::new(),
             rid_to_trip_id: DashMap::new(),
             gtfs: GTFSManager::new(gtfs_url),
+            reference: RwLock::default(),
+            last_message_at: AtomicI64::new(0),
+            queue_depth: AtomicUsize::new(0),
+            frames: FrameCounters::default(),
+            dead_letters: DeadLetters::default(),
+            recorder: Recorder::default(),
         }
     }
 }
+
+// Shown on /status. Every frame is counted under its encoding; one that isn't applied is
+// also counted under why (a FrameError kind).
+#[derive(Default)]
+pub struct FrameCounters {
+    encodings: Mutex<BTreeMap<&'static str, u64>>,
+    dropped: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl FrameCounters {
+    pub fn received(&self, encoding: &'static str) {
+        *self.encodings.lock().unwrap().entry(encoding).or_default() += 1;
+    }
+
+    pub fn dropped(&self, reason: &'static str) {
+        *self.dropped.lock().unwrap().entry(reason).or_default() += 1;
+    }
+
+    pub fn encodings(&self) -> BTreeMap<&'static str, u64> {
+        self.encodings.lock().unwrap().clone()
+    }
+
+    pub fn drops(&self) -> BTreeMap<&'static str, u64> {
+        self.dropped.lock().unwrap().clone()
+    }
+}
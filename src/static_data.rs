@@ -1,38 +1,47 @@
-use anyhow::{Context, Result};
+use crate::darwin_types::{Association, PportTimetable, Schedule};
+use anyhow::Result;
 use chrono::{Datelike, NaiveDate};
 use compact_str::CompactString;
 use gtfs_structures::{Calendar, CalendarDate, Exception, Gtfs, Trip};
+use quick_xml::de::from_reader;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use std::thread;
 use std::time::Duration;
 
+#[derive(Default)]
 pub struct GtfsData {
     pub tiploc_map: HashMap<CompactString, CompactString>,
-    pub uid_index: HashMap<CompactString, Vec<String>>, // UID -> List of TripIDs
+    pub stop_names: HashMap<CompactString, CompactString>, // StopID -> Name
+    pub uid_index: HashMap<CompactString, Vec<String>>,    // UID -> List of TripIDs
     pub trips: HashMap<CompactString, Trip>,
     pub calendar: HashMap<CompactString, Calendar>,
     pub calendar_dates: HashMap<CompactString, Vec<CalendarDate>>,
     pub trip_start_times: HashMap<CompactString, u32>,
+    pub toc_routes: HashMap<CompactString, CompactString>, // TOC (agency_id) -> RouteID
 }
 
-impl Default for GtfsData {
-    fn default() -> Self {
-        Self {
-            tiploc_map: HashMap::new(),
-            uid_index: HashMap::new(),
-            trips: HashMap::new(),
-            calendar: HashMap::new(),
-            calendar_dates: HashMap::new(),
-            trip_start_times: HashMap::new(),
-        }
-    }
+// TIPLOC <-> CRS from Darwin's reference data, kept apart from GtfsData so it survives reloads
+#[derive(Default)]
+struct CrsCodes {
+    tiploc_to_crs: HashMap<CompactString, CompactString>,
+    crs_to_tiplocs: HashMap<CompactString, Vec<CompactString>>,
+}
+
+// Darwin's daily timetable (DARWIN_TIMETABLE), for services the static GTFS doesn't carry
+#[derive(Default)]
+struct DarwinTimetable {
+    timetable_id: CompactString,
+    journeys: HashMap<CompactString, Schedule>, // RID -> Journey
+    associations: HashMap<CompactString, Vec<Association>>, // RID -> Associations it takes part in
 }
 
 pub struct GTFSManager {
     url: String,
     // Use Arc<RwLock> to allow safe sharing between the updater thread and the main application
     data: Arc<RwLock<GtfsData>>,
+    crs_codes: RwLock<CrsCodes>,
+    timetable: RwLock<DarwinTimetable>,
 }
 
 impl GTFSManager {
@@ -40,6 +49,18 @@ impl GTFSManager {
         Self {
             url,
             data: Arc::new(RwLock::new(GtfsData::default())),
+            crs_codes: RwLock::default(),
+            timetable: RwLock::default(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn from_data(data: GtfsData) -> Self {
+        Self {
+            url: String::new(),
+            data: Arc::new(RwLock::new(data)),
+            crs_codes: RwLock::default(),
+            timetable: RwLock::default(),
         }
     }
 
@@ -91,9 +112,92 @@ impl GTFSManager {
         if let Some(id) = data.tiploc_map.get(tiploc) {
             return Some(id.clone());
         }
-        None
+        // Other TIPLOCs at the same station (e.g. a separate platform group) share its CRS
+        let codes = self.crs_codes.read().unwrap();
+        let crs = codes.tiploc_to_crs.get(tiploc)?;
+        data.tiploc_map.get(crs).cloned()
     }
 
+    pub fn get_stop_name(&self, stop_id: &str) -> Option<CompactString> {
+        self.data.read().unwrap().stop_names.get(stop_id).cloned()
+    }
+
+    // Stop codes in the static feed are CRS codes, so they share the TIPLOC map
+    pub fn get_stop_id_for_crs(&self, crs: &str) -> Option<CompactString> {
+        let data = self.data.read().unwrap();
+        if let Some(id) = data.tiploc_map.get(crs) {
+            return Some(id.clone());
+        }
+        // Stations the static feed only knows by TIPLOC
+        let codes = self.crs_codes.read().unwrap();
+        codes
+            .crs_to_tiplocs
+            .get(crs)?
+            .iter()
+            .find_map(|tiploc| data.tiploc_map.get(tiploc).cloned())
+    }
+
+    // TIPLOC -> CRS pairs from the Darwin reference data, to fill gaps in the static stops
+    pub fn set_crs_codes<'a>(&self, pairs: impl Iterator<Item = (&'a str, &'a str)>) {
+        let mut codes = CrsCodes::default();
+        for (tiploc, crs) in pairs {
+            codes.tiploc_to_crs.insert(tiploc.into(), crs.into());
+            codes
+                .crs_to_tiplocs
+                .entry(crs.into())
+                .or_default()
+                .push(tiploc.into());
+        }
+        *self.crs_codes.write().unwrap() = codes;
+    }
+
+    // Replace the Darwin timetable with a PportTimetable file (gzipped or not), parsed as it
+    // inflates rather than holding the whole document as text
+    pub fn load_timetable(&self, body: &[u8]) -> Result<()> {
+        let file: PportTimetable = from_reader(crate::xml_reader(body))?;
+
+        let mut timetable = DarwinTimetable {
+            timetable_id: file.timetable_id,
+            ..Default::default()
+        };
+        for journey in file.journeys {
+            timetable.journeys.insert(journey.rid.clone(), journey);
+        }
+        for assoc in file.associations.into_iter().filter(|a| !a.is_deleted) {
+            for rid in [&assoc.main.rid, &assoc.assoc.rid] {
+                timetable
+                    .associations
+                    .entry(rid.clone())
+                    .or_default()
+                    .push(assoc.clone());
+            }
+        }
+
+        log_info(&format!(
+            "Loaded Darwin timetable {} ({} journeys, {} associated services)",
+            timetable.timetable_id,
+            timetable.journeys.len(),
+            timetable.associations.len()
+        ));
+        *self.timetable.write().unwrap() = timetable;
+        Ok(())
+    }
+
+    pub fn timetable_journey(&self, rid: &str) -> Option<Schedule> {
+        self.timetable.read().unwrap().journeys.get(rid).cloned()
+    }
+
+    pub fn timetable_associations(&self, rid: &str) -> Vec<Association> {
+        self.timetable
+            .read()
+            .unwrap()
+            .associations
+            .get(rid)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[allow(dead_code)]
     pub fn unwrap_stop_id(&self, tiploc: &str) -> CompactString {
         self.get_stop_id(tiploc)
             .unwrap_or_else(|| CompactString::from(tiploc))
@@ -107,10 +211,10 @@ impl GTFSManager {
             for trip_id in candidates {
                 // 2. Check service calendar
                 // trip_id in uid_index is String (Vec<String>), need to handle lookup
-                if let Some(trip) = data.trips.get(trip_id.as_str()) {
-                    if self.service_runs_on_date(&data, &trip.service_id, date) {
-                        return Some(CompactString::from(trip_id));
-                    }
+                if let Some(trip) = data.trips.get(trip_id.as_str())
+                    && self.service_runs_on_date(&data, &trip.service_id, date)
+                {
+                    return Some(CompactString::from(trip_id));
                 }
             }
         }
@@ -126,18 +230,34 @@ impl GTFSManager {
             .cloned()
     }
 
+    // Route for trips the static GTFS doesn't carry: the operator's lowest route_id,
+    // so it stays the same across reloads
+    pub fn get_route_for_toc(&self, toc: &str) -> Option<CompactString> {
+        self.data.read().unwrap().toc_routes.get(toc).cloned()
+    }
+
     pub fn get_trip_stops(&self, trip_id: &str) -> Option<Vec<(CompactString, u32)>> {
         let data = self.data.read().unwrap();
-        if let Some(trip) = data.trips.get(trip_id) {
-            Some(
-                trip.stop_times
-                    .iter()
-                    .map(|st| (CompactString::from(st.stop.id.clone()), st.stop_sequence))
-                    .collect(),
-            )
-        } else {
-            None
-        }
+        data.trips.get(trip_id).map(|trip| {
+            trip.stop_times
+                .iter()
+                .map(|st| (CompactString::from(st.stop.id.clone()), st.stop_sequence))
+                .collect()
+        })
+    }
+
+    // Scheduled (arrival, departure) as seconds past the service day's noon-minus-12h
+    pub fn get_stop_time_secs(
+        &self,
+        trip_id: &str,
+        seq: u32,
+    ) -> Option<(Option<u32>, Option<u32>)> {
+        let data = self.data.read().unwrap();
+        let trip = data.trips.get(trip_id)?;
+        trip.stop_times
+            .iter()
+            .find(|st| st.stop_sequence == seq)
+            .map(|st| (st.arrival_time, st.departure_time))
     }
 
     fn service_runs_on_date(&self, data: &GtfsData, service_id: &str, date: NaiveDate) -> bool {
@@ -155,27 +275,29 @@ impl GTFSManager {
         }
 
         // Check Calendar
-        if let Some(cal) = data.calendar.get(service_id) {
-            if date >= cal.start_date && date <= cal.end_date {
-                let runs = match date.weekday() {
-                    chrono::Weekday::Mon => cal.monday,
-                    chrono::Weekday::Tue => cal.tuesday,
-                    chrono::Weekday::Wed => cal.wednesday,
-                    chrono::Weekday::Thu => cal.thursday,
-                    chrono::Weekday::Fri => cal.friday,
-                    chrono::Weekday::Sat => cal.saturday,
-                    chrono::Weekday::Sun => cal.sunday,
-                };
-
-                if runs {
-                    return true;
-                }
+        if let Some(cal) = data.calendar.get(service_id)
+            && date >= cal.start_date
+            && date <= cal.end_date
+        {
+            let runs = match date.weekday() {
+                chrono::Weekday::Mon => cal.monday,
+                chrono::Weekday::Tue => cal.tuesday,
+                chrono::Weekday::Wed => cal.wednesday,
+                chrono::Weekday::Thu => cal.thursday,
+                chrono::Weekday::Fri => cal.friday,
+                chrono::Weekday::Sat => cal.saturday,
+                chrono::Weekday::Sun => cal.sunday,
+            };
+
+            if runs {
+                return true;
             }
         }
 
         false
     }
 
+    #[allow(dead_code)]
     pub fn has_data(&self) -> bool {
         !self.data.read().unwrap().tiploc_map.is_empty()
     }
@@ -194,6 +316,10 @@ impl GTFSManager {
         for (id, stop) in &gtfs.stops {
             data.tiploc_map
                 .insert(CompactString::from(id), CompactString::from(id));
+            if let Some(name) = &stop.name {
+                data.stop_names
+                    .insert(CompactString::from(id), CompactString::from(name));
+            }
             if let Some(code) = &stop.code {
                 data.tiploc_map
                     .insert(CompactString::from(code), CompactString::from(id));
@@ -217,6 +343,21 @@ impl GTFSManager {
         }
         log_info("Built UID index");
 
+        // Routes by operator
+        for (route_id, route) in &gtfs.routes {
+            if let Some(agency_id) = &route.agency_id {
+                data.toc_routes
+                    .entry(CompactString::from(agency_id))
+                    .and_modify(|r| {
+                        if route_id.as_str() < r.as_str() {
+                            *r = CompactString::from(route_id);
+                        }
+                    })
+                    .or_insert_with(|| CompactString::from(route_id));
+            }
+        }
+        log_info("Built TOC routes");
+
         // Calendar
         for (service_id, cal) in &gtfs.calendar {
             data.calendar
@@ -0,0 +1,927 @@
+// Minimal STOMP 1.2 client for the Darwin Push Port.
+// The codec and session are generic over AsyncRead/AsyncWrite so they run against
+// in-memory streams in tests as well as TCP and TLS.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+    ReadHalf, WriteHalf,
+};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+// Larger bodies mean a corrupt stream, not a Darwin message
+const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+
+// Darwin STOMP connection settings, from the environment
+pub struct StompConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub destination: String,
+    // Durable subscription: the broker keeps our messages while we're away
+    // (DARWIN_CLIENT_ID, DARWIN_SUBSCRIPTION_NAME). Not needed for a /queue/ destination.
+    pub client_id: Option<String>,
+    pub subscription_name: Option<String>,
+    // Heart-beat interval offered in both directions (DARWIN_HEARTBEAT_MS, 0 disables)
+    pub heartbeat: Duration,
+    // Read timeout when the broker won't send heart-beats (DARWIN_IDLE_TIMEOUT_SECS)
+    pub idle_timeout: Duration,
+    // Limit on the TCP connect and TLS handshake (DARWIN_CONNECT_TIMEOUT_SECS)
+    pub connect_timeout: Duration,
+    // STOMP over SSL (DARWIN_TLS=true), trusting DARWIN_CA_BUNDLE as well as the system roots
+    pub tls: Option<tokio_native_tls::TlsConnector>,
+}
+
+impl StompConfig {
+    pub fn from_env() -> Self {
+        let heartbeat_ms: u64 = std::env::var("DARWIN_HEARTBEAT_MS")
+            .unwrap_or_else(|_| "15000".to_string())
+            .parse()
+            .expect("Invalid DARWIN_HEARTBEAT_MS");
+        let idle_timeout_secs: u64 = std::env::var("DARWIN_IDLE_TIMEOUT_SECS")
+            .unwrap_or_else(|_| "300".to_string())
+            .parse()
+            .expect("Invalid DARWIN_IDLE_TIMEOUT_SECS");
+        let connect_timeout_secs: u64 = std::env::var("DARWIN_CONNECT_TIMEOUT_SECS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse()
+            .expect("Invalid DARWIN_CONNECT_TIMEOUT_SECS");
+        let client_id = std::env::var("DARWIN_CLIENT_ID").ok();
+        let tls = std::env::var("DARWIN_TLS")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false)
+            .then(|| {
+                let ca_bundle = std::env::var("DARWIN_CA_BUNDLE").ok();
+                tls_connector(ca_bundle.as_deref()).expect("Invalid Darwin TLS setup")
+            });
+        let default_port = if tls.is_some() { "61614" } else { "61613" };
+
+        Self {
+            host: std::env::var("DARWIN_HOST")
+                .unwrap_or_else(|_| "darwin-dist-44ae45.nationalrail.co.uk".to_string()),
+            port: std::env::var("DARWIN_PORT")
+                .unwrap_or_else(|_| default_port.to_string())
+                .parse()
+                .expect("Invalid DARWIN_PORT"),
+            username: std::env::var("DARWIN_USER").expect("DARWIN_USER not set"),
+            password: std::env::var("DARWIN_PASS").expect("DARWIN_PASS not set"),
+            destination: std::env::var("DARWIN_DESTINATION")
+                .unwrap_or_else(|_| "/topic/darwin.pushport-v16".to_string()),
+            subscription_name: std::env::var("DARWIN_SUBSCRIPTION_NAME")
+                .ok()
+                .or_else(|| client_id.clone()),
+            client_id,
+            heartbeat: Duration::from_millis(heartbeat_ms),
+            idle_timeout: Duration::from_secs(idle_timeout_secs),
+            connect_timeout: Duration::from_secs(connect_timeout_secs),
+            tls,
+        }
+    }
+}
+
+// System roots plus every certificate in the PEM bundle, if given
+pub fn tls_connector(
+    ca_bundle: Option<&str>,
+) -> Result<tokio_native_tls::TlsConnector, StompError> {
+    let mut builder = native_tls::TlsConnector::builder();
+    if let Some(path) = ca_bundle {
+        let pem = std::fs::read_to_string(path)?;
+        let certs: Vec<&str> = pem
+            .split_inclusive("-----END CERTIFICATE-----")
+            .filter(|block| block.contains("-----BEGIN CERTIFICATE-----"))
+            .collect();
+        if certs.is_empty() {
+            return Err(StompError::Protocol(format!(
+                "No certificates in CA bundle {}",
+                path
+            )));
+        }
+        for cert in certs {
+            builder.add_root_certificate(native_tls::Certificate::from_pem(cert.as_bytes())?);
+        }
+    }
+    Ok(builder.build()?.into())
+}
+
+#[derive(Debug)]
+pub enum StompError {
+    Io(std::io::Error),
+    Tls(native_tls::Error),
+    // The broker closed the connection
+    Closed,
+    // Nothing, not even a heart-beat, within the read timeout
+    Timeout(Duration),
+    // TCP connect or TLS handshake didn't finish within the connect timeout
+    ConnectTimeout(Duration),
+    // Bytes that aren't a STOMP 1.2 frame
+    Protocol(String),
+    // ERROR in reply to CONNECT, usually bad credentials
+    LoginRejected(String),
+    // ERROR once connected; the broker closes the connection after it
+    Broker(String),
+    // Anything but CONNECTED or ERROR in reply to CONNECT
+    UnexpectedFrame(String),
+}
+
+impl std::fmt::Display for StompError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StompError::Io(e) => write!(f, "I/O error: {}", e),
+            StompError::Tls(e) => write!(f, "TLS error: {}", e),
+            StompError::Closed => write!(f, "Connection closed by Darwin"),
+            StompError::Timeout(after) => {
+                write!(
+                    f,
+                    "Nothing from Darwin for {:?}, dropping connection",
+                    after
+                )
+            }
+            StompError::ConnectTimeout(after) => {
+                write!(f, "Couldn't reach Darwin within {:?}", after)
+            }
+            StompError::Protocol(message) => write!(f, "Malformed STOMP frame: {}", message),
+            StompError::LoginRejected(message) => write!(f, "Darwin rejected login: {}", message),
+            StompError::Broker(message) => write!(f, "Darwin sent ERROR: {}", message),
+            StompError::UnexpectedFrame(command) => {
+                write!(f, "Expected CONNECTED from Darwin, got {}", command)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StompError {}
+
+impl From<std::io::Error> for StompError {
+    fn from(e: std::io::Error) -> Self {
+        StompError::Io(e)
+    }
+}
+
+impl From<native_tls::Error> for StompError {
+    fn from(e: native_tls::Error) -> Self {
+        StompError::Tls(e)
+    }
+}
+
+// Headers in wire order. A repeated header keeps its first value, as STOMP 1.2 requires.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headers(Vec<(String, String)>);
+
+impl Headers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.0.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    // Client frames
+    Connect(Headers),
+    Subscribe(Headers),
+    Ack(Headers),
+    Nack(Headers),
+    Disconnect(Headers),
+    // Server frames
+    Connected(Headers),
+    Message(Headers, Vec<u8>),
+    Receipt(Headers),
+    Error(Headers, Vec<u8>),
+    // A bare EOL between frames
+    HeartBeat,
+}
+
+impl Frame {
+    pub fn command(&self) -> &'static str {
+        match self {
+            Frame::Connect(_) => "CONNECT",
+            Frame::Subscribe(_) => "SUBSCRIBE",
+            Frame::Ack(_) => "ACK",
+            Frame::Nack(_) => "NACK",
+            Frame::Disconnect(_) => "DISCONNECT",
+            Frame::Connected(_) => "CONNECTED",
+            Frame::Message(..) => "MESSAGE",
+            Frame::Receipt(_) => "RECEIPT",
+            Frame::Error(..) => "ERROR",
+            Frame::HeartBeat => "",
+        }
+    }
+
+    pub fn headers(&self) -> Option<&Headers> {
+        match self {
+            Frame::Connect(h)
+            | Frame::Subscribe(h)
+            | Frame::Ack(h)
+            | Frame::Nack(h)
+            | Frame::Disconnect(h)
+            | Frame::Connected(h)
+            | Frame::Message(h, _)
+            | Frame::Receipt(h)
+            | Frame::Error(h, _) => Some(h),
+            Frame::HeartBeat => None,
+        }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers()?.get(name)
+    }
+
+    fn body(&self) -> &[u8] {
+        match self {
+            Frame::Message(_, body) | Frame::Error(_, body) => body,
+            _ => &[],
+        }
+    }
+
+    // CONNECT and CONNECTED predate header escaping and are sent as-is
+    fn escapes_headers(command: &str) -> bool {
+        command != "CONNECT" && command != "CONNECTED"
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        if let Frame::HeartBeat = self {
+            return b"\n".to_vec();
+        }
+        let command = self.command();
+        let escape = Self::escapes_headers(command);
+        let body = self.body();
+
+        let mut out = Vec::with_capacity(64 + body.len());
+        out.extend_from_slice(command.as_bytes());
+        out.push(b'\n');
+        if let Some(headers) = self.headers() {
+            for (name, value) in headers.iter() {
+                if escape {
+                    out.extend_from_slice(escape_header(name).as_bytes());
+                    out.push(b':');
+                    out.extend_from_slice(escape_header(value).as_bytes());
+                } else {
+                    out.extend_from_slice(name.as_bytes());
+                    out.push(b':');
+                    out.extend_from_slice(value.as_bytes());
+                }
+                out.push(b'\n');
+            }
+            if !body.is_empty() && headers.get("content-length").is_none() {
+                out.extend_from_slice(format!("content-length:{}\n", body.len()).as_bytes());
+            }
+        }
+        out.push(b'\n');
+        out.extend_from_slice(body);
+        out.push(0);
+        out
+    }
+
+    // ERROR frames carry a short `message` header and the detail in the body
+    pub fn error_message(&self) -> String {
+        let message = self.header("message").unwrap_or("no message");
+        let details = String::from_utf8_lossy(self.body());
+        let details = details.trim();
+        if details.is_empty() {
+            message.to_string()
+        } else {
+            format!("{} ({})", message, details)
+        }
+    }
+}
+
+fn escape_header(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\r' => out.push_str("\\r"),
+            '\n' => out.push_str("\\n"),
+            ':' => out.push_str("\\c"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape_header(value: &str) -> Result<String, StompError> {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('r') => out.push('\r'),
+            Some('n') => out.push('\n'),
+            Some('c') => out.push(':'),
+            other => {
+                return Err(StompError::Protocol(format!(
+                    "undefined header escape \\{}",
+                    other.map(String::from).unwrap_or_default()
+                )));
+            }
+        }
+    }
+    Ok(out)
+}
+
+// One line without its EOL (LF or CRLF). EOF before any byte is a closed connection.
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<String, StompError> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line).await? == 0 {
+        return Err(StompError::Closed);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+    String::from_utf8(line).map_err(|_| StompError::Protocol("header is not UTF-8".to_string()))
+}
+
+pub async fn read_frame<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Frame, StompError> {
+    // 1. Command. A bare EOL between frames is a heart-beat.
+    let command = read_line(reader).await?;
+    if command.is_empty() {
+        return Ok(Frame::HeartBeat);
+    }
+    let escape = Frame::escapes_headers(&command);
+
+    // 2. Headers, up to the blank line
+    let mut headers = Headers::new();
+    let mut content_length = None;
+    loop {
+        let line = read_line(reader).await?;
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(StompError::Protocol(format!(
+                "header without ':': {}",
+                line
+            )));
+        };
+        let (name, value) = if escape {
+            (unescape_header(name)?, unescape_header(value)?)
+        } else {
+            (name.to_string(), value.to_string())
+        };
+        if name == "content-length" && content_length.is_none() {
+            let len: usize = value
+                .trim()
+                .parse()
+                .map_err(|_| StompError::Protocol(format!("bad content-length {}", value)))?;
+            if len > MAX_BODY_LEN {
+                return Err(StompError::Protocol(format!("body of {} bytes", len)));
+            }
+            content_length = Some(len);
+        }
+        headers.0.push((name, value));
+    }
+
+    // 3. Body: content-length bytes then NUL, or everything up to the first NUL
+    let body = match content_length {
+        Some(len) => {
+            let mut body = vec![0u8; len];
+            reader.read_exact(&mut body).await?;
+            let mut null_byte = [0u8; 1];
+            reader.read_exact(&mut null_byte).await?;
+            if null_byte[0] != 0 {
+                return Err(StompError::Protocol("body not followed by NUL".to_string()));
+            }
+            body
+        }
+        None => {
+            let mut body = Vec::new();
+            let mut limited = (&mut *reader).take(MAX_BODY_LEN as u64 + 1);
+            limited.read_until(0, &mut body).await?;
+            if body.pop() != Some(0) {
+                return Err(StompError::Protocol(
+                    "body not terminated by NUL".to_string(),
+                ));
+            }
+            body
+        }
+    };
+
+    match command.as_str() {
+        "CONNECT" | "STOMP" => Ok(Frame::Connect(headers)),
+        "SUBSCRIBE" => Ok(Frame::Subscribe(headers)),
+        "ACK" => Ok(Frame::Ack(headers)),
+        "NACK" => Ok(Frame::Nack(headers)),
+        "DISCONNECT" => Ok(Frame::Disconnect(headers)),
+        "CONNECTED" => Ok(Frame::Connected(headers)),
+        "MESSAGE" => Ok(Frame::Message(headers, body)),
+        "RECEIPT" => Ok(Frame::Receipt(headers)),
+        "ERROR" => Ok(Frame::Error(headers, body)),
+        other => Err(StompError::Protocol(format!("unknown command {}", other))),
+    }
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    frame: &Frame,
+) -> Result<(), StompError> {
+    writer.write_all(&frame.encode()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+// STOMP 1.2 heart-beat negotiation. We offer `ours` both ways; the server replies "sx,sy".
+// Returns (how often we must send, how often the server will send); zero means none.
+pub fn negotiate_heartbeat(ours: Duration, server: Option<&str>) -> (Duration, Duration) {
+    let (sx, sy) = server
+        .and_then(|hb| hb.split_once(','))
+        .and_then(|(sx, sy)| {
+            Some((
+                sx.trim().parse::<u64>().ok()?,
+                sy.trim().parse::<u64>().ok()?,
+            ))
+        })
+        .unwrap_or((0, 0));
+    let ours = ours.as_millis() as u64;
+
+    let every = |a: u64, b: u64| {
+        if a == 0 || b == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_millis(a.max(b))
+        }
+    };
+    (every(ours, sy), every(sx, ours))
+}
+
+pub fn connect_frame(config: &StompConfig) -> Frame {
+    let heartbeat_ms = config.heartbeat.as_millis();
+    let mut headers = Headers::new()
+        .with("accept-version", "1.2")
+        .with("login", config.username.as_str())
+        .with("passcode", config.password.as_str())
+        .with("heart-beat", format!("{},{}", heartbeat_ms, heartbeat_ms));
+    if let Some(client_id) = &config.client_id {
+        headers = headers.with("client-id", client_id.as_str());
+    }
+    Frame::Connect(headers)
+}
+
+pub fn subscribe_frame(config: &StompConfig) -> Frame {
+    let mut headers = Headers::new()
+        .with("id", "0")
+        .with("destination", config.destination.as_str())
+        .with("ack", "client-individual");
+    // ActiveMQ makes a topic subscription durable when it has a name and the connection a client-id
+    if config.client_id.is_some()
+        && let Some(name) = &config.subscription_name
+    {
+        headers = headers.with("activemq.subscriptionName", name.as_str());
+    }
+    Frame::Subscribe(headers)
+}
+
+// A MESSAGE delivered on our subscription
+pub struct Message {
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl Message {
+    // STOMP 1.2 brokers send `ack`; older ones only `message-id`
+    pub fn ack_id(&self) -> Option<&str> {
+        self.headers
+            .get("ack")
+            .or_else(|| self.headers.get("message-id"))
+    }
+}
+
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Transport for T {}
+
+// Open TCP (and TLS if configured) to the broker and log in
+pub async fn connect(config: &StompConfig) -> Result<Connection<Box<dyn Transport>>, StompError> {
+    let timeout = |_| StompError::ConnectTimeout(config.connect_timeout);
+    let tcp = tokio::time::timeout(
+        config.connect_timeout,
+        TcpStream::connect((config.host.as_str(), config.port)),
+    )
+    .await
+    .map_err(timeout)??;
+    let stream: Box<dyn Transport> = match &config.tls {
+        Some(tls) => Box::new(
+            tokio::time::timeout(config.connect_timeout, tls.connect(&config.host, tcp))
+                .await
+                .map_err(timeout)??,
+        ),
+        None => Box::new(tcp),
+    };
+    Connection::open(stream, config).await
+}
+
+// A logged-in, subscribed STOMP session
+pub struct Connection<S> {
+    reader: BufReader<ReadHalf<S>>,
+    // Shared with the heart-beat task
+    writer: Arc<Mutex<WriteHalf<S>>>,
+    read_timeout: Duration,
+    heartbeat_task: Option<JoinHandle<()>>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Send + 'static> Connection<S> {
+    // CONNECT, wait for CONNECTED, SUBSCRIBE, then start heart-beating
+    pub async fn open(stream: S, config: &StompConfig) -> Result<Self, StompError> {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+
+        write_frame(&mut writer, &connect_frame(config)).await?;
+
+        let connected = loop {
+            match tokio::time::timeout(config.idle_timeout, read_frame(&mut reader)).await {
+                Ok(Ok(Frame::HeartBeat)) => continue,
+                Ok(frame) => break frame?,
+                Err(_) => return Err(StompError::Timeout(config.idle_timeout)),
+            }
+        };
+        let server_heartbeat = match &connected {
+            Frame::Connected(headers) => headers.get("heart-beat"),
+            Frame::Error(..) => return Err(StompError::LoginRejected(connected.error_message())),
+            other => return Err(StompError::UnexpectedFrame(other.command().to_string())),
+        };
+        let (send_every, expect_every) = negotiate_heartbeat(config.heartbeat, server_heartbeat);
+        println!(
+            "Logged in to Darwin (heart-beats: send {:?}, expect {:?}).",
+            send_every, expect_every
+        );
+
+        write_frame(&mut writer, &subscribe_frame(config)).await?;
+        let writer = Arc::new(Mutex::new(writer));
+
+        // Heart-beats out: a bare EOL every interval keeps the broker from dropping us
+        let heartbeat_task = (!send_every.is_zero()).then(|| {
+            let writer = writer.clone();
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(send_every);
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    if write_frame(&mut *writer.lock().await, &Frame::HeartBeat)
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            })
+        });
+
+        // Heart-beats in: allow twice the agreed interval before calling the connection dead
+        let read_timeout = if expect_every.is_zero() {
+            config.idle_timeout
+        } else {
+            expect_every * 2
+        };
+
+        Ok(Self {
+            reader,
+            writer,
+            read_timeout,
+            heartbeat_task,
+        })
+    }
+
+    // Next MESSAGE. Heart-beats and receipts are consumed here; an ERROR ends the session.
+    pub async fn next_message(&mut self) -> Result<Message, StompError> {
+        loop {
+            let frame =
+                match tokio::time::timeout(self.read_timeout, read_frame(&mut self.reader)).await {
+                    Ok(frame) => frame?,
+                    Err(_) => return Err(StompError::Timeout(self.read_timeout)),
+                };
+            match frame {
+                Frame::Message(headers, body) => return Ok(Message { headers, body }),
+                Frame::HeartBeat => {}
+                Frame::Error(..) => return Err(StompError::Broker(frame.error_message())),
+                Frame::Receipt(headers) => {
+                    println!(
+                        "STOMP RECEIPT: {}",
+                        headers.get("receipt-id").unwrap_or("?")
+                    );
+                }
+                other => eprintln!("Ignoring unexpected STOMP {} frame", other.command()),
+            }
+        }
+    }
+
+    // ACKs can be sent from elsewhere while this connection keeps reading
+    pub fn acker(&self) -> Acker<S> {
+        Acker {
+            writer: self.writer.clone(),
+        }
+    }
+}
+
+pub struct Acker<S> {
+    writer: Arc<Mutex<WriteHalf<S>>>,
+}
+
+impl<S> Clone for Acker<S> {
+    fn clone(&self) -> Self {
+        Self {
+            writer: self.writer.clone(),
+        }
+    }
+}
+
+impl<S: AsyncWrite> Acker<S> {
+    // `id` is the message's ack_id(). The subscription is client-individual, so order doesn't matter.
+    pub async fn ack(&self, id: &str) -> Result<(), StompError> {
+        let frame = Frame::Ack(Headers::new().with("id", id));
+        write_frame(&mut *self.writer.lock().await, &frame).await
+    }
+}
+
+impl<S> Drop for Connection<S> {
+    fn drop(&mut self) {
+        if let Some(task) = self.heartbeat_task.take() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn test_config() -> StompConfig {
+        StompConfig {
+            host: "localhost".to_string(),
+            port: 61613,
+            username: "user".to_string(),
+            password: "pass".to_string(),
+            destination: "/topic/darwin.pushport-v16".to_string(),
+            client_id: None,
+            subscription_name: None,
+            heartbeat: Duration::from_millis(15000),
+            idle_timeout: Duration::from_secs(300),
+            connect_timeout: Duration::from_secs(30),
+            tls: None,
+        }
+    }
+
+    #[test]
+    fn heartbeat_negotiation_takes_the_slower_side() {
+        let ours = Duration::from_millis(15000);
+        assert_eq!(
+            negotiate_heartbeat(ours, Some("5000,30000")),
+            (Duration::from_millis(30000), Duration::from_millis(15000))
+        );
+        // Server can't send, or doesn't want ours
+        assert_eq!(
+            negotiate_heartbeat(ours, Some("0,0")),
+            (Duration::ZERO, Duration::ZERO)
+        );
+        assert_eq!(
+            negotiate_heartbeat(ours, None),
+            (Duration::ZERO, Duration::ZERO)
+        );
+        assert_eq!(
+            negotiate_heartbeat(Duration::ZERO, Some("10000,10000")),
+            (Duration::ZERO, Duration::ZERO)
+        );
+    }
+
+    #[tokio::test]
+    async fn read_frame_reports_heartbeats_between_frames() {
+        let mut input: &[u8] = b"\nMESSAGE\ncontent-length:2\nack:7\n\nhi\0\r\n";
+        assert_eq!(read_frame(&mut input).await.unwrap(), Frame::HeartBeat);
+
+        let frame = read_frame(&mut input).await.unwrap();
+        assert_eq!(
+            frame,
+            Frame::Message(
+                Headers::new().with("content-length", "2").with("ack", "7"),
+                b"hi".to_vec()
+            )
+        );
+
+        assert_eq!(read_frame(&mut input).await.unwrap(), Frame::HeartBeat);
+        assert!(matches!(
+            read_frame(&mut input).await,
+            Err(StompError::Closed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn body_without_content_length_ends_at_nul() {
+        let mut input: &[u8] = b"MESSAGE\r\nack:1\r\n\r\n<Pport/>\0RECEIPT\nreceipt-id:9\n\n\0";
+        let frame = read_frame(&mut input).await.unwrap();
+        assert_eq!(frame.header("ack"), Some("1"));
+        assert!(matches!(frame, Frame::Message(_, ref body) if body == b"<Pport/>"));
+        assert_eq!(
+            read_frame(&mut input).await.unwrap().header("receipt-id"),
+            Some("9")
+        );
+
+        // Truncated before the NUL
+        let mut input: &[u8] = b"MESSAGE\nack:1\n\n<Pport";
+        assert!(matches!(
+            read_frame(&mut input).await,
+            Err(StompError::Protocol(_))
+        ));
+        // content-length that overruns the NUL
+        let mut input: &[u8] = b"MESSAGE\ncontent-length:1\n\nab\0";
+        assert!(matches!(
+            read_frame(&mut input).await,
+            Err(StompError::Protocol(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn headers_are_escaped_except_on_connect() {
+        let frame = Frame::Message(Headers::new().with("selector", "a:b\\c\nd\re"), Vec::new());
+        let encoded = frame.encode();
+        assert_eq!(encoded, b"MESSAGE\nselector:a\\cb\\\\c\\nd\\re\n\n\0");
+        assert_eq!(read_frame(&mut encoded.as_slice()).await.unwrap(), frame);
+
+        // The first ':' separates; later ones are kept verbatim on CONNECTED
+        let mut input: &[u8] = b"CONNECTED\nserver:ActiveMQ/5.15\\n\nsession:ID:host:1\n\n\0";
+        let frame = read_frame(&mut input).await.unwrap();
+        assert_eq!(frame.header("server"), Some("ActiveMQ/5.15\\n"));
+        assert_eq!(frame.header("session"), Some("ID:host:1"));
+
+        // Repeated header: first value wins
+        let mut input: &[u8] = b"RECEIPT\nreceipt-id:1\nreceipt-id:2\n\n\0";
+        assert_eq!(
+            read_frame(&mut input).await.unwrap().header("receipt-id"),
+            Some("1")
+        );
+
+        let mut input: &[u8] = b"MESSAGE\nbad:\\t\n\n\0";
+        assert!(matches!(
+            read_frame(&mut input).await,
+            Err(StompError::Protocol(_))
+        ));
+        let mut input: &[u8] = b"SEND\n\n\0";
+        assert!(matches!(
+            read_frame(&mut input).await,
+            Err(StompError::Protocol(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn error_frame_carries_message_header_and_body() {
+        let mut input: &[u8] =
+            b"ERROR\nmessage:Authentication failed\ncontent-length:12\n\nbad passcode\0";
+        let frame = read_frame(&mut input).await.unwrap();
+        assert_eq!(
+            StompError::LoginRejected(frame.error_message()).to_string(),
+            "Darwin rejected login: Authentication failed (bad passcode)"
+        );
+    }
+
+    #[test]
+    fn durable_subscription_sends_client_id_and_subscription_name() {
+        let plain = test_config();
+        assert_eq!(connect_frame(&plain).header("client-id"), None);
+        assert_eq!(
+            subscribe_frame(&plain).header("activemq.subscriptionName"),
+            None
+        );
+
+        let durable = StompConfig {
+            client_id: Some("gtfs-rt-1".to_string()),
+            subscription_name: Some("gtfs-rt-1".to_string()),
+            ..test_config()
+        };
+        assert_eq!(
+            connect_frame(&durable).encode(),
+            b"CONNECT\naccept-version:1.2\nlogin:user\npasscode:pass\nheart-beat:15000,15000\nclient-id:gtfs-rt-1\n\n\0"
+        );
+        assert_eq!(
+            subscribe_frame(&durable).encode(),
+            b"SUBSCRIBE\nid:0\ndestination:/topic/darwin.pushport-v16\nack:client-individual\nactivemq.subscriptionName:gtfs-rt-1\n\n\0"
+        );
+    }
+
+    // Whole session against an in-memory broker
+    #[tokio::test]
+    async fn session_over_duplex_stream() {
+        let (client, broker) = tokio::io::duplex(4096);
+        let broker = tokio::spawn(async move {
+            let (reader, mut writer) = tokio::io::split(broker);
+            let mut reader = BufReader::new(reader);
+
+            let connect = read_frame(&mut reader).await.unwrap();
+            write_frame(
+                &mut writer,
+                &Frame::Connected(Headers::new().with("version", "1.2")),
+            )
+            .await
+            .unwrap();
+            let subscribe = read_frame(&mut reader).await.unwrap();
+            writer
+                .write_all(b"\n\nRECEIPT\nreceipt-id:0\n\n\0MESSAGE\nmessage-id:m1\n\nbody\0")
+                .await
+                .unwrap();
+            let ack = read_frame(&mut reader).await.unwrap();
+            write_frame(
+                &mut writer,
+                &Frame::Error(Headers::new().with("message", "shutting down"), Vec::new()),
+            )
+            .await
+            .unwrap();
+            (connect, subscribe, ack)
+        });
+
+        let config = test_config();
+        let mut connection = Connection::open(client, &config).await.unwrap();
+        let message = connection.next_message().await.unwrap();
+        assert_eq!(message.body, b"body");
+        connection
+            .acker()
+            .ack(message.ack_id().unwrap())
+            .await
+            .unwrap();
+        assert!(matches!(
+            connection.next_message().await,
+            Err(StompError::Broker(m)) if m == "shutting down"
+        ));
+
+        let (connect, subscribe, ack) = broker.await.unwrap();
+        assert_eq!(connect, connect_frame(&config));
+        assert_eq!(subscribe, subscribe_frame(&config));
+        assert_eq!(ack, Frame::Ack(Headers::new().with("id", "m1")));
+    }
+
+    #[tokio::test]
+    async fn login_rejection_and_silence_end_the_session() {
+        let (client, mut broker) = tokio::io::duplex(4096);
+        broker
+            .write_all(b"ERROR\nmessage:Authentication failed\n\n\0")
+            .await
+            .unwrap();
+        assert!(matches!(
+            Connection::open(client, &test_config()).await,
+            Err(StompError::LoginRejected(_))
+        ));
+
+        let (client, mut broker) = tokio::io::duplex(4096);
+        broker
+            .write_all(b"CONNECTED\nheart-beat:0,0\n\n\0")
+            .await
+            .unwrap();
+        let config = StompConfig {
+            idle_timeout: Duration::from_millis(50),
+            ..test_config()
+        };
+        let mut connection = Connection::open(client, &config).await.unwrap();
+        assert!(matches!(
+            connection.next_message().await,
+            Err(StompError::Timeout(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stalled_tls_handshake_times_out() {
+        // Accepts the TCP connection but never answers the ClientHello
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let broker = tokio::spawn(async move { listener.accept().await.unwrap() });
+
+        let config = StompConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            connect_timeout: Duration::from_millis(50),
+            tls: Some(tls_connector(None).unwrap()),
+            ..test_config()
+        };
+        assert!(matches!(
+            connect(&config).await,
+            Err(StompError::ConnectTimeout(_))
+        ));
+        drop(broker.await.unwrap());
+    }
+
+    #[test]
+    fn ca_bundle_without_certificates_is_rejected() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"not a certificate").unwrap();
+        assert!(tls_connector(file.path().to_str()).is_err());
+    }
+}
@@ -0,0 +1,28 @@
+// Messages built for the parse tests and benches/parse.rs
+
+use flate2::{Compression, write::GzEncoder};
+use std::io::Write;
+
+pub fn gzip(xml: impl AsRef<[u8]>) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(xml.as_ref()).unwrap();
+    encoder.finish().unwrap()
+}
+
+// A TS as Darwin sends it: default namespace on Pport, prefixed forecast elements
+pub fn train_status_message(prefix: &str, locations: usize) -> String {
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><Pport xmlns="http://www.thalesgroup.com/rtti/PushPort/v16" xmlns:{0}="http://www.thalesgroup.com/rtti/PushPort/Forecasts/v3" ts="2026-01-15T10:31:02.1234567+00:00" version="16.0"><uR updateOrigin="TD"><TS rid="202601157654321" uid="V54321" ssd="2026-01-15"><{0}:LateReason>100</{0}:LateReason>"#,
+        prefix
+    );
+    for n in 0..locations {
+        xml.push_str(&format!(
+            r#"<{0}:Location tpl="TPL{1:03}" wta="10:{1:02}" wtd="10:{1:02}:30" pta="10:{1:02}" ptd="10:{1:02}"><{0}:arr et="10:{1:02}" src="TD"/><{0}:dep et="10:{1:02}" src="Darwin"/><{0}:plat conf="true" platsup="false">{2}</{0}:plat></{0}:Location>"#,
+            prefix,
+            n % 60,
+            n % 12 + 1
+        ));
+    }
+    xml.push_str("</TS></uR></Pport>");
+    xml
+}
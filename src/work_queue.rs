@@ -0,0 +1,371 @@
+// Bounded queue between the Darwin reader and the processing workers.
+// Messages are parsed in parallel but handed on in arrival order, and every RID is
+// pinned to one worker while it has messages in flight, so each service's updates are
+// applied in the order Darwin sent them. A message is acknowledged once it is applied.
+
+use crate::darwin_types::Pport;
+use crate::processor::process_pmap;
+use crate::state::AppState;
+use crate::stomp::Headers;
+use compact_str::CompactString;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::hash::{BuildHasher, BuildHasherDefault, DefaultHasher};
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Notify, mpsc};
+use tokio::task::JoinHandle;
+
+// Runs once the message has been applied (e.g. the STOMP ACK)
+pub type Ack = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+struct Received {
+    // The STOMP headers, kept with the body if it fails to parse
+    headers: Headers,
+    body: Vec<u8>,
+    ack: Option<Ack>,
+}
+
+struct Work {
+    pport: Option<Pport>,
+    rids: Vec<CompactString>,
+    ack: Option<Ack>,
+}
+
+// RID -> (worker, messages in flight)
+type InFlight = Mutex<HashMap<CompactString, (usize, usize)>>;
+
+pub struct WorkQueue {
+    received: mpsc::Sender<Received>,
+}
+
+impl WorkQueue {
+    // `capacity` messages may wait before `submit` blocks the reader
+    pub fn start(state: Arc<AppState>, capacity: usize, workers: usize) -> Self {
+        let workers = workers.max(1);
+        let (received_tx, received_rx) = mpsc::channel(capacity.max(1));
+        let in_flight = Arc::new(InFlight::default());
+        let applied = Arc::new(Notify::new());
+
+        let worker_txs: Vec<mpsc::Sender<Work>> = (0..workers)
+            .map(|_| {
+                let (tx, rx) = mpsc::channel(capacity.div_ceil(workers).max(1));
+                tokio::spawn(run_worker(
+                    rx,
+                    state.clone(),
+                    in_flight.clone(),
+                    applied.clone(),
+                ));
+                tx
+            })
+            .collect();
+        tokio::spawn(dispatch(
+            received_rx,
+            worker_txs,
+            in_flight,
+            applied,
+            state.clone(),
+        ));
+
+        Self {
+            received: received_tx,
+        }
+    }
+
+    // Waits while the queue is full, which stops the reader and so pushes back on the broker
+    pub async fn submit(
+        &self,
+        state: &AppState,
+        headers: Headers,
+        body: Vec<u8>,
+        ack: Option<Ack>,
+    ) {
+        state.recorder.record(&headers, &body);
+        state.queue_depth.fetch_add(1, Ordering::Relaxed);
+        let received = Received { headers, body, ack };
+        if self.received.send(received).await.is_err() {
+            eprintln!("Work queue has shut down, dropping message");
+            state.queue_depth.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// Parse up to one message per worker ahead, then route each in arrival order
+async fn dispatch(
+    mut received: mpsc::Receiver<Received>,
+    workers: Vec<mpsc::Sender<Work>>,
+    in_flight: Arc<InFlight>,
+    applied: Arc<Notify>,
+    state: Arc<AppState>,
+) {
+    let mut parsing: VecDeque<(JoinHandle<Option<Pport>>, Option<Ack>)> = VecDeque::new();
+    let mut next_unkeyed = 0;
+    loop {
+        while parsing.len() < workers.len() {
+            let Ok(message) = received.try_recv() else {
+                break;
+            };
+            parsing.push_back(parse(message, &state));
+        }
+        let Some((handle, ack)) = parsing.pop_front() else {
+            match received.recv().await {
+                Some(message) => {
+                    parsing.push_back(parse(message, &state));
+                    continue;
+                }
+                None => return,
+            }
+        };
+
+        let pport = handle.await.unwrap_or_else(|e| {
+            eprintln!("Parser task failed: {}", e);
+            None
+        });
+        let rids = pport.as_ref().map(Pport::rids).unwrap_or_default();
+
+        let worker = loop {
+            if let Some(worker) = claim(&in_flight, &rids, workers.len(), &mut next_unkeyed) {
+                break worker;
+            }
+            // Its RIDs are in flight on different workers; wait for one to finish
+            applied.notified().await;
+        };
+        if workers[worker]
+            .send(Work { pport, rids, ack })
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+// A message that can't be parsed is logged, counted and dead-lettered, then acknowledged
+// like any other
+fn parse(message: Received, state: &Arc<AppState>) -> (JoinHandle<Option<Pport>>, Option<Ack>) {
+    let Received { headers, body, .. } = message;
+    let state = state.clone();
+    let handle = tokio::task::spawn_blocking(move || {
+        state.frames.received(crate::Encoding::sniff(&body).name());
+        match crate::parse_frame_bytes(&body) {
+            Ok(pport) => Some(pport),
+            Err(e) => {
+                eprintln!(
+                    "Dropping message {} ({}): {}",
+                    headers.get("message-id").unwrap_or("without an ID"),
+                    e.kind(),
+                    e
+                );
+                state.frames.dropped(e.kind());
+                state.dead_letters.store(&headers, &body, &e);
+                None
+            }
+        }
+    });
+    (handle, message.ack)
+}
+
+// Pick the worker already handling any of these RIDs, or a fresh one by hash, and
+// mark the RIDs as in flight there. None if they are spread over several workers.
+fn claim(
+    in_flight: &InFlight,
+    rids: &[CompactString],
+    workers: usize,
+    next_unkeyed: &mut usize,
+) -> Option<usize> {
+    let mut in_flight = in_flight.lock().unwrap();
+    let mut busy = rids
+        .iter()
+        .filter_map(|rid| in_flight.get(rid).map(|e| e.0));
+    let worker = match busy.next() {
+        Some(worker) => {
+            if busy.any(|other| other != worker) {
+                return None;
+            }
+            worker
+        }
+        None => match rids.first() {
+            Some(rid) => {
+                let hasher = BuildHasherDefault::<DefaultHasher>::default();
+                hasher.hash_one(rid) as usize % workers
+            }
+            // Station messages and the like carry no RID
+            None => {
+                *next_unkeyed = (*next_unkeyed + 1) % workers;
+                *next_unkeyed
+            }
+        },
+    };
+    for rid in rids {
+        in_flight.entry(rid.clone()).or_insert((worker, 0)).1 += 1;
+    }
+    Some(worker)
+}
+
+fn release(in_flight: &InFlight, rids: &[CompactString]) {
+    let mut in_flight = in_flight.lock().unwrap();
+    for rid in rids {
+        if let Some(entry) = in_flight.get_mut(rid) {
+            entry.1 -= 1;
+            if entry.1 == 0 {
+                in_flight.remove(rid);
+            }
+        }
+    }
+}
+
+async fn run_worker(
+    mut work: mpsc::Receiver<Work>,
+    state: Arc<AppState>,
+    in_flight: Arc<InFlight>,
+    applied: Arc<Notify>,
+) {
+    while let Some(Work { pport, rids, ack }) = work.recv().await {
+        if let Some(pport) = pport {
+            // Applying takes the GTFS lock and DashMap shards, so keep it off the runtime threads
+            let state = state.clone();
+            if let Err(e) = tokio::task::spawn_blocking(move || process_pmap(pport, &state)).await {
+                eprintln!("Processing task failed: {}", e);
+            }
+        }
+        release(&in_flight, &rids);
+        applied.notify_one();
+        state.queue_depth.fetch_sub(1, Ordering::Relaxed);
+        if let Some(ack) = ack {
+            ack.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compression, write::GzEncoder};
+    use std::io::Write;
+
+    fn gzip(xml: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(xml.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn schedule_message(rid: &str, train_id: &str) -> Vec<u8> {
+        gzip(&format!(
+            r#"<Pport ts="T" version="16.0"><uR updateOrigin="CIS"><schedule rid="{}" uid="V54321" trainId="{}" ssd="2026-01-15" toc="GW"><OR tpl="PADTON" wtd="10:00"/><DT tpl="OXFD" wta="10:55"/></schedule></uR></Pport>"#,
+            rid, train_id
+        ))
+    }
+
+    #[test]
+    fn rids_in_flight_stay_on_one_worker() {
+        let in_flight = InFlight::default();
+        let mut next = 0;
+        let a = CompactString::from("202601150000001");
+        let b = CompactString::from("202601150000002");
+
+        let worker_a = claim(&in_flight, std::slice::from_ref(&a), 64, &mut next).unwrap();
+        assert_eq!(
+            claim(&in_flight, std::slice::from_ref(&a), 64, &mut next),
+            Some(worker_a)
+        );
+        // An association of A and B follows A while A is busy
+        assert_eq!(
+            claim(&in_flight, &[a.clone(), b.clone()], 64, &mut next),
+            Some(worker_a)
+        );
+
+        release(&in_flight, std::slice::from_ref(&a));
+        release(&in_flight, std::slice::from_ref(&a));
+        release(&in_flight, &[a.clone(), b.clone()]);
+        assert!(in_flight.lock().unwrap().is_empty());
+
+        // Busy on two different workers: has to wait
+        let hash = |rid: &CompactString| {
+            BuildHasherDefault::<DefaultHasher>::default().hash_one(rid) as usize % 64
+        };
+        let elsewhere = (0..)
+            .map(|n| CompactString::from(format!("2026011500001{:02}", n)))
+            .find(|rid| hash(rid) != worker_a)
+            .unwrap();
+        claim(&in_flight, std::slice::from_ref(&a), 64, &mut next);
+        claim(&in_flight, std::slice::from_ref(&elsewhere), 64, &mut next);
+        assert_eq!(claim(&in_flight, &[a, elsewhere], 64, &mut next), None);
+    }
+
+    #[tokio::test]
+    async fn applies_in_order_per_rid_and_acks_after_applying() {
+        let dead_letter_dir = tempfile::tempdir().unwrap();
+        let mut state = AppState::new(String::new());
+        state.dead_letters = crate::dead_letter::DeadLetters::open(dead_letter_dir.path(), 10)
+            .await
+            .unwrap();
+        let state = Arc::new(state);
+        let queue = WorkQueue::start(state.clone(), 4, 3);
+        let (acked_tx, mut acked_rx) = mpsc::unbounded_channel();
+
+        for version in 0..20 {
+            for rid in ["202601150000001", "202601150000002", "202601150000003"] {
+                let train_id = format!("1A{:02}", version);
+                let state_at_ack = state.clone();
+                let acked_tx = acked_tx.clone();
+                let rid_owned = rid.to_string();
+                let expected = train_id.clone();
+                let ack: Ack = Box::pin(async move {
+                    let applied = state_at_ack
+                        .schedules
+                        .get(rid_owned.as_str())
+                        .map(|s| s.train_id.as_deref() == Some(expected.as_str()))
+                        .unwrap_or(false);
+                    acked_tx.send(applied).unwrap();
+                });
+                queue
+                    .submit(
+                        &state,
+                        Headers::new(),
+                        schedule_message(rid, &train_id),
+                        Some(ack),
+                    )
+                    .await;
+            }
+        }
+        queue
+            .submit(
+                &state,
+                Headers::new().with("message-id", "ID:bad"),
+                b"not gzip".to_vec(),
+                None,
+            )
+            .await;
+        drop(acked_tx);
+
+        let mut acks = 0;
+        while let Some(applied) = acked_rx.recv().await {
+            assert!(applied, "acknowledged before it was applied");
+            acks += 1;
+        }
+        assert_eq!(acks, 60);
+        for rid in ["202601150000001", "202601150000002", "202601150000003"] {
+            assert_eq!(
+                state.schedules.get(rid).unwrap().train_id.as_deref(),
+                Some("1A19")
+            );
+        }
+        while state.queue_depth.load(Ordering::Relaxed) > 0 {
+            tokio::task::yield_now().await;
+        }
+
+        assert_eq!(
+            state.frames.encodings(),
+            [("gzip", 60), ("unknown", 1)].into()
+        );
+        assert_eq!(state.frames.drops(), [("unknown_encoding", 1)].into());
+        let dead_letters = state.dead_letters.list();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].error_kind, "unknown_encoding");
+        assert_eq!(
+            dead_letters[0].headers,
+            vec![("message-id".to_string(), "ID:bad".to_string())]
+        );
+    }
+}